@@ -0,0 +1,599 @@
+//! 压缩包模块
+//!
+//! 支持直接读取 zip 压缩包中的谱面与音频，无需解压。
+//! 压缩包内的文件以 `压缩包路径!包内路径` 表示，如 `songs/pack.zip!pack/chart.bme`，
+//! 加载资源时使用 `zip://` 资源源
+
+use std::{
+    io::{Read, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result, bail};
+use async_fs as afs;
+use bevy::{
+    app::App,
+    asset::{
+        AssetApp,
+        io::{AssetReader, AssetReaderError, AssetSourceBuilder, PathStream, Reader, VecReader},
+    },
+    log::warn,
+    platform::collections::HashMap,
+};
+use chardetng::EncodingDetector;
+use flate2::read::DeflateDecoder;
+use futures_lite::{AsyncReadExt, AsyncSeekExt};
+
+/// 压缩包路径与包内路径的分隔符
+pub const ARCHIVE_SEPARATOR: char = '!';
+
+/// 中央目录结束记录签名
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+/// 中央目录记录签名
+const CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+/// 本地文件头签名
+const LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+/// 中央目录结束记录的最小长度
+const EOCD_SIZE: usize = 22;
+/// 本地文件头的固定长度
+const LOCAL_HEADER_SIZE: usize = 30;
+/// 中央目录记录的固定长度
+const CENTRAL_HEADER_SIZE: usize = 46;
+/// 压缩包注释的最大长度
+const MAX_COMMENT_SIZE: usize = 0xFFFF;
+
+/// 已解析的压缩包索引，按压缩包路径缓存，文件大小或修改时间变化时重新解析
+static INDEX_CACHE: LazyLock<Mutex<HashMap<PathBuf, CachedIndex>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 压缩包内的文件
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    /// 压缩方式（0: 存储，8: Deflate）
+    method: u16,
+    /// 压缩后大小
+    compressed_size: u64,
+    /// 解压后大小（解压时不读取超出此大小的数据）
+    uncompressed_size: u64,
+    /// 本地文件头偏移
+    header_offset: u64,
+}
+
+/// 压缩包文件索引
+#[derive(Debug, Default)]
+pub struct ZipIndex {
+    /// 包内路径（以 `/` 分隔）到文件的映射
+    entries: HashMap<String, ZipEntry>,
+    /// 解析时的压缩包大小
+    len: u64,
+}
+
+/// 缓存的压缩包索引
+struct CachedIndex {
+    /// 解析时的压缩包大小
+    len: u64,
+    /// 解析时的压缩包修改时间
+    modified: Option<SystemTime>,
+    /// 压缩包索引
+    index: Arc<ZipIndex>,
+}
+
+/// 将压缩包内的文件路径拆分为压缩包路径与包内路径
+///
+/// 仅识别紧跟在 `.zip` 之后的分隔符，普通文件名中的 `!` 不受影响
+#[must_use]
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    let text = path.to_str()?;
+    let marker = format!(".zip{ARCHIVE_SEPARATOR}");
+    let pos = text.to_ascii_lowercase().find(&marker)?;
+    let (archive, rest) = text.split_at(pos + ".zip".len());
+    let inner = rest.strip_prefix(ARCHIVE_SEPARATOR)?;
+    Some((PathBuf::from(archive), normalize(inner)))
+}
+
+/// 拼接压缩包路径与包内路径
+#[must_use]
+pub fn join_archive_path(archive: &Path, inner: &str) -> PathBuf {
+    PathBuf::from(format!(
+        "{}{ARCHIVE_SEPARATOR}{inner}",
+        archive.to_string_lossy()
+    ))
+}
+
+/// 注册加载资源使用的 `fs://` 与 `zip://` 资源源（需在 `AssetPlugin` 之前调用）
+pub fn register_asset_sources(app: &mut App) {
+    app.register_asset_source("fs", AssetSourceBuilder::platform_default(".", None))
+        .register_asset_source(
+            "zip",
+            AssetSourceBuilder::default().with_reader(|| Box::new(ZipAssetReader)),
+        );
+}
+
+/// 生成加载资源使用的路径（压缩包内使用 `zip://`，否则使用 `fs://`）
+#[must_use]
+pub fn asset_path_string(path: &Path) -> String {
+    let scheme = if split_archive_path(path).is_some() {
+        "zip"
+    } else {
+        "fs"
+    };
+    format!("{scheme}://{}", path.to_string_lossy())
+}
+
+/// 统一包内路径的分隔符
+fn normalize(inner: &str) -> String {
+    inner.replace('\\', "/").trim_start_matches('/').to_string()
+}
+
+/// 读取小端序 `u16`
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(*bytes.get(at..)?.first_chunk()?))
+}
+
+/// 读取小端序 `u32`
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(*bytes.get(at..)?.first_chunk()?))
+}
+
+/// 解码文件名（未标记 UTF-8 时检测编码，常见为 `Shift_JIS`）
+fn decode_name(bytes: &[u8], utf8: bool) -> String {
+    if utf8 {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    let mut det = EncodingDetector::new();
+    det.feed(bytes, true);
+    let (name, _, _) = det.guess(None, true).decode(bytes);
+    name.into_owned()
+}
+
+impl ZipIndex {
+    /// 读取压缩包的中央目录
+    ///
+    /// # Errors
+    ///
+    /// 读取失败、格式不正确或使用 ZIP64 时返回错误
+    pub async fn open(archive: &Path) -> Result<Self> {
+        let mut file = afs::File::open(archive)
+            .await
+            .with_context(|| format!("打开压缩包失败: {}", archive.display()))?;
+        let len = file.metadata().await?.len();
+
+        // 在文件末尾查找中央目录结束记录
+        let tail_len = len.min((EOCD_SIZE + MAX_COMMENT_SIZE) as u64);
+        file.seek(SeekFrom::Start(len - tail_len)).await?;
+        let mut tail = vec![0; tail_len as usize];
+        file.read_exact(&mut tail).await?;
+        let Some(eocd) = (0..=tail.len().saturating_sub(EOCD_SIZE))
+            .rev()
+            .find(|&i| read_u32(&tail, i) == Some(EOCD_SIGNATURE))
+        else {
+            bail!("不是有效的 zip 文件: {}", archive.display());
+        };
+        let (Some(count), Some(dir_size), Some(dir_offset)) = (
+            read_u16(&tail, eocd + 10),
+            read_u32(&tail, eocd + 12),
+            read_u32(&tail, eocd + 16),
+        ) else {
+            bail!("zip 目录已损坏: {}", archive.display());
+        };
+        if dir_offset == u32::MAX {
+            bail!("不支持 ZIP64: {}", archive.display());
+        }
+        // 分配内存前确认目录位于文件之内，且足以容纳记录的文件数量
+        if u64::from(dir_offset) + u64::from(dir_size) > len
+            || (dir_size as usize) < count as usize * CENTRAL_HEADER_SIZE
+        {
+            bail!("zip 目录已损坏: {}", archive.display());
+        }
+
+        let mut dir = vec![0; dir_size as usize];
+        file.seek(SeekFrom::Start(u64::from(dir_offset))).await?;
+        file.read_exact(&mut dir).await?;
+
+        let mut entries = HashMap::new();
+        let mut at = 0;
+        for _ in 0..count {
+            let header = (
+                read_u32(&dir, at),
+                read_u16(&dir, at + 8),
+                read_u16(&dir, at + 10),
+                read_u32(&dir, at + 20),
+                read_u32(&dir, at + 24),
+                read_u16(&dir, at + 28),
+                read_u16(&dir, at + 30),
+                read_u16(&dir, at + 32),
+                read_u32(&dir, at + 42),
+            );
+            let (
+                Some(CENTRAL_SIGNATURE),
+                Some(flags),
+                Some(method),
+                Some(compressed_size),
+                Some(uncompressed_size),
+                Some(name_len),
+                Some(extra_len),
+                Some(comment_len),
+                Some(header_offset),
+            ) = header
+            else {
+                bail!("zip 目录已损坏: {}", archive.display());
+            };
+            let name_start = at + CENTRAL_HEADER_SIZE;
+            let Some(name) = dir.get(name_start..name_start + name_len as usize) else {
+                bail!("zip 目录已损坏: {}", archive.display());
+            };
+            let name = normalize(&decode_name(name, flags & 0x0800 != 0));
+            if !name.ends_with('/') {
+                entries.insert(
+                    name,
+                    ZipEntry {
+                        method,
+                        compressed_size: u64::from(compressed_size),
+                        uncompressed_size: u64::from(uncompressed_size),
+                        header_offset: u64::from(header_offset),
+                    },
+                );
+            }
+            at = name_start + name_len as usize + extra_len as usize + comment_len as usize;
+        }
+        Ok(Self { entries, len })
+    }
+
+    /// 获取压缩包索引，压缩包未变化时复用之前解析的结果
+    ///
+    /// # Errors
+    ///
+    /// 需要重新解析且解析失败时返回错误（见 [`Self::open`]）
+    pub async fn cached(archive: &Path) -> Result<Arc<Self>> {
+        let meta = afs::metadata(archive)
+            .await
+            .with_context(|| format!("打开压缩包失败: {}", archive.display()))?;
+        let (len, modified) = (meta.len(), meta.modified().ok());
+        let cached = INDEX_CACHE.lock().ok().and_then(|cache| {
+            cache
+                .get(archive)
+                .filter(|c| c.len == len && c.modified == modified)
+                .map(|c| c.index.clone())
+        });
+        if let Some(index) = cached {
+            return Ok(index);
+        }
+        let index = Arc::new(Self::open(archive).await?);
+        if let Ok(mut cache) = INDEX_CACHE.lock() {
+            cache.insert(
+                archive.to_path_buf(),
+                CachedIndex {
+                    len,
+                    modified,
+                    index: index.clone(),
+                },
+            );
+        }
+        Ok(index)
+    }
+
+    /// 包内全部文件路径
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// 读取并解压包内文件
+    ///
+    /// # Errors
+    ///
+    /// 文件不存在、读取失败、压缩方式不支持或数据超出记录的大小时返回错误
+    pub async fn read(&self, archive: &Path, inner: &str) -> Result<Vec<u8>> {
+        let Some(entry) = self.entries.get(inner) else {
+            bail!("压缩包内不存在 {inner}: {}", archive.display());
+        };
+        let mut file = afs::File::open(archive)
+            .await
+            .with_context(|| format!("打开压缩包失败: {}", archive.display()))?;
+
+        // 本地文件头的扩展字段长度可能与中央目录不同，需重新读取
+        let mut header = [0; LOCAL_HEADER_SIZE];
+        file.seek(SeekFrom::Start(entry.header_offset)).await?;
+        file.read_exact(&mut header).await?;
+        let (Some(LOCAL_SIGNATURE), Some(name_len), Some(extra_len)) = (
+            read_u32(&header, 0),
+            read_u16(&header, 26),
+            read_u16(&header, 28),
+        ) else {
+            bail!("zip 文件头已损坏: {inner}");
+        };
+        let data_offset = entry.header_offset
+            + LOCAL_HEADER_SIZE as u64
+            + u64::from(name_len)
+            + u64::from(extra_len);
+        if data_offset + entry.compressed_size > self.len {
+            bail!("zip 文件头已损坏: {inner}");
+        }
+        file.seek(SeekFrom::Start(data_offset)).await?;
+        let mut data = vec![0; entry.compressed_size as usize];
+        file.read_exact(&mut data).await?;
+        inflate(entry, data).with_context(|| format!("解压失败: {inner}"))
+    }
+}
+
+/// 按压缩方式还原文件内容，内容超出记录的解压后大小时返回错误
+fn inflate(entry: &ZipEntry, data: Vec<u8>) -> Result<Vec<u8>> {
+    match entry.method {
+        0 if data.len() as u64 == entry.uncompressed_size => Ok(data),
+        0 => bail!("存储的大小与记录不符"),
+        8 => {
+            // 多读取 1 字节以发现超出记录大小的数据，避免解压炸弹占满内存
+            let mut out = Vec::with_capacity(entry.uncompressed_size.min(1 << 20) as usize);
+            DeflateDecoder::new(data.as_slice())
+                .take(entry.uncompressed_size + 1)
+                .read_to_end(&mut out)?;
+            if out.len() as u64 != entry.uncompressed_size {
+                bail!("解压后的大小与记录不符");
+            }
+            Ok(out)
+        }
+        method => bail!("不支持的压缩方式 {method}"),
+    }
+}
+
+/// 读取文件内容（支持压缩包内路径）
+///
+/// # Errors
+///
+/// 读取失败时返回错误
+pub async fn read_file(path: &Path) -> Result<Vec<u8>> {
+    match split_archive_path(path) {
+        Some((archive, inner)) => {
+            ZipIndex::cached(&archive)
+                .await?
+                .read(&archive, &inner)
+                .await
+        }
+        None => afs::read(path)
+            .await
+            .with_context(|| format!("读取文件失败: {}", path.display())),
+    }
+}
+
+/// 列出压缩包内指定扩展名的文件，结果按路径排序
+pub async fn find_entries_by_ext(archive: &Path, exts: &[&str]) -> Vec<PathBuf> {
+    let index = match ZipIndex::cached(archive).await {
+        Ok(index) => index,
+        Err(e) => {
+            warn!("{e:#}");
+            return Vec::new();
+        }
+    };
+    let mut found: Vec<PathBuf> = index
+        .paths()
+        .filter(|p| {
+            Path::new(p)
+                .extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|e| exts.iter().any(|x| e.eq_ignore_ascii_case(x)))
+        })
+        .map(|p| join_archive_path(archive, p))
+        .collect();
+    found.sort();
+    found
+}
+
+/// 在压缩包内查找与 `entry` 同名（忽略扩展名）且内容满足 `accept` 的文件
+pub async fn find_entry_by_content(
+    archive: &Path,
+    entry: &str,
+    accept: impl Fn(&[u8]) -> bool,
+) -> Option<PathBuf> {
+    let index = ZipIndex::cached(archive).await.ok()?;
+    let entry = normalize(entry);
+    let (dir, name) = entry.rsplit_once('/').unwrap_or(("", &entry));
+    let stem = Path::new(name).file_stem()?.to_str()?;
+
+    let mut candidates: Vec<&str> = index
+        .paths()
+        .filter(|path| {
+            let (d, n) = path.rsplit_once('/').unwrap_or(("", path));
+            d == dir
+                && Path::new(n)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|s| s.eq_ignore_ascii_case(stem))
+        })
+        .collect();
+    candidates.sort_unstable();
+    for path in candidates {
+        if index
+            .read(archive, path)
+            .await
+            .is_ok_and(|bytes| accept(&bytes))
+        {
+            return Some(join_archive_path(archive, path));
+        }
+    }
+    None
+}
+
+/// 在压缩包内按文件名匹配可用格式，返回文件名（不含扩展名）到路径的映射
+///
+/// 与 [`crate::filesystem::choose_paths_by_ext_async`] 相同，但在包内查找
+pub async fn choose_entries_by_ext(
+    archive: &Path,
+    parent: &str,
+    children: &[PathBuf],
+    exts: &[&str],
+) -> HashMap<String, PathBuf> {
+    let index = match ZipIndex::cached(archive).await {
+        Ok(index) => index,
+        Err(e) => {
+            warn!("{e:#}");
+            return HashMap::new();
+        }
+    };
+    // 只在谱面所在目录及引用的子目录中查找
+    let dirs: Vec<String> = std::iter::once(parent.to_string())
+        .chain(children.iter().map(|c| {
+            let joined = normalize(&format!("{parent}/{}", c.to_string_lossy()));
+            joined
+                .rsplit_once('/')
+                .map_or_else(String::new, |(dir, _)| dir.to_string())
+        }))
+        .collect();
+
+    let mut found: HashMap<String, PathBuf> = HashMap::new();
+    for path in index.paths() {
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if !dirs.iter().any(|d| d == dir) {
+            continue;
+        }
+        let name = Path::new(name);
+        let (Some(stem), Some(ext)) = (
+            name.file_stem().and_then(|s| s.to_str()),
+            name.extension().and_then(|s| s.to_str()),
+        ) else {
+            continue;
+        };
+        if exts.iter().any(|x| ext.eq_ignore_ascii_case(x)) {
+            found
+                .entry(stem.to_string())
+                .or_insert_with(|| join_archive_path(archive, path));
+        }
+    }
+    found
+}
+
+/// 读取压缩包内资源的 [`AssetReader`]
+///
+/// 资源路径为 `压缩包路径!包内路径`，压缩包索引在首次访问后缓存
+#[derive(Default)]
+pub struct ZipAssetReader;
+
+impl AssetReader for ZipAssetReader {
+    async fn read<'a>(&'a self, path: &'a Path) -> Result<impl Reader + 'a, AssetReaderError> {
+        let not_found = || AssetReaderError::NotFound(path.to_path_buf());
+        let (archive, inner) = split_archive_path(path).ok_or_else(not_found)?;
+        let index = ZipIndex::cached(&archive).await.map_err(|e| {
+            warn!("{e:#}");
+            not_found()
+        })?;
+        let bytes = index.read(&archive, &inner).await.map_err(|e| {
+            warn!("{e:#}");
+            not_found()
+        })?;
+        Ok(VecReader::new(bytes))
+    }
+
+    async fn read_meta<'a>(&'a self, path: &'a Path) -> Result<impl Reader + 'a, AssetReaderError> {
+        // 压缩包内没有 .meta 文件，使用默认加载设置
+        Err::<VecReader, _>(AssetReaderError::NotFound(path.to_path_buf()))
+    }
+
+    async fn read_directory<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Result<Box<PathStream>, AssetReaderError> {
+        Err(AssetReaderError::NotFound(path.to_path_buf()))
+    }
+
+    async fn is_directory<'a>(&'a self, _path: &'a Path) -> Result<bool, AssetReaderError> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::DeflateEncoder};
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    /// 包内文件名
+    const NAME: &str = "pack/chart.bms";
+
+    /// 生成只含一个 Deflate 文件的压缩包，解压后大小记为 `recorded_size`
+    fn zip_bytes(content: &[u8], recorded_size: u32) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).expect("压缩");
+        let data = encoder.finish().expect("压缩");
+        let sizes = |out: &mut Vec<u8>| {
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&recorded_size.to_le_bytes());
+            out.extend_from_slice(&(NAME.len() as u16).to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+        };
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&LOCAL_SIGNATURE.to_le_bytes());
+        for field in [20u16, 0, 8, 0, 0] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        sizes(&mut bytes);
+        bytes.extend_from_slice(NAME.as_bytes());
+        bytes.extend_from_slice(&data);
+
+        let dir_offset = bytes.len();
+        bytes.extend_from_slice(&CENTRAL_SIGNATURE.to_le_bytes());
+        for field in [20u16, 20, 0, 8, 0, 0] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        sizes(&mut bytes);
+        for field in [0u16, 0, 0] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(NAME.as_bytes());
+        let dir_size = bytes.len() - dir_offset;
+
+        bytes.extend_from_slice(&EOCD_SIGNATURE.to_le_bytes());
+        for field in [0u16, 0, 1, 1] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(dir_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&(dir_offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes
+    }
+
+    /// 写入临时压缩包并读取包内文件，返回读取结果
+    fn read_zip(name: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+        let path =
+            std::env::temp_dir().join(format!("nebula-tunes-{}-{name}.zip", std::process::id()));
+        std::fs::write(&path, bytes).expect("写入测试压缩包");
+        let read = block_on(async {
+            let index = ZipIndex::cached(&path).await?;
+            let again = ZipIndex::cached(&path).await?;
+            anyhow::ensure!(Arc::ptr_eq(&index, &again), "压缩包未变化时应复用索引");
+            index.read(&path, NAME).await
+        });
+        std::fs::remove_file(&path).ok();
+        read
+    }
+
+    #[test]
+    fn reads_deflated_entry() {
+        let content = b"#TITLE test\n".repeat(100);
+        let bytes = zip_bytes(&content, content.len() as u32);
+        assert_eq!(read_zip("ok", &bytes).expect("读取包内文件"), content);
+    }
+
+    #[test]
+    fn rejects_entry_larger_than_recorded() {
+        let content = vec![0; 1 << 20];
+        let bytes = zip_bytes(&content, 1024);
+        assert!(read_zip("bomb", &bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_directory_beyond_file_end() {
+        let mut bytes = zip_bytes(b"#TITLE test", 11);
+        let dir_size_at = bytes.len() - EOCD_SIZE + 12;
+        if let Some(dir_size) = bytes.get_mut(dir_size_at..dir_size_at + 4) {
+            dir_size.copy_from_slice(&u32::MAX.to_le_bytes());
+        }
+        assert!(read_zip("dir", &bytes).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! 谱面检查模块
+//!
+//! 不启动窗口，加载谱面并报告解析警告、缺失资源、轨道外音符与各轨道音符数量
+
+use std::{path::Path, time::Duration};
+
+use bms_rs::chart_process::prelude::*;
+use futures_lite::future;
+use gametime::{TimeSpan, TimeStamp};
+use serde::Serialize;
+
+use crate::filesystem;
+use crate::lane::KeyLayout;
+use crate::plugins::bms_processor::{LoadedBms, VISIBLE_TRAVEL, load_bms_and_collect_paths};
+
+/// 支持的 BGA 扩展名（按优先级排列）
+const BGA_EXTS: [&str; 8] = ["png", "bmp", "jpg", "jpeg", "mpg", "mpeg", "mp4", "wmv"];
+
+/// 推进处理器时使用的时长，足以覆盖整张谱面
+const SCAN_SPAN: Duration = Duration::from_secs(24 * 3600);
+
+/// 缺失的资源文件
+#[derive(Serialize)]
+struct MissingFile {
+    /// 谱面中的定义编号
+    id: String,
+    /// 谱面中声明的路径
+    path: String,
+}
+
+/// 检查结果
+#[derive(Serialize, Default)]
+struct CheckReport {
+    /// 谱面路径
+    chart: String,
+    /// 谱面内容摘要
+    hash: Option<String>,
+    /// 加载失败的原因
+    error: Option<String>,
+    /// 解析警告
+    warnings: Vec<String>,
+    /// 缺失的音频文件
+    missing_audio: Vec<MissingFile>,
+    /// 缺失的 BGA 文件
+    missing_bga: Vec<MissingFile>,
+    /// 当前键位布局下无法映射到轨道的音符数量
+    unmapped_notes: usize,
+    /// 各轨道的音符数量
+    lane_notes: Vec<usize>,
+}
+
+impl CheckReport {
+    /// 错误数量（解析警告不计入）
+    fn error_count(&self) -> usize {
+        usize::from(self.error.is_some())
+            + self.missing_audio.len()
+            + self.missing_bga.len()
+            + self.unmapped_notes
+    }
+
+    /// 输出为可读文本
+    fn print_text(&self) {
+        println!("📄 谱面: {}", self.chart);
+        if let Some(hash) = &self.hash {
+            println!("🔑 谱面摘要: {hash}");
+        }
+        if let Some(error) = &self.error {
+            println!("✗ 加载失败: {error}");
+        }
+        print_list("⚠ 解析警告", self.warnings.iter().map(String::as_str));
+        print_missing("✗ 缺失音频", "#WAV", &self.missing_audio);
+        print_missing("✗ 缺失 BGA", "#BMP", &self.missing_bga);
+        if self.unmapped_notes > 0 {
+            println!("✗ 轨道外音符: {}", self.unmapped_notes);
+        }
+        if self.error.is_none() {
+            let counts: Vec<String> = self
+                .lane_notes
+                .iter()
+                .enumerate()
+                .map(|(lane, count)| format!("{lane}:{count}"))
+                .collect();
+            println!(
+                "🎹 音符数: {}（{}）",
+                self.lane_notes.iter().sum::<usize>(),
+                counts.join(" ")
+            );
+        }
+        match self.error_count() {
+            0 => println!("✓ 检查通过"),
+            n => println!("✗ 发现 {n} 个错误"),
+        }
+    }
+}
+
+/// 输出带标题的列表
+fn print_list<'a>(title: &str, items: impl ExactSizeIterator<Item = &'a str>) {
+    if items.len() == 0 {
+        return;
+    }
+    println!("{title}: {}", items.len());
+    for item in items {
+        println!("  {item}");
+    }
+}
+
+/// 输出缺失文件列表
+fn print_missing(title: &str, prefix: &str, files: &[MissingFile]) {
+    let lines: Vec<String> = files
+        .iter()
+        .map(|file| format!("{prefix}{} -> {}", file.id, file.path))
+        .collect();
+    print_list(title, lines.iter().map(String::as_str));
+}
+
+/// 检查谱面并输出结果，返回进程退出码（指定 `seed` 时按该种子选择 `#RANDOM` 分支）
+#[must_use]
+pub fn run(chart_path: &Path, layout: KeyLayout, json: bool, seed: Option<i64>) -> i32 {
+    let report = future::block_on(check_chart(chart_path, layout, seed));
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(text) => println!("{text}"),
+            Err(e) => eprintln!("{e}"),
+        }
+    } else {
+        report.print_text();
+    }
+    i32::from(report.error_count() > 0)
+}
+
+/// 加载谱面并收集检查结果
+async fn check_chart(chart_path: &Path, layout: KeyLayout, seed: Option<i64>) -> CheckReport {
+    let mut report = CheckReport {
+        chart: chart_path.display().to_string(),
+        lane_notes: vec![0; layout.lane_count()],
+        ..Default::default()
+    };
+    let LoadedBms {
+        mut processor,
+        missing_audio,
+        warnings,
+        hash,
+        ..
+    } = match load_bms_and_collect_paths(chart_path.to_path_buf(), layout, VISIBLE_TRAVEL, seed)
+        .await
+    {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            report.error = Some(format!("{e:#}"));
+            return report;
+        }
+    };
+
+    report.hash = Some(hash);
+    report.warnings = warnings.iter().map(ToString::to_string).collect();
+    report.missing_audio = missing_audio
+        .into_iter()
+        .map(|(id, path)| MissingFile {
+            id: format!("{:03}", id.0),
+            path: path.to_string_lossy().into_owned(),
+        })
+        .collect();
+
+    // BGA 只检查文件是否存在
+    let (bmp_ids, bmp_list): (Vec<BmpId>, Vec<_>) = processor
+        .bmp_files()
+        .into_iter()
+        .map(|(id, path)| (id, path.to_path_buf()))
+        .unzip();
+    let resolved = filesystem::resolve_chart_files(chart_path, &bmp_list, &BGA_EXTS).await;
+    report.missing_bga = bmp_ids
+        .into_iter()
+        .zip(bmp_list)
+        .zip(resolved)
+        .filter(|(_, found)| found.is_none())
+        .map(|((id, path), _)| MissingFile {
+            id: format!("{:03}", id.0),
+            path: path.to_string_lossy().into_owned(),
+        })
+        .collect();
+    report.missing_bga.sort_by(|a, b| a.id.cmp(&b.id));
+
+    // 推进到谱面结尾，统计可判定的音符
+    let start = TimeStamp::now();
+    processor.start_play(start);
+    for evp in processor.update(start + TimeSpan::from_duration(SCAN_SPAN)) {
+        let ChartEvent::Note {
+            side, key, kind, ..
+        } = evp.event()
+        else {
+            continue;
+        };
+        if matches!(kind, NoteKind::Invisible | NoteKind::Landmine) {
+            continue;
+        }
+        match layout
+            .key_to_lane(*side, *key)
+            .and_then(|lane| report.lane_notes.get_mut(lane))
+        {
+            Some(count) => *count += 1,
+            None => report.unmapped_notes += 1,
+        }
+    }
+    report
+}
@@ -8,6 +8,53 @@ use bevy::prelude::*;
 #[derive(Component)]
 pub struct NoteMarker;
 
+/// 小节线标记组件
+#[derive(Component)]
+pub struct BarLineMarker;
+
+/// 轨道背景组件
+#[derive(Component)]
+pub struct LaneBackground(pub usize);
+
+/// 场地内固定元素组件，记录下落方向时的 Y 坐标，上升方向时按判定区镜像
+#[derive(Component)]
+pub struct FieldAnchor(pub f32);
+
+/// 场地元素组件（皮肤重新加载时移除后重建）
+#[derive(Component)]
+pub struct NoteScene;
+
+/// 轨道遮挡（SUDDEN+）组件
+#[derive(Component)]
+pub struct LaneCover;
+
+/// 按键闪光组件
+#[derive(Component)]
+pub struct LaneFlash {
+    /// 轨道索引
+    pub lane: usize,
+    /// 剩余显示时长（秒）
+    pub remaining: f32,
+}
+
+/// 连击里程碑闪光组件
+#[derive(Component)]
+pub struct ComboFlash {
+    /// 剩余显示时长（秒）
+    pub remaining: f32,
+}
+
+/// 早晚指示组件
+#[derive(Component)]
+pub struct FastSlowIndicator {
+    /// 剩余显示时长（秒）
+    pub remaining: f32,
+}
+
+/// BPM 与小节号显示组件
+#[derive(Component)]
+pub struct TempoIndicator;
+
 /// 池化音符组件
 #[derive(Component)]
 pub struct PooledNote {
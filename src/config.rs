@@ -0,0 +1,728 @@
+//! 配置模块
+//!
+//! 负责系统配置文件 `config_sys.toml` 的读取、校验与保存
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::lane::KeyMode;
+use crate::plugins::judge::{ClearGauge, GaugeKind, GaugeProfiles};
+use crate::plugins::window_mode::PresentModeSetting;
+
+/// 系统配置文件路径
+pub const SYS_CONFIG_PATH: &str = "config_sys.toml";
+
+/// 写入配置文件时置于开头的说明
+const CONFIG_HEADER: &str = "\
+# Nebula Tunes 系统配置
+# 删除某一项即恢复其默认值；键位可在选曲界面按 F8 重新设置
+# BMS 谱面的键位布局由 keys.lanes 的按键数量决定（6: 5K，8: 7K），PMS 谱面使用 keys.lanes_pms
+
+";
+
+/// 默认配置文件内容：逐项注明各设置的含义，取值与 [`SysConfig::default`] 一致
+const DEFAULT_CONFIG_TEMPLATE: &str = r#"# Nebula Tunes 系统配置
+# 删除某一项即恢复其默认值；键位可在选曲界面按 F8 重新设置
+# 游戏内修改设置时会重新写入此文件，届时注释不再保留
+
+# 显示设置
+[display]
+# 轨道遮挡（SUDDEN+）占可见高度的比例（0.0 ~ 1.0），游戏中按 PageUp/PageDown 调整
+lane_cover = 0.0
+# 小节线颜色（sRGB，各分量 0.0 ~ 1.0）
+bar_line_color = [0.35, 0.35, 0.4]
+# 是否以无边框全屏启动（按 F11 切换时保存）
+fullscreen = false
+# 音符自下而上移动，判定线位于顶端（只影响显示）
+scroll_up = false
+# 每条轨道最多显示的音符数（保留离判定线最近的），0 表示不限制；判定仍处理全部音符
+max_visible_notes = 0
+
+# 画面输出设置
+[video]
+# 呈现模式：fifo（严格垂直同步）、auto_vsync、auto_no_vsync、mailbox、immediate
+# 显卡不支持 mailbox 或 immediate 时自动回退
+present_mode = "fifo"
+# 窗口获得焦点时的最低更新频率（Hz），0 表示不限制；开启垂直同步时不超过显示器刷新率
+tick_hz = 250
+# 多重采样抗锯齿的采样数（1 表示关闭，可选 2、4、8）
+msaa = 4
+# 窗口失去焦点时停止绘制画面（游戏逻辑与音频照常运行）
+background_throttle = true
+
+# 音频设置（音量均为线性增益，1.0 为原始音量）
+[audio]
+# BGM 音量
+bgm_volume = 1.0
+# 按键音音量
+key_volume = 1.0
+# 已解码音频的内存预算（MB），播放中超出时只淘汰谱面不再使用的音频
+cache_budget_mb = 256
+# 按键音最大同时发声数，超出时停止最早的发声（不影响 BGM）
+max_voices = 32
+# 达到连击里程碑时播放的音效，取消注释并填写路径后启用
+# milestone_sound = "milestone.wav"
+# 节拍器音量（配合 --metronome 使用）
+metronome_volume = 0.5
+# 开始播放前在静音通道中把每个音频播放一次，消除首次发声的卡顿
+prewarm = false
+
+# 键位设置（按键名称见 Bevy 的 KeyCode）
+[keys]
+# BMS 谱面各轨道按键，数量决定键位布局（6: 5K，8: 7K），第一项为皿
+lanes = ["ShiftLeft", "KeyZ", "KeyS", "KeyX", "KeyD", "KeyC", "KeyF", "KeyV"]
+# PMS 谱面（.pms）9 个轨道的按键，从左到右
+lanes_pms = ["KeyZ", "KeyS", "KeyX", "KeyD", "KeyC", "KeyF", "KeyV", "KeyG", "KeyB"]
+# 双人模式下 P2 侧各轨道按键（从左到右，皿在最右端）
+lanes_2p = ["KeyM", "KeyK", "Comma", "KeyL", "Period", "Semicolon", "Slash", "ShiftRight"]
+
+# 手柄设置
+[gamepad]
+# 各轨道对应的手柄按钮（下标即轨道索引）
+lanes = ["LeftTrigger2", "West", "North", "South", "East", "LeftTrigger", "RightTrigger", "RightTrigger2"]
+# 皿使用的模拟轴（转盘），取消注释后启用，未设置时仅使用按钮
+# scratch_axis = "LeftStickX"
+# 模拟轴的最小变化量，小于此值的抖动不视为转动
+scratch_min_delta = 0.02
+
+# 鼠标与触摸设置
+[input]
+# 点击或触摸轨道所在的列视为按下该轨道（多点触摸时各触摸点独立）
+touch_enabled = false
+
+# 曲库设置
+[songs]
+# 曲库目录（递归扫描其中的谱面）
+dir = "songs"
+
+# 判定设置
+[judge]
+# 判定时间偏移（毫秒），正值表示按键普遍偏晚；可用校准模式测量
+offset_ms = 0.0
+# 基准 BPM 下音符从出现到抵达判定线的时长（毫秒），越小音符移动越快
+visible_travel_ms = 600
+# 校准模式使用的节拍器音效
+metronome_sound = "metronome.wav"
+# 血条类型：groove、hard、easy（可被 --gauge 覆盖）
+gauge = "groove"
+# 任意血条类型降到 fail_gauge 时都立即失败（关闭时只有 hard 血条归零立即失败）
+fail_on_empty = false
+# 立即失败的血条阈值（0.0 ~ 1.0）
+fail_gauge = 0.0
+# 触发连击里程碑效果的连击数
+combo_milestones = [100, 200, 300, 400, 500, 600, 700, 800, 900, 1000]
+# 空按时按 POOR 中断连击并扣减血条（不计入判定分布）
+empty_hit_penalty = false
+
+# 结束时通关所需的最低血条（hard 血条未归零即通关）
+[judge.clear_gauge]
+groove = 0.8
+easy = 0.8
+
+# 各血条类型的初始值、上下限（需满足 0 ≤ min ≤ initial ≤ max ≤ 1）
+# 与各判定的增减量（按 PERFECT、GREAT、GOOD、BAD、POOR 顺序排列）
+[judge.gauge_profiles.groove]
+initial = 0.2
+min = 0.0
+max = 1.0
+deltas = [0.02, 0.02, 0.01, -0.03, -0.05]
+
+[judge.gauge_profiles.hard]
+initial = 1.0
+min = 0.0
+max = 1.0
+deltas = [0.01, 0.01, 0.005, -0.06, -0.1]
+
+[judge.gauge_profiles.easy]
+initial = 0.2
+min = 0.0
+max = 1.0
+deltas = [0.025, 0.025, 0.0125, -0.024, -0.04]
+
+# 调试设置
+[debug]
+# 是否在内存中保留最近的日志供日志控制台（F10）显示，修改后需重启生效
+log_console = false
+# 日志控制台保留的行数
+log_console_lines = 200
+# 运行时检查皮肤与配置文件的修改并重新加载
+hot_reload = false
+"#;
+
+/// 系统配置
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SysConfig {
+    /// 显示设置
+    pub display: DisplayConfig,
+    /// 画面输出设置
+    pub video: VideoConfig,
+    /// 音频设置
+    pub audio: AudioConfig,
+    /// 键位设置
+    pub keys: KeysConfig,
+    /// 手柄设置
+    pub gamepad: GamepadConfig,
+    /// 鼠标与触摸设置
+    pub input: InputConfig,
+    /// 曲库设置
+    pub songs: SongsConfig,
+    /// 判定设置
+    pub judge: JudgeConfig,
+    /// 调试设置
+    pub debug: DebugConfig,
+}
+
+/// 显示设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// 轨道遮挡（SUDDEN+）占可见高度的比例
+    pub lane_cover: f32,
+    /// 小节线颜色（sRGB）
+    pub bar_line_color: [f32; 3],
+    /// 是否以无边框全屏启动（按 F11 切换时保存）
+    pub fullscreen: bool,
+    /// 音符自下而上移动，判定线位于顶端（只影响显示）
+    pub scroll_up: bool,
+    /// 每条轨道最多显示的音符数（保留离判定线最近的），0 表示不限制；只影响显示，判定仍处理全部音符
+    pub max_visible_notes: u32,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            lane_cover: 0.0,
+            bar_line_color: [0.35, 0.35, 0.4],
+            fullscreen: false,
+            scroll_up: false,
+            max_visible_notes: 0,
+        }
+    }
+}
+
+/// 画面输出设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoConfig {
+    /// 呈现模式（垂直同步）
+    pub present_mode: PresentModeSetting,
+    /// 窗口获得焦点时的最低更新频率（Hz），0 表示不限制
+    ///
+    /// 输入到达时立即更新，此值只决定无输入时的更新间隔。
+    /// 输入在每次更新开始时读取，同一次更新读到的输入记为同一时刻，判定精度约为实际的更新间隔。
+    /// 频率越高判定与显示越及时，CPU 占用也越高。开启垂直同步（`fifo`、`auto_vsync`）时
+    /// 每次更新都要等待画面刷新，实际频率不超过显示器刷新率（60Hz 下约 16ms）；
+    /// 需要更高的输入精度时将 `present_mode` 设为 `mailbox` 或 `immediate`
+    pub tick_hz: u32,
+    /// 多重采样抗锯齿的采样数（1 表示关闭，可选 2、4、8），显卡不支持时回退为 1
+    pub msaa: u32,
+    /// 窗口失去焦点时停止绘制画面（游戏逻辑与音频照常运行）
+    pub background_throttle: bool,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentModeSetting::default(),
+            tick_hz: 250,
+            msaa: 4,
+            background_throttle: true,
+        }
+    }
+}
+
+/// 音频设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// BGM 音量（线性增益，1.0 为原始音量）
+    pub bgm_volume: f32,
+    /// 按键音音量（线性增益，1.0 为原始音量）
+    pub key_volume: f32,
+    /// 已解码音频的内存预算（MB）：开始播放前最多预加载到此大小，
+    /// 播放中超出时只淘汰谱面不再使用的音频
+    pub cache_budget_mb: usize,
+    /// 按键音最大同时发声数，超出时停止最早的发声（不影响 BGM）
+    pub max_voices: usize,
+    /// 达到连击里程碑时播放的音效（留空则不播放）
+    pub milestone_sound: Option<PathBuf>,
+    /// 节拍器音量（线性增益，配合 `--metronome` 使用）
+    pub metronome_volume: f32,
+    /// 开始播放前在静音通道中把每个音频播放一次，消除首次发声的卡顿
+    pub prewarm: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            bgm_volume: 1.0,
+            key_volume: 1.0,
+            cache_budget_mb: 256,
+            max_voices: 32,
+            milestone_sound: None,
+            metronome_volume: 0.5,
+            prewarm: false,
+        }
+    }
+}
+
+/// 游戏控制占用的按键，不能绑定到轨道
+pub const RESERVED_KEYS: [KeyCode; 25] = [
+    KeyCode::Escape,
+    KeyCode::Enter,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::KeyR,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F11,
+    KeyCode::F10,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Backslash,
+];
+
+/// 键位设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    /// BMS 谱面各轨道按键，数量决定键位布局（6: 5K，8: 7K）
+    pub lanes: Vec<KeyCode>,
+    /// PMS 谱面 9 个轨道的按键（从左到右）
+    pub lanes_pms: Vec<KeyCode>,
+    /// 双人模式下 P2 侧各轨道按键（从左到右，皿在最右端）
+    pub lanes_2p: Vec<KeyCode>,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            lanes: vec![
+                KeyCode::ShiftLeft,
+                KeyCode::KeyZ,
+                KeyCode::KeyS,
+                KeyCode::KeyX,
+                KeyCode::KeyD,
+                KeyCode::KeyC,
+                KeyCode::KeyF,
+                KeyCode::KeyV,
+            ],
+            lanes_pms: vec![
+                KeyCode::KeyZ,
+                KeyCode::KeyS,
+                KeyCode::KeyX,
+                KeyCode::KeyD,
+                KeyCode::KeyC,
+                KeyCode::KeyF,
+                KeyCode::KeyV,
+                KeyCode::KeyG,
+                KeyCode::KeyB,
+            ],
+            lanes_2p: vec![
+                KeyCode::KeyM,
+                KeyCode::KeyK,
+                KeyCode::Comma,
+                KeyCode::KeyL,
+                KeyCode::Period,
+                KeyCode::Semicolon,
+                KeyCode::Slash,
+                KeyCode::ShiftRight,
+            ],
+        }
+    }
+}
+
+impl KeysConfig {
+    /// 游玩 BMS 谱面时的键位模式（`lanes` 为 6 个按键时为 5K，否则为 7K）
+    #[must_use]
+    pub const fn bms_mode(&self) -> KeyMode {
+        match KeyMode::from_lane_count(self.lanes.len()) {
+            Some(KeyMode::Beat5K) => KeyMode::Beat5K,
+            _ => KeyMode::Beat7K,
+        }
+    }
+
+    /// 键位模式对应的 P1 侧按键
+    #[must_use]
+    pub fn lanes_for(&self, mode: KeyMode) -> &[KeyCode] {
+        match mode {
+            KeyMode::Pms9K => &self.lanes_pms,
+            KeyMode::Beat5K | KeyMode::Beat7K => &self.lanes,
+        }
+    }
+
+    /// 键位模式对应的 P1 侧按键（重新设置键位时写入）
+    pub const fn lanes_for_mut(&mut self, mode: KeyMode) -> &mut Vec<KeyCode> {
+        match mode {
+            KeyMode::Pms9K => &mut self.lanes_pms,
+            KeyMode::Beat5K | KeyMode::Beat7K => &mut self.lanes,
+        }
+    }
+}
+
+/// 手柄设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    /// 各轨道对应的手柄按钮（下标即轨道索引）
+    pub lanes: Vec<GamepadButton>,
+    /// 皿（轨道 0）使用的模拟轴（转盘），未设置时仅使用按钮
+    pub scratch_axis: Option<GamepadAxis>,
+    /// 模拟轴的最小变化量，小于此值的抖动不视为转动
+    pub scratch_min_delta: f32,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self {
+            lanes: vec![
+                GamepadButton::LeftTrigger2,
+                GamepadButton::West,
+                GamepadButton::North,
+                GamepadButton::South,
+                GamepadButton::East,
+                GamepadButton::LeftTrigger,
+                GamepadButton::RightTrigger,
+                GamepadButton::RightTrigger2,
+            ],
+            scratch_axis: None,
+            scratch_min_delta: 0.02,
+        }
+    }
+}
+
+/// 鼠标与触摸设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    /// 点击或触摸轨道所在的列视为按下该轨道（多点触摸时各触摸点独立）
+    pub touch_enabled: bool,
+}
+
+/// 曲库设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SongsConfig {
+    /// 曲库目录（递归扫描其中的谱面）
+    pub dir: PathBuf,
+}
+
+impl Default for SongsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("songs"),
+        }
+    }
+}
+
+/// 判定设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JudgeConfig {
+    /// 判定时间偏移（毫秒），正值表示按键普遍偏晚
+    pub offset_ms: f64,
+    /// 基准BPM下音符从出现到抵达判定线的时长（毫秒），越小音符移动越快；
+    /// 在选曲界面按 ←/→ 调整，从下一次加载谱面起生效
+    pub visible_travel_ms: u64,
+    /// 校准模式使用的节拍器音效
+    pub metronome_sound: PathBuf,
+    /// 血条类型（可被 `--gauge` 覆盖）
+    pub gauge: GaugeKind,
+    /// 任意血条类型降到 `fail_gauge` 时都立即失败（关闭时只有 HARD 血条归零立即失败，
+    /// 其他血条在结束时未达到通关线即失败）
+    pub fail_on_empty: bool,
+    /// 立即失败的血条阈值（0.0 ~ 1.0）
+    pub fail_gauge: f32,
+    /// 结束时通关所需的最低血条
+    pub clear_gauge: ClearGauge,
+    /// 各血条类型的初始值、上下限与各判定的增减量
+    pub gauge_profiles: GaugeProfiles,
+    /// 触发连击里程碑效果的连击数
+    pub combo_milestones: Vec<u32>,
+    /// 空按（判定窗口内没有可击中的音符）时按 POOR 中断连击并扣减血条（不计入判定分布）
+    pub empty_hit_penalty: bool,
+}
+
+impl Default for JudgeConfig {
+    fn default() -> Self {
+        Self {
+            offset_ms: 0.0,
+            visible_travel_ms: 600,
+            metronome_sound: PathBuf::from("metronome.wav"),
+            gauge: GaugeKind::default(),
+            fail_on_empty: false,
+            fail_gauge: 0.0,
+            clear_gauge: ClearGauge::default(),
+            gauge_profiles: GaugeProfiles::default(),
+            combo_milestones: (1..=10).map(|i| i * 100).collect(),
+            empty_hit_penalty: false,
+        }
+    }
+}
+
+/// 调试设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// 是否在内存中保留最近的日志供日志控制台（F10）显示，修改后需重启生效
+    pub log_console: bool,
+    /// 日志控制台保留的行数
+    pub log_console_lines: usize,
+    /// 是否在运行时检查皮肤与配置文件的修改并重新加载（画面输出等启动时读取的设置除外）
+    pub hot_reload: bool,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            log_console: false,
+            log_console_lines: 200,
+            hot_reload: false,
+        }
+    }
+}
+
+impl SysConfig {
+    /// 检查配置取值是否有效，出错时指出对应的配置项
+    ///
+    /// # Errors
+    ///
+    /// 配置项超出取值范围时返回错误
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.keys.lanes.is_empty(), "keys.lanes 不能为空");
+        ensure_unique_keys("keys.lanes", &self.keys.lanes)?;
+        ensure_unique_keys("keys.lanes_pms", &self.keys.lanes_pms)?;
+        ensure_unique_keys("keys.lanes_2p", &self.keys.lanes_2p)?;
+        for (name, keys) in [
+            ("keys.lanes", &self.keys.lanes),
+            ("keys.lanes_pms", &self.keys.lanes_pms),
+            ("keys.lanes_2p", &self.keys.lanes_2p),
+        ] {
+            ensure_unreserved_keys(name, keys)?;
+        }
+        // 双人模式下 P1 与 P2 的按键同时生效
+        let lanes_1p = self.keys.lanes_for(self.keys.bms_mode());
+        if let Some(key) = self.keys.lanes_2p.iter().find(|key| lanes_1p.contains(key)) {
+            bail!("keys.lanes_2p 中的按键 {key:?} 已绑定到 keys.lanes");
+        }
+        ensure_unit("display.lane_cover", self.display.lane_cover)?;
+        ensure!(
+            self.judge.visible_travel_ms > 0,
+            "judge.visible_travel_ms 必须大于 0"
+        );
+        ensure_unit("judge.fail_gauge", self.judge.fail_gauge)?;
+        ensure_unit("judge.clear_gauge.groove", self.judge.clear_gauge.groove)?;
+        ensure_unit("judge.clear_gauge.easy", self.judge.clear_gauge.easy)?;
+        let profiles = &self.judge.gauge_profiles;
+        for (name, profile) in [
+            ("groove", &profiles.groove),
+            ("hard", &profiles.hard),
+            ("easy", &profiles.easy),
+        ] {
+            ensure!(
+                0.0 <= profile.min
+                    && profile.min <= profile.initial
+                    && profile.initial <= profile.max
+                    && profile.max <= 1.0,
+                "judge.gauge_profiles.{name} 需满足 0 ≤ min ≤ initial ≤ max ≤ 1（当前 min {}、initial {}、max {}）",
+                profile.min,
+                profile.initial,
+                profile.max
+            );
+        }
+        for (name, volume) in [
+            ("audio.bgm_volume", self.audio.bgm_volume),
+            ("audio.key_volume", self.audio.key_volume),
+            ("audio.metronome_volume", self.audio.metronome_volume),
+        ] {
+            ensure!(volume >= 0.0, "{name} 不能为负数（当前 {volume}）");
+        }
+        Ok(())
+    }
+}
+
+/// 检查取值是否在 0.0 ~ 1.0 之间
+fn ensure_unit(name: &str, value: f32) -> Result<()> {
+    ensure!(
+        (0.0..=1.0).contains(&value),
+        "{name} 需在 0.0 ~ 1.0 之间（当前 {value}）"
+    );
+    Ok(())
+}
+
+/// 检查按键列表中没有重复的按键
+fn ensure_unique_keys(name: &str, keys: &[KeyCode]) -> Result<()> {
+    for (i, key) in keys.iter().enumerate() {
+        ensure!(
+            !keys.iter().take(i).any(|other| other == key),
+            "{name} 中的按键 {key:?} 重复（第 {} 项）",
+            i + 1
+        );
+    }
+    Ok(())
+}
+
+/// 检查按键列表中没有游戏控制占用的按键
+fn ensure_unreserved_keys(name: &str, keys: &[KeyCode]) -> Result<()> {
+    if let Some(key) = keys.iter().find(|key| RESERVED_KEYS.contains(key)) {
+        bail!("{name} 中的按键 {key:?} 已被游戏控制占用，不能绑定到轨道");
+    }
+    Ok(())
+}
+
+/// 读取系统配置，未填写的配置项使用默认值
+///
+/// 文件不存在时写入带注释的默认配置（写入失败时仍使用默认配置启动）
+///
+/// # Errors
+///
+/// 文件读取失败、内容无法解析或配置项取值无效时返回错误
+pub fn load_sys(path: &Path) -> Result<SysConfig> {
+    if !path.exists() {
+        match std::fs::write(path, DEFAULT_CONFIG_TEMPLATE) {
+            Ok(()) => println!("📝 已创建默认配置: {}", path.display()),
+            Err(e) => eprintln!("写入默认配置失败: {}: {e}", path.display()),
+        }
+        return Ok(SysConfig::default());
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("读取配置失败: {}", path.display()))?;
+    let config: SysConfig =
+        toml::from_str(&text).with_context(|| format!("解析配置失败: {}", path.display()))?;
+    config
+        .validate()
+        .with_context(|| format!("配置无效: {}", path.display()))?;
+    Ok(config)
+}
+
+/// 保存系统配置
+///
+/// # Errors
+///
+/// 序列化或写入文件失败时返回错误
+pub fn save_sys(path: &Path, config: &SysConfig) -> Result<()> {
+    let text = toml::to_string_pretty(config).context("序列化配置失败")?;
+    std::fs::write(path, format!("{CONFIG_HEADER}{text}"))
+        .with_context(|| format!("写入配置失败: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试专用的配置文件路径
+    fn temp_config(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nebula-tunes-{}-{name}.toml", std::process::id()))
+    }
+
+    /// 读取内容为 `text` 的配置文件，返回读取结果与读取后的文件内容
+    fn load_text(name: &str, text: &str) -> (Result<SysConfig>, String) {
+        let path = temp_config(name);
+        std::fs::write(&path, text).expect("写入测试配置");
+        let loaded = load_sys(&path);
+        let after = std::fs::read_to_string(&path).unwrap_or_default();
+        std::fs::remove_file(&path).ok();
+        (loaded, after)
+    }
+
+    #[test]
+    fn missing_file_creates_default_config() {
+        let path = temp_config("missing");
+        std::fs::remove_file(&path).ok();
+        let config = load_sys(&path).expect("使用默认配置");
+        let written = load_sys(&path).expect("读取生成的配置");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(written.keys.lanes, config.keys.lanes);
+        assert_eq!(
+            written.judge.visible_travel_ms,
+            config.judge.visible_travel_ms
+        );
+    }
+
+    #[test]
+    fn default_template_matches_default_config() {
+        let config: SysConfig = toml::from_str(DEFAULT_CONFIG_TEMPLATE).expect("解析默认配置");
+        config.validate().expect("默认配置有效");
+        let serialize = |c: &SysConfig| toml::to_string(c).expect("序列化配置");
+        assert_eq!(serialize(&config), serialize(&SysConfig::default()));
+    }
+
+    #[test]
+    fn unparsable_config_is_rejected_and_kept() {
+        for (name, text) in [
+            ("syntax", "[display\nlane_cover = 0.5\n"),
+            ("type", "[display]\nlane_cover = \"half\"\n"),
+            ("key", "[keys]\nlanes = [\"NotAKey\"]\n"),
+        ] {
+            let (loaded, after) = load_text(name, text);
+            assert!(loaded.is_err(), "{name} 应当读取失败");
+            assert_eq!(after, text, "{name} 读取失败后配置文件不应被改写");
+        }
+    }
+
+    #[test]
+    fn out_of_range_config_names_the_offending_key() {
+        for (name, text, key) in [
+            (
+                "cover",
+                "[display]\nlane_cover = 1.5\n",
+                "display.lane_cover",
+            ),
+            (
+                "travel",
+                "[judge]\nvisible_travel_ms = 0\n",
+                "judge.visible_travel_ms",
+            ),
+            ("volume", "[audio]\nbgm_volume = -1.0\n", "audio.bgm_volume"),
+            (
+                "duplicate",
+                "[keys]\nlanes = [\"KeyZ\", \"KeyZ\"]\n",
+                "keys.lanes",
+            ),
+            (
+                "reserved",
+                "[keys]\nlanes = [\"KeyZ\", \"KeyR\", \"KeyX\", \"KeyD\", \"KeyC\", \"KeyF\"]\n",
+                "keys.lanes",
+            ),
+            (
+                "reserved_pms",
+                "[keys]\nlanes_pms = [\"KeyZ\", \"Escape\"]\n",
+                "keys.lanes_pms",
+            ),
+            (
+                "reserved_2p",
+                "[keys]\nlanes_2p = [\"KeyM\", \"F5\"]\n",
+                "keys.lanes_2p",
+            ),
+            (
+                "shared_2p",
+                "[keys]\nlanes_2p = [\"KeyM\", \"KeyZ\"]\n",
+                "keys.lanes_2p",
+            ),
+        ] {
+            let (loaded, after) = load_text(name, text);
+            let Err(e) = loaded else {
+                panic!("{name} 应当校验失败");
+            };
+            assert!(format!("{e:#}").contains(key), "{name}: {e:#}");
+            assert_eq!(after, text);
+        }
+    }
+}
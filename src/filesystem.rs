@@ -6,7 +6,9 @@ use bevy::{
     platform::collections::{HashMap, HashSet},
     prelude::*,
 };
-use futures_lite::{StreamExt, stream};
+use futures_lite::{AsyncReadExt, StreamExt, stream};
+
+use crate::archive;
 
 pub async fn choose_paths_by_ext_async(
     parent: &Path,
@@ -57,3 +59,146 @@ pub async fn choose_paths_by_ext_async(
     }
     found
 }
+
+/// 递归查找目录下指定扩展名的文件，结果按路径排序
+pub async fn find_files_by_ext_async(root: &Path, exts: &[&str]) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = Vec::new();
+    let mut dirs: Vec<PathBuf> = vec![root.to_path_buf()];
+    while let Some(dir_path) = dirs.pop() {
+        let Ok(mut dir) = afs::read_dir(&dir_path).await else {
+            continue;
+        };
+        while let Some(entry) = dir.next().await {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let Ok(ft) = entry.file_type().await else {
+                continue;
+            };
+            let p = entry.path();
+            if ft.is_dir() {
+                dirs.push(p);
+            } else if ft.is_file()
+                && p.extension()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|e| exts.iter().any(|x| e.eq_ignore_ascii_case(x)))
+            {
+                found.push(p);
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// 按文件头识别音频格式，返回对应的扩展名
+#[must_use]
+pub fn sniff_audio_format(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'A',
+            b'V',
+            b'E',
+            ..,
+        ] => Some("wav"),
+        [b'O', b'g', b'g', b'S', ..] => Some("ogg"),
+        [b'f', b'L', b'a', b'C', ..] => Some("flac"),
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        [0xFF, b, ..] if b & 0xE0 == 0xE0 => Some("mp3"),
+        _ => None,
+    }
+}
+
+/// 识别文件格式所需读取的字节数
+const SNIFF_SIZE: usize = 12;
+
+/// 在目录中查找与 `path` 同名（忽略扩展名）且内容满足 `accept` 的文件
+async fn find_file_by_content(path: &Path, accept: impl Fn(&[u8]) -> bool) -> Option<PathBuf> {
+    let dir_path = path.parent()?;
+    let stem = path.file_stem()?.to_str()?;
+    let mut dir = afs::read_dir(dir_path).await.ok()?;
+
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    while let Some(entry) = dir.next().await {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let p = entry.path();
+        if p.file_stem()
+            .and_then(|s| s.to_str())
+            .is_some_and(|s| s.eq_ignore_ascii_case(stem))
+        {
+            candidates.push(p);
+        }
+    }
+    candidates.sort();
+    for p in candidates {
+        let Ok(mut file) = afs::File::open(&p).await else {
+            continue;
+        };
+        let mut head = Vec::with_capacity(SNIFF_SIZE);
+        if (&mut file)
+            .take(SNIFF_SIZE as u64)
+            .read_to_end(&mut head)
+            .await
+            .is_ok()
+            && accept(&head)
+        {
+            return Some(p);
+        }
+    }
+    None
+}
+
+/// 按谱面引用解析实际文件路径（支持压缩包内的谱面），结果与 `children` 顺序一致
+///
+/// 声明的扩展名可能与实际文件不符，先按文件名匹配可用格式；
+/// 找不到时按文件头识别同名的音频文件，仍找不到则为 `None`
+pub async fn resolve_chart_files(
+    chart_path: &Path,
+    children: &[PathBuf],
+    exts: &[&str],
+) -> Vec<Option<PathBuf>> {
+    let stem_of = |p: &PathBuf| p.file_stem().and_then(|s| s.to_str()).map(str::to_string);
+    let accept = |bytes: &[u8]| sniff_audio_format(bytes).is_some_and(|f| exts.contains(&f));
+
+    let mut resolved = Vec::with_capacity(children.len());
+    if let Some((archive_path, inner)) = archive::split_archive_path(chart_path) {
+        let parent = inner.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let index = archive::choose_entries_by_ext(&archive_path, parent, children, exts).await;
+        for child in children {
+            if let Some(found) = stem_of(child).and_then(|s| index.get(&s).cloned()) {
+                resolved.push(Some(found));
+                continue;
+            }
+            let rel = child.to_string_lossy().replace('\\', "/");
+            let entry = if parent.is_empty() {
+                rel
+            } else {
+                format!("{parent}/{rel}")
+            };
+            resolved.push(archive::find_entry_by_content(&archive_path, &entry, accept).await);
+        }
+        return resolved;
+    }
+
+    let parent = chart_path.parent().unwrap_or_else(|| Path::new("."));
+    let index = choose_paths_by_ext_async(parent, children, exts).await;
+    for child in children {
+        if let Some(found) = stem_of(child).and_then(|s| index.get(&s).cloned()) {
+            resolved.push(Some(found));
+            continue;
+        }
+        resolved.push(find_file_by_content(&parent.join(child), accept).await);
+    }
+    resolved
+}
@@ -0,0 +1,403 @@
+//! 轨道布局定义
+//!
+//! 定义键位布局、轨道数量、按键到轨道的映射以及轨道变换
+
+use std::{
+    hash::{BuildHasher, Hash, Hasher, RandomState},
+    path::Path,
+};
+
+use bevy::{platform::collections::HashMap, prelude::*};
+use bms_rs::{bms::prelude::*, chart_process::prelude::ChartEventId};
+use clap::ValueEnum;
+
+use crate::plugins::bms_processor::ChartNote;
+
+/// 轨道类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaneKind {
+    /// 皿
+    Scratch,
+    /// 白键
+    White,
+    /// 黑键
+    Black,
+}
+
+/// 单侧键位模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyMode {
+    /// 5 键 + 皿
+    Beat5K,
+    /// 7 键 + 皿
+    #[default]
+    Beat7K,
+    /// PMS 9 键
+    Pms9K,
+}
+
+impl KeyMode {
+    /// 根据单侧轨道数量选择键位模式，无对应模式时返回 `None`
+    #[must_use]
+    pub const fn from_lane_count(count: usize) -> Option<Self> {
+        match count {
+            6 => Some(Self::Beat5K),
+            8 => Some(Self::Beat7K),
+            9 => Some(Self::Pms9K),
+            _ => None,
+        }
+    }
+
+    /// 单侧轨道数量
+    #[must_use]
+    pub const fn lane_count(self) -> usize {
+        match self {
+            Self::Beat5K => 6,
+            Self::Beat7K => 8,
+            Self::Pms9K => 9,
+        }
+    }
+
+    /// 是否支持双人（DP）布局
+    #[must_use]
+    pub const fn supports_double(self) -> bool {
+        !matches!(self, Self::Pms9K)
+    }
+
+    /// 按谱面扩展名选择键位模式：`.pms` 使用 PMS 9 键，`.bms`/`.bme`/`.bml` 使用按键模式
+    /// （当前为 PMS 时改用 7K），其他扩展名保持不变
+    #[must_use]
+    pub fn for_chart(self, path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match (ext.as_deref(), self) {
+            (Some("pms"), _) => Self::Pms9K,
+            (Some("bms" | "bme" | "bml"), Self::Pms9K) => Self::Beat7K,
+            _ => self,
+        }
+    }
+}
+
+/// 键位布局
+///
+/// 双人模式下 P2 轨道排在 P1 右侧，皿位于最右端
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyLayout {
+    /// 单侧键位模式
+    pub mode: KeyMode,
+    /// 是否启用 P2 侧（DP）
+    pub double: bool,
+}
+
+impl KeyLayout {
+    /// 创建键位布局，模式不支持双人时忽略 `double`
+    #[must_use]
+    pub const fn new(mode: KeyMode, double: bool) -> Self {
+        Self {
+            mode,
+            double: double && mode.supports_double(),
+        }
+    }
+
+    /// 单侧轨道数量
+    #[must_use]
+    pub const fn side_lane_count(self) -> usize {
+        self.mode.lane_count()
+    }
+
+    /// 总轨道数量
+    #[must_use]
+    pub const fn lane_count(self) -> usize {
+        if self.double {
+            self.side_lane_count() * 2
+        } else {
+            self.side_lane_count()
+        }
+    }
+
+    /// 将玩家侧与Key转换为轨道索引
+    #[must_use]
+    pub const fn key_to_lane(self, side: PlayerSide, key: Key) -> Option<usize> {
+        match side {
+            PlayerSide::Player1 => self.p1_lane(key),
+            PlayerSide::Player2 if self.double => match self.p1_lane(key) {
+                // P2 皿位于最右端，按键顺延
+                Some(0) => Some(self.lane_count() - 1),
+                Some(n) => Some(self.side_lane_count() + n - 1),
+                None => None,
+            },
+            PlayerSide::Player2 => None,
+        }
+    }
+
+    /// 轨道类型（PMS 按键交替视为白键与黑键）
+    #[must_use]
+    pub const fn lane_kind(self, lane: usize) -> LaneKind {
+        let side = self.side_lane_count();
+        let (p2, idx) = if lane >= side {
+            (true, lane - side)
+        } else {
+            (false, lane)
+        };
+        let key = match self.mode {
+            KeyMode::Pms9K => idx + 1,
+            // P1 皿位于最左端，P2 皿位于最右端
+            KeyMode::Beat5K | KeyMode::Beat7K if p2 && idx == side - 1 => return LaneKind::Scratch,
+            KeyMode::Beat5K | KeyMode::Beat7K if p2 => idx + 1,
+            KeyMode::Beat5K | KeyMode::Beat7K if idx == 0 => return LaneKind::Scratch,
+            KeyMode::Beat5K | KeyMode::Beat7K => idx,
+        };
+        if key % 2 == 1 {
+            LaneKind::White
+        } else {
+            LaneKind::Black
+        }
+    }
+
+    /// P1 侧的轨道索引
+    const fn p1_lane(self, key: Key) -> Option<usize> {
+        match (self.mode, key) {
+            (KeyMode::Beat5K | KeyMode::Beat7K, Key::Scratch(_)) => Some(0),
+            (KeyMode::Beat5K, Key::Key(n @ 1..=5)) | (KeyMode::Beat7K, Key::Key(n @ 1..=7)) => {
+                Some(n as usize)
+            }
+            (KeyMode::Pms9K, Key::Key(n @ 1..=9)) => Some(n as usize - 1),
+            _ => None,
+        }
+    }
+}
+
+/// 键位布局在启动后是否改变（加载不同键位模式的谱面时切换）
+pub fn layout_changed(layout: Res<KeyLayout>) -> bool {
+    layout.is_changed() && !layout.is_added()
+}
+
+/// 轨道变换
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LaneModifier {
+    /// 左右镜像
+    Mirror,
+    /// 每次游玩随机打乱轨道顺序
+    Random,
+    /// 每个音符单独随机分配轨道（同时出现的音符分配到不同轨道）
+    SRandom,
+}
+
+/// 以种子初始化的 64 位 FNV-1a 哈希
+///
+/// 不使用标准库的 `RandomState`：同一种子在不同版本与平台上得到相同的结果，回放据此还原轨道变换
+#[derive(Debug, Clone, Copy)]
+struct SeededHasher(u64);
+
+impl SeededHasher {
+    /// 以种子计算值的哈希
+    fn hash_one(seed: u64, value: impl Hash) -> u64 {
+        let mut hasher = Self(0xcbf2_9ce4_8422_2325 ^ seed);
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl Hasher for SeededHasher {
+    fn finish(&self) -> u64 {
+        // splitmix64 的终混步骤，使取模时低位也足够随机
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        // 与平台的指针宽度无关
+        self.write(&(i as u64).to_le_bytes());
+    }
+}
+
+/// 变换后的音符轨道映射
+///
+/// 各侧独立变换；皿固定时皿轨道不参与变换
+#[derive(Resource, Debug, Clone)]
+pub struct LaneShuffle {
+    /// 轨道变换（`None` 表示不变换）
+    modifier: Option<LaneModifier>,
+    /// 皿轨道是否参与变换
+    include_scratch: bool,
+    /// 键位布局
+    layout: KeyLayout,
+    /// 参与变换的轨道（每侧一组）
+    groups: Vec<Vec<usize>>,
+    /// 各轨道变换后的轨道（S-RANDOM 时为恒等映射）
+    permutation: Vec<usize>,
+    /// S-RANDOM 时各音符分配到的轨道
+    note_lanes: HashMap<ChartEventId, usize>,
+    /// 本次游玩的随机种子
+    seed: u64,
+    /// 固定使用的随机种子（回放时使用录制时的种子）
+    fixed_seed: Option<u64>,
+}
+
+impl LaneShuffle {
+    /// 创建映射（排列在每次加载谱面时由 [`Self::reroll`] 生成）
+    #[must_use]
+    pub fn new(modifier: Option<LaneModifier>, include_scratch: bool, layout: KeyLayout) -> Self {
+        let side = layout.side_lane_count();
+        let sides = if layout.double { 2 } else { 1 };
+        let groups = (0..sides)
+            .map(|s| {
+                (s * side..(s + 1) * side)
+                    .filter(|&lane| include_scratch || layout.lane_kind(lane) != LaneKind::Scratch)
+                    .collect()
+            })
+            .collect();
+        Self {
+            modifier,
+            include_scratch,
+            layout,
+            groups,
+            permutation: (0..layout.lane_count()).collect(),
+            note_lanes: HashMap::new(),
+            seed: 0,
+            fixed_seed: None,
+        }
+    }
+
+    /// 每次生成排列都使用指定的种子，得到与录制时相同的轨道变换
+    #[must_use]
+    pub const fn with_seed(mut self, seed: u64) -> Self {
+        self.fixed_seed = Some(seed);
+        self
+    }
+
+    /// 切换键位布局（加载不同键位模式的谱面时），保留轨道变换与固定的种子
+    pub fn set_layout(&mut self, layout: KeyLayout) {
+        if self.layout == layout {
+            return;
+        }
+        *self = Self {
+            fixed_seed: self.fixed_seed,
+            ..Self::new(self.modifier, self.include_scratch, layout)
+        };
+    }
+
+    /// 轨道变换
+    #[must_use]
+    pub const fn modifier(&self) -> Option<LaneModifier> {
+        self.modifier
+    }
+
+    /// 皿轨道是否参与变换
+    #[must_use]
+    pub const fn include_scratch(&self) -> bool {
+        self.include_scratch
+    }
+
+    /// 本次游玩的随机种子
+    #[must_use]
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// 各轨道变换后的轨道
+    #[must_use]
+    pub fn permutation(&self) -> &[usize] {
+        &self.permutation
+    }
+
+    /// 重新生成本次游玩的排列（RANDOM 时输出新的轨道顺序），S-RANDOM 时为谱面的每个音符分配轨道
+    pub fn reroll(&mut self, notes: &[ChartNote]) {
+        self.seed = self
+            .fixed_seed
+            .unwrap_or_else(|| RandomState::new().hash_one(()));
+        let mut permutation: Vec<usize> = (0..self.layout.lane_count()).collect();
+        for group in &self.groups {
+            let mut targets = group.clone();
+            match self.modifier {
+                Some(LaneModifier::Mirror) => targets.reverse(),
+                Some(LaneModifier::Random) => {
+                    for i in (1..targets.len()).rev() {
+                        let j = (SeededHasher::hash_one(self.seed, i) % (i as u64 + 1)) as usize;
+                        targets.swap(i, j);
+                    }
+                }
+                Some(LaneModifier::SRandom) | None => {}
+            }
+            for (&from, &to) in group.iter().zip(&targets) {
+                if let Some(slot) = permutation.get_mut(from) {
+                    *slot = to;
+                }
+            }
+        }
+        if self.modifier == Some(LaneModifier::Random) {
+            println!("🎲 RANDOM: {:?}", self.permutation_display(&permutation));
+        }
+        self.permutation = permutation;
+        self.note_lanes = if self.modifier == Some(LaneModifier::SRandom) {
+            self.assign_note_lanes(notes)
+        } else {
+            HashMap::new()
+        };
+    }
+
+    /// S-RANDOM：同一时刻的音符在各自的组内随机分配到互不相同的轨道
+    fn assign_note_lanes(&self, notes: &[ChartNote]) -> HashMap<ChartEventId, usize> {
+        let mut note_lanes = HashMap::new();
+        for (chord_idx, chord) in notes.chunk_by(|a, b| a.at == b.at).enumerate() {
+            for (group_idx, group) in self.groups.iter().enumerate() {
+                let mut targets = group.clone();
+                for i in (1..targets.len()).rev() {
+                    let j = (SeededHasher::hash_one(self.seed, (chord_idx, group_idx, i))
+                        % (i as u64 + 1)) as usize;
+                    targets.swap(i, j);
+                }
+                let members = chord.iter().filter(|note| {
+                    self.layout
+                        .key_to_lane(note.side, note.key)
+                        .is_some_and(|lane| group.contains(&lane))
+                });
+                for (note, &lane) in members.zip(&targets) {
+                    note_lanes.insert(note.id, lane);
+                }
+            }
+        }
+        note_lanes
+    }
+
+    /// 以原轨道号列出变换后各轨道上的音符来源（从 1 开始）
+    fn permutation_display(&self, permutation: &[usize]) -> Vec<usize> {
+        let mut sources = vec![0; permutation.len()];
+        for (from, &to) in permutation.iter().enumerate() {
+            if let Some(slot) = sources.get_mut(to) {
+                *slot = from + 1;
+            }
+        }
+        sources
+    }
+
+    /// 音符变换后所在的轨道，无法映射时返回 `None`
+    ///
+    /// S-RANDOM 使用加载谱面时为各音符分配的轨道，同一音符下落过程中轨道保持不变；
+    /// 未分配的音符（固定的皿或超出统计时长）以事件 ID 决定轨道
+    #[must_use]
+    pub fn note_lane(&self, side: PlayerSide, key: Key, event_id: ChartEventId) -> Option<usize> {
+        let lane = self.layout.key_to_lane(side, key)?;
+        if self.modifier != Some(LaneModifier::SRandom) {
+            return self.permutation.get(lane).copied();
+        }
+        if let Some(&assigned) = self.note_lanes.get(&event_id) {
+            return Some(assigned);
+        }
+        let Some(group) = self.groups.iter().find(|group| group.contains(&lane)) else {
+            return Some(lane);
+        };
+        let pick = SeededHasher::hash_one(self.seed, event_id) % group.len() as u64;
+        group.get(pick as usize).copied()
+    }
+}
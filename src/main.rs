@@ -10,48 +10,175 @@
 #![warn(clippy::redundant_else)]
 #![warn(clippy::redundant_feature_names)]
 
+mod archive;
+mod check;
 mod components;
+mod config;
 mod filesystem;
+mod lane;
+mod play_result;
 mod plugins;
+mod replay;
 mod resources;
 mod schedule;
+mod skin;
+mod song_index;
+mod state;
+mod stress;
+mod wav_preview;
+
+use std::path::Path;
 
 use bevy::{
     app::MainScheduleOrder,
-    asset::{AssetPlugin, UnapprovedPathMode, io::AssetSourceBuilder},
+    asset::{AssetPlugin, UnapprovedPathMode},
     ecs::schedule::{ExecutorKind, Schedule},
+    log::LogPlugin,
     prelude::*,
 };
 use bevy_kira_audio::AudioPlugin;
 use clap::Parser;
 
+use config::SYS_CONFIG_PATH;
+use lane::{KeyLayout, KeyMode, LaneShuffle};
 use plugins::{
-    AudioManagerPlugin, AudioTriggerPlugin, BMSProcessorPlugin, NoteRendererPlugin,
-    TimeSystemPlugin,
+    AudioManagerPlugin, AudioTriggerPlugin, BMSProcessorPlugin, CalibrationPlugin,
+    ConfigSavePlugin, HotReloadPlugin, InputHandlerPlugin, JudgePlugin, KeyConfigPlugin,
+    LoadingScreenPlugin, LogConsolePlugin, MetronomePlugin, NoteRendererPlugin, ReplayPlugin,
+    ResultPlugin, SongSelectPlugin, StatsOverlayPlugin, TimeSystemPlugin, WindowModePlugin,
 };
 use resources::ExecArgs;
 use schedule::{AudioSchedule, LogicSchedule};
+use skin::{SKIN_PATH, Skin};
+use state::AppState;
 
 fn main() {
     let args = ExecArgs::parse();
+    // 配置无效时不以默认配置启动，否则之后保存设置会覆盖用户的配置文件
+    let config = config::load_sys(Path::new(SYS_CONFIG_PATH)).unwrap_or_else(|e| {
+        eprintln!("✗ 配置加载失败: {e:#}");
+        eprintln!("请修正 {SYS_CONFIG_PATH} 后重新启动（删除该文件将重新生成默认配置）");
+        std::process::exit(1);
+    });
+    let skin = skin::load_skin(Path::new(SKIN_PATH)).unwrap_or_else(|e| {
+        eprintln!("皮肤加载失败,使用默认皮肤: {e:#}");
+        Skin::default()
+    });
+    if !matches!(
+        KeyMode::from_lane_count(config.keys.lanes.len()),
+        Some(KeyMode::Beat5K | KeyMode::Beat7K)
+    ) {
+        eprintln!(
+            "keys.lanes 应为 6（5K）或 8（7K）个按键,当前 {} 个,使用 7K 布局（PMS 键位见 keys.lanes_pms）",
+            config.keys.lanes.len()
+        );
+    }
+    // 指定了谱面时按扩展名选择键位模式（`.pms` 为 PMS 9 键），选曲时随所选谱面切换
+    let mode = config.keys.bms_mode();
+    let mode = args
+        .check
+        .as_deref()
+        .or(args.stress.as_deref())
+        .or(args.list_wavs.as_deref())
+        .or_else(|| {
+            args.play_wav
+                .as_deref()
+                .and_then(<[String]>::first)
+                .map(Path::new)
+        })
+        .or(args.bms_path.as_deref())
+        .map_or(mode, |path| mode.for_chart(path));
+    if mode.lane_count() > config.keys.lanes_for(mode).len() {
+        eprintln!(
+            "⚠ 已配置 {} 个轨道按键,{} 个轨道中多出的轨道没有按键",
+            config.keys.lanes_for(mode).len(),
+            mode.lane_count()
+        );
+    }
+    if args.double && !mode.supports_double() {
+        eprintln!("当前键位模式不支持双人模式,已忽略 --double");
+    }
+    // 未指定谱面时进入选曲
+    let initial_state = if args.calibrate {
+        AppState::Calibration
+    } else if args.bms_path.is_some() {
+        AppState::Playing
+    } else {
+        AppState::SongSelect
+    };
+    let layout = KeyLayout::new(mode, args.double);
+    if let Some(chart_path) = &args.check {
+        std::process::exit(check::run(chart_path, layout, args.json, args.seed));
+    }
+    if let Some(chart_path) = &args.stress {
+        std::process::exit(stress::run(
+            chart_path,
+            layout,
+            config.video.tick_hz,
+            args.seed,
+        ));
+    }
+    if let Some(chart_path) = &args.list_wavs {
+        std::process::exit(wav_preview::list(chart_path, layout, args.seed));
+    }
+    if let Some([chart_path, id]) = args.play_wav.as_deref() {
+        std::process::exit(wav_preview::play(
+            Path::new(chart_path),
+            layout,
+            args.seed,
+            id,
+        ));
+    }
+    let shuffle = LaneShuffle::new(args.lane_modifier, args.shuffle_scratch, layout);
+    let window = plugins::window_mode::primary_window(&config);
     let mut app = App::new();
 
-    app.register_asset_source("fs", AssetSourceBuilder::platform_default(".", None))
-        .insert_resource(args)
-        .add_plugins(DefaultPlugins.set(AssetPlugin {
-            unapproved_path_mode: UnapprovedPathMode::Deny,
-            ..Default::default()
-        }))
-        .add_plugins(AudioPlugin);
+    archive::register_asset_sources(&mut app);
+    app.insert_resource(args)
+        .insert_resource(config)
+        .insert_resource(skin)
+        .insert_resource(layout)
+        .insert_resource(shuffle)
+        .add_plugins(
+            DefaultPlugins
+                .set(AssetPlugin {
+                    unapproved_path_mode: UnapprovedPathMode::Deny,
+                    ..Default::default()
+                })
+                .set(WindowPlugin {
+                    primary_window: Some(window),
+                    ..Default::default()
+                })
+                .set(LogPlugin {
+                    custom_layer: plugins::log_console::log_layer,
+                    ..Default::default()
+                }),
+        )
+        .add_plugins(AudioPlugin)
+        .insert_state(initial_state);
 
     // 配置自定义 Schedule
     configure_schedules(&mut app);
 
     app.add_plugins(TimeSystemPlugin)
         .add_plugins(BMSProcessorPlugin)
+        .add_plugins(InputHandlerPlugin)
+        .add_plugins(JudgePlugin)
         .add_plugins(AudioTriggerPlugin)
         .add_plugins(AudioManagerPlugin)
         .add_plugins(NoteRendererPlugin)
+        .add_plugins(LoadingScreenPlugin)
+        .add_plugins(ResultPlugin)
+        .add_plugins(SongSelectPlugin)
+        .add_plugins(CalibrationPlugin)
+        .add_plugins(KeyConfigPlugin)
+        .add_plugins(MetronomePlugin)
+        .add_plugins(ReplayPlugin)
+        .add_plugins(StatsOverlayPlugin)
+        .add_plugins(LogConsolePlugin)
+        .add_plugins(WindowModePlugin)
+        .add_plugins(HotReloadPlugin)
+        .add_plugins(ConfigSavePlugin)
         .run();
 }
 
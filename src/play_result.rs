@@ -0,0 +1,97 @@
+//! 演奏结果导出模块
+//!
+//! 将结算时的成绩写为 JSON 文件，供外部成绩管理工具读取
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::plugins::judge::{GaugeKind, JudgeLevel, LaneStats};
+
+/// 各判定等级计数
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct JudgeCounts {
+    /// PERFECT 数
+    pub perfect: u32,
+    /// GREAT 数
+    pub great: u32,
+    /// GOOD 数
+    pub good: u32,
+    /// BAD 数
+    pub bad: u32,
+    /// POOR 数
+    pub poor: u32,
+}
+
+impl From<[u32; JudgeLevel::COUNT]> for JudgeCounts {
+    fn from([perfect, great, good, bad, poor]: [u32; JudgeLevel::COUNT]) -> Self {
+        Self {
+            perfect,
+            great,
+            good,
+            bad,
+            poor,
+        }
+    }
+}
+
+/// 单条轨道的成绩
+#[derive(Debug, Clone, Serialize)]
+pub struct LaneResult {
+    /// 判定分布
+    pub judge_counts: JudgeCounts,
+    /// 击中次数（不含漏判）
+    pub hits: u32,
+    /// 平均时间偏差（毫秒，正值为偏晚）
+    pub average_timing_ms: Option<f64>,
+}
+
+impl From<&LaneStats> for LaneResult {
+    fn from(stats: &LaneStats) -> Self {
+        Self {
+            judge_counts: stats.judge_counts.into(),
+            hits: stats.hits,
+            average_timing_ms: stats.average_timing_ms(),
+        }
+    }
+}
+
+/// 一次演奏的成绩
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayResult {
+    /// 谱面内容摘要，用于跨会话匹配同一谱面
+    pub chart_hash: Option<String>,
+    /// 谱面标题
+    pub title: Option<String>,
+    /// 谱面艺术家
+    pub artist: Option<String>,
+    /// 血条类型
+    pub gauge_kind: GaugeKind,
+    /// 最终血条（0.0 ~ 1.0）
+    pub gauge: f32,
+    /// 是否通关
+    pub cleared: bool,
+    /// 判定分布
+    pub judge_counts: JudgeCounts,
+    /// 准确率（0.0 ~ 1.0）
+    pub accuracy: f32,
+    /// 最大连击数
+    pub max_combo: u32,
+    /// 偏早击中数
+    pub fast: u32,
+    /// 偏晚击中数
+    pub slow: u32,
+    /// 各轨道的成绩
+    pub lanes: Vec<LaneResult>,
+}
+
+/// 保存演奏结果
+///
+/// # Errors
+///
+/// 序列化或写入文件失败时返回错误
+pub fn save_result(path: &Path, result: &PlayResult) -> Result<()> {
+    let text = serde_json::to_string_pretty(result).context("序列化演奏结果失败")?;
+    std::fs::write(path, text).with_context(|| format!("写入演奏结果失败: {}", path.display()))
+}
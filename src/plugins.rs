@@ -5,11 +5,39 @@
 pub mod audio_manager;
 pub mod audio_trigger;
 pub mod bms_processor;
+pub mod calibration;
+pub mod config_save;
+pub mod hot_reload;
+pub mod input_handler;
+pub mod judge;
+pub mod key_config;
+pub mod loading_screen;
+pub mod log_console;
+pub mod metronome;
 pub mod note_renderer;
+pub mod replay;
+pub mod result;
+pub mod song_select;
+pub mod stats_overlay;
 pub mod time_system;
+pub mod window_mode;
 
 pub use audio_manager::AudioManagerPlugin;
 pub use audio_trigger::AudioTriggerPlugin;
 pub use bms_processor::BMSProcessorPlugin;
+pub use calibration::CalibrationPlugin;
+pub use config_save::ConfigSavePlugin;
+pub use hot_reload::HotReloadPlugin;
+pub use input_handler::InputHandlerPlugin;
+pub use judge::JudgePlugin;
+pub use key_config::KeyConfigPlugin;
+pub use loading_screen::LoadingScreenPlugin;
+pub use log_console::LogConsolePlugin;
+pub use metronome::MetronomePlugin;
 pub use note_renderer::NoteRendererPlugin;
+pub use replay::ReplayPlugin;
+pub use result::ResultPlugin;
+pub use song_select::SongSelectPlugin;
+pub use stats_overlay::StatsOverlayPlugin;
 pub use time_system::TimeSystemPlugin;
+pub use window_mode::WindowModePlugin;
@@ -1,13 +1,27 @@
 //! 音频管理插件
 //!
-//! 负责音频资源的加载、管理和播放控制
+//! 负责音频资源的加载、管理和播放控制。
+//! 开启 `audio.prewarm` 时，开始播放前先在静音通道中把每个音频播放一次，避免首次发声卡顿
 
-use bevy::prelude::*;
-use bevy_kira_audio::{AudioApp, AudioChannel, AudioControl};
+use std::{collections::VecDeque, path::Path, time::Duration};
 
-use crate::plugins::bms_processor::{AudioSystemSet, BmsProcessorResource};
+use bevy::{asset::AssetPath, platform::collections::HashMap, prelude::*};
+use bevy_kira_audio::{
+    AudioApp, AudioChannel, AudioControl,
+    prelude::{AudioInstance, AudioSource as KiraAudioSource, AudioTween, Decibels, PlaybackState},
+};
+use bms_rs::chart_process::prelude::WavId;
+
+use crate::config::SysConfig;
+use crate::plugins::bms_processor::{
+    AudioSystemSet, BmsProcessorResource, PreviewChannel, SectionLoopMessage,
+};
+use crate::plugins::input_handler::ControlMessage;
+use crate::plugins::judge::ComboMilestoneMessage;
+use crate::plugins::time_system::{PauseState, PlaybackRate, signed_secs};
 use crate::resources::NowStamp;
 use crate::schedule::AudioSchedule;
+use crate::state::AppState;
 
 // 导入trait以访问BmsProcessor的方法
 use bms_rs::chart_process::ChartProcessor;
@@ -22,6 +36,209 @@ pub struct AudioPlayMessage {
     pub is_bgm: bool,
 }
 
+/// 连击里程碑音效
+#[derive(Resource, Default)]
+struct MilestoneSound(Option<Handle<KiraAudioSource>>);
+
+/// 每帧预热的音频数量（不超过混音器的同时发声上限）
+const PREWARM_BATCH: usize = 32;
+
+/// 按键音预热使用的静音通道
+#[derive(Resource)]
+struct PrewarmChannel;
+
+/// 按键音预热进度
+#[derive(Resource, Default)]
+enum KeysoundPrewarm {
+    /// 尚未开始
+    #[default]
+    Idle,
+    /// 正在预热，保存尚未播放过的音频
+    Running(Vec<Handle<KiraAudioSource>>),
+    /// 已完成
+    Done,
+}
+
+/// 音频通道类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannelKind {
+    /// BGM 通道
+    Bgm,
+    /// 按键音通道
+    KeySound,
+}
+
+/// 音量设置消息
+#[derive(Message, Clone, Copy, Debug)]
+pub struct SetVolumeMessage {
+    /// 目标通道
+    pub channel: AudioChannelKind,
+    /// 线性增益（1.0 为原始音量）
+    pub gain: f32,
+}
+
+/// 音频停止消息
+///
+/// 停止所有通道（含预览通道）中正在播放的音频，通道本身保持可用
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioStopMessage {
+    /// 立即停止
+    StopAll,
+    /// 在指定时长内淡出后停止
+    FadeOut(Duration),
+}
+
+/// 选曲预览播放消息
+///
+/// 在预览通道循环播放指定音频，替换当前预览；预览通道音量跟随 BGM
+#[derive(Message, Clone, Debug)]
+pub struct PreviewPlayMessage {
+    /// 预览音频句柄
+    pub handle: Handle<KiraAudioSource>,
+}
+
+/// 各通道当前音量（线性增益）
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ChannelVolumes {
+    /// BGM 通道音量
+    pub bgm: f32,
+    /// 按键音通道音量
+    pub key_sound: f32,
+}
+
+/// 已解码音频缓存统计
+///
+/// 记录每个已载入音频的估算大小和最近使用时间，超出预算时按 LRU 淘汰；
+/// 谱面之后还会用到的音频不会被淘汰（此时允许超出预算）
+#[derive(Resource, Debug)]
+pub struct AudioCache {
+    /// 内存预算（字节）
+    budget_bytes: usize,
+    /// 已载入音频的估算大小
+    sizes: HashMap<WavId, usize>,
+    /// 已载入音频的总大小
+    total_bytes: usize,
+    /// 最近使用时刻（单调递增计数）
+    last_used: HashMap<WavId, u64>,
+    /// 使用计数器
+    tick: u64,
+}
+
+/// 抢占发声时的淡出时长（避免爆音）
+const VOICE_STEAL_FADE: Duration = Duration::from_millis(5);
+
+/// 按键音发声管理
+///
+/// 按播放顺序持有按键音实例，超过上限时停止最早的发声
+#[derive(Resource)]
+struct KeysoundVoices {
+    /// 最大同时发声数
+    max_voices: usize,
+    /// 正在发声的实例（从旧到新）
+    active: VecDeque<Handle<AudioInstance>>,
+}
+
+impl FromWorld for KeysoundVoices {
+    fn from_world(world: &mut World) -> Self {
+        let max_voices = world.get_resource::<SysConfig>().map_or_else(
+            || SysConfig::default().audio.max_voices,
+            |c| c.audio.max_voices,
+        );
+        Self {
+            max_voices: max_voices.max(1),
+            active: VecDeque::new(),
+        }
+    }
+}
+
+impl KeysoundVoices {
+    /// 当前发声数
+    fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    /// 移除已停止的实例
+    fn prune(&mut self, instances: &Assets<AudioInstance>) {
+        self.active.retain(|handle| {
+            instances
+                .get(handle)
+                .is_some_and(|instance| instance.state() != PlaybackState::Stopped)
+        });
+    }
+
+    /// 登记新的发声，超出上限时停止最早的发声
+    fn push(&mut self, handle: Handle<AudioInstance>, instances: &mut Assets<AudioInstance>) {
+        self.active.push_back(handle);
+        while self.active.len() > self.max_voices {
+            let Some(oldest) = self.active.pop_front() else {
+                break;
+            };
+            if let Some(instance) = instances.get_mut(&oldest) {
+                instance.stop(AudioTween::linear(VOICE_STEAL_FADE));
+            }
+        }
+    }
+}
+
+impl FromWorld for AudioCache {
+    fn from_world(world: &mut World) -> Self {
+        let budget_mb = world.get_resource::<SysConfig>().map_or_else(
+            || SysConfig::default().audio.cache_budget_mb,
+            |c| c.audio.cache_budget_mb,
+        );
+        Self {
+            budget_bytes: budget_mb.saturating_mul(1024 * 1024),
+            sizes: HashMap::new(),
+            total_bytes: 0,
+            last_used: HashMap::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl AudioCache {
+    /// 缓存是否已达到预算
+    #[must_use]
+    pub const fn is_full(&self) -> bool {
+        self.total_bytes >= self.budget_bytes
+    }
+
+    /// 清空统计（切换谱面时调用）
+    fn clear(&mut self) {
+        self.sizes.clear();
+        self.total_bytes = 0;
+        self.last_used.clear();
+        self.tick = 0;
+    }
+
+    /// 记录一次使用
+    fn touch(&mut self, id: WavId) {
+        self.tick += 1;
+        if let Some(last) = self.last_used.get_mut(&id) {
+            *last = self.tick;
+        } else {
+            self.last_used.insert(id, self.tick);
+        }
+    }
+
+    /// 在满足 `evictable` 的音频中移除最久未使用的一个，返回其ID
+    fn evict_lru(&mut self, evictable: impl Fn(WavId) -> bool) -> Option<WavId> {
+        let victim = self
+            .sizes
+            .keys()
+            .copied()
+            .filter(|&id| evictable(id))
+            .min_by_key(|id| self.last_used.get(id).copied().unwrap_or(0))?;
+        if let Some(size) = self.sizes.remove(&victim) {
+            self.total_bytes -= size;
+        }
+        Some(victim)
+    }
+}
+
+/// 每次按键调整音量的步长
+const VOLUME_STEP: f32 = 0.1;
+
 /// 音频管理插件
 pub struct AudioManagerPlugin;
 
@@ -29,21 +246,313 @@ impl Plugin for AudioManagerPlugin {
     fn build(&self, app: &mut App) {
         app.add_audio_channel::<crate::plugins::bms_processor::BgmChannel>()
             .add_audio_channel::<crate::plugins::bms_processor::SfxChannel>()
+            .add_audio_channel::<PreviewChannel>()
+            .add_audio_channel::<PrewarmChannel>()
+            .add_message::<PreviewPlayMessage>()
             .add_message::<AudioPlayMessage>()
+            .add_message::<SetVolumeMessage>()
+            .add_message::<AudioStopMessage>()
+            .init_resource::<AudioCache>()
+            .init_resource::<KeysoundVoices>()
+            .init_resource::<MilestoneSound>()
+            .init_resource::<KeysoundPrewarm>()
+            .add_systems(Startup, (init_channel_volumes, load_milestone_sound))
+            .add_systems(Update, adjust_volume_by_keys)
             .add_systems(
                 AudioSchedule,
-                (start_when_audio_ready, handle_audio_messages)
+                (
+                    update_audio_cache,
+                    prewarm_keysounds,
+                    start_when_audio_ready,
+                    handle_audio_messages,
+                    play_milestone_sound,
+                )
                     .chain()
                     .in_set(AudioSystemSet::AudioPlay),
             )
+            .add_systems(AudioSchedule, apply_volume_messages)
+            .add_systems(AudioSchedule, (sync_audio_pause, sync_playback_rate))
+            .add_systems(
+                AudioSchedule,
+                (
+                    stop_audio_on_restart,
+                    handle_stop_messages,
+                    handle_preview_messages,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(AppState::SongSelect), stop_audio_on_leave)
             .add_systems(AudioSchedule, print_playback_status);
     }
 }
 
-/// 等待音频资源就绪后开始播放
+/// 将线性增益转换为分贝
+pub fn gain_to_decibels(gain: f32) -> Decibels {
+    if gain <= 0.0 {
+        Decibels::SILENCE
+    } else {
+        Decibels(20.0 * gain.log10())
+    }
+}
+
+/// 加载连击里程碑音效
+fn load_milestone_sound(
+    asset_server: Res<AssetServer>,
+    config: Res<SysConfig>,
+    mut sound: ResMut<MilestoneSound>,
+) {
+    let Some(path) = &config.audio.milestone_sound else {
+        return;
+    };
+    if Path::new(path).exists() {
+        let asset_str = format!("fs://{}", path.to_string_lossy());
+        sound.0 = Some(asset_server.load_override(AssetPath::parse(&asset_str)));
+    } else {
+        warn!("里程碑音效不存在: {}", path.display());
+    }
+}
+
+/// 达到连击里程碑时在按键音通道播放音效，与按键音共用发声数上限
+fn play_milestone_sound(
+    mut milestones: MessageReader<ComboMilestoneMessage>,
+    sound: Res<MilestoneSound>,
+    sfx_channel: Res<AudioChannel<crate::plugins::bms_processor::SfxChannel>>,
+    (mut voices, mut instances): (ResMut<KeysoundVoices>, ResMut<Assets<AudioInstance>>),
+) {
+    if milestones.read().count() == 0 {
+        return;
+    }
+    if let Some(handle) = &sound.0 {
+        let instance = sfx_channel.play(handle.clone()).handle();
+        voices.push(instance, &mut instances);
+    }
+}
+
+/// 从配置读取初始音量并应用到各通道
+fn init_channel_volumes(
+    mut commands: Commands,
+    config: Res<SysConfig>,
+    bgm_channel: Res<AudioChannel<crate::plugins::bms_processor::BgmChannel>>,
+    sfx_channel: Res<AudioChannel<crate::plugins::bms_processor::SfxChannel>>,
+    preview_channel: Res<AudioChannel<PreviewChannel>>,
+    prewarm_channel: Res<AudioChannel<PrewarmChannel>>,
+) {
+    prewarm_channel.set_volume(Decibels::SILENCE);
+    let volumes = ChannelVolumes {
+        bgm: config.audio.bgm_volume.max(0.0),
+        key_sound: config.audio.key_volume.max(0.0),
+    };
+    bgm_channel.set_volume(gain_to_decibels(volumes.bgm));
+    preview_channel.set_volume(gain_to_decibels(volumes.bgm));
+    sfx_channel.set_volume(gain_to_decibels(volumes.key_sound));
+    commands.insert_resource(volumes);
+}
+
+/// 应用音量设置消息
+fn apply_volume_messages(
+    mut messages: MessageReader<SetVolumeMessage>,
+    volumes: Option<ResMut<ChannelVolumes>>,
+    bgm_channel: Res<AudioChannel<crate::plugins::bms_processor::BgmChannel>>,
+    sfx_channel: Res<AudioChannel<crate::plugins::bms_processor::SfxChannel>>,
+    preview_channel: Res<AudioChannel<PreviewChannel>>,
+) {
+    let Some(mut volumes) = volumes else {
+        return;
+    };
+    for message in messages.read() {
+        let gain = message.gain.max(0.0);
+        match message.channel {
+            AudioChannelKind::Bgm => {
+                volumes.bgm = gain;
+                bgm_channel.set_volume(gain_to_decibels(gain));
+                preview_channel.set_volume(gain_to_decibels(gain));
+            }
+            AudioChannelKind::KeySound => {
+                volumes.key_sound = gain;
+                sfx_channel.set_volume(gain_to_decibels(gain));
+            }
+        }
+        println!("🔊 音量 | {:?}: {:.0}%", message.channel, gain * 100.0);
+    }
+}
+
+/// 通过按键调整音量（F1/F2: BGM，F3/F4: 按键音）
+fn adjust_volume_by_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    volumes: Option<Res<ChannelVolumes>>,
+    mut messages: MessageWriter<SetVolumeMessage>,
+) {
+    let Some(volumes) = volumes else {
+        return;
+    };
+    let bindings = [
+        (KeyCode::F1, AudioChannelKind::Bgm, -VOLUME_STEP),
+        (KeyCode::F2, AudioChannelKind::Bgm, VOLUME_STEP),
+        (KeyCode::F3, AudioChannelKind::KeySound, -VOLUME_STEP),
+        (KeyCode::F4, AudioChannelKind::KeySound, VOLUME_STEP),
+    ];
+    for (key, channel, delta) in bindings {
+        if !keys.just_pressed(key) {
+            continue;
+        }
+        let current = match channel {
+            AudioChannelKind::Bgm => volumes.bgm,
+            AudioChannelKind::KeySound => volumes.key_sound,
+        };
+        messages.write(SetVolumeMessage {
+            channel,
+            gain: (current + delta).clamp(0.0, 1.0),
+        });
+    }
+}
+
+/// 估算音频数据占用的字节数（双声道 f32 帧）
+fn audio_source_bytes(source: &bevy_kira_audio::AudioSource) -> usize {
+    source.sound.frames.len() * size_of::<[f32; 2]>()
+}
+
+/// 统计已载入音频的大小，超出预算时淘汰谱面不再使用的音频中最久未使用的一个
+fn update_audio_cache(
+    status: Option<ResMut<BmsProcessorResource>>,
+    assets: Res<Assets<bevy_kira_audio::AudioSource>>,
+    mut cache: ResMut<AudioCache>,
+    now_stamp: Res<NowStamp>,
+) {
+    let Some(mut status) = status else {
+        return;
+    };
+    // 新谱面的音频ID与旧谱面无关
+    if status.is_added() {
+        cache.clear();
+    }
+
+    for (id, handle) in &status.audio_handles {
+        if cache.sizes.contains_key(id) {
+            continue;
+        }
+        if let Some(source) = assets.get(handle) {
+            let size = audio_source_bytes(source);
+            cache.sizes.insert(*id, size);
+            cache.total_bytes += size;
+        }
+    }
+
+    let elapsed = status.chart_elapsed(now_stamp.0);
+    while cache.total_bytes > cache.budget_bytes {
+        let Some(victim) = cache.evict_lru(|id| !status.audio_needed_after(id, elapsed)) else {
+            break;
+        };
+        // 释放句柄后资源随之卸载，重新开始或循环回到之前的位置时重新加载
+        status.audio_handles.remove(&victim);
+    }
+}
+
+/// 在静音通道中分批播放待预热的音频，每帧先停止上一批
+///
+/// 加载新谱面时重置进度
+fn prewarm_keysounds(
+    status: Option<Res<BmsProcessorResource>>,
+    mut prewarm: ResMut<KeysoundPrewarm>,
+    channel: Res<AudioChannel<PrewarmChannel>>,
+) {
+    if status.is_some_and(|status| status.is_added()) {
+        *prewarm = KeysoundPrewarm::Idle;
+    }
+    let KeysoundPrewarm::Running(queue) = &mut *prewarm else {
+        return;
+    };
+    channel.stop();
+    if queue.is_empty() {
+        println!("✓ 按键音预热完成");
+        *prewarm = KeysoundPrewarm::Done;
+        return;
+    }
+    let start = queue.len().saturating_sub(PREWARM_BATCH);
+    for handle in queue.drain(start..) {
+        channel.play(handle).with_volume(Decibels::SILENCE);
+    }
+}
+
+/// 音频的加载结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AudioLoad {
+    /// 已加载
+    Loaded,
+    /// 仍在加载
+    Loading,
+    /// 加载或解码失败
+    Failed,
+}
+
+/// 尚未就绪的音频（按音频 ID 记录）
+#[derive(Debug)]
+struct UnreadyAudio<K = WavId> {
+    /// 仍在加载的音频
+    loading: Vec<K>,
+    /// 加载失败的音频（静音处理，不再等待）
+    failed: Vec<K>,
+}
+
+impl<K: Copy> UnreadyAudio<K> {
+    /// 按加载结果归类音频
+    fn collect<'a>(
+        handles: impl IntoIterator<Item = (&'a K, &'a Handle<KiraAudioSource>)>,
+        load: impl Fn(&Handle<KiraAudioSource>) -> AudioLoad,
+    ) -> Self
+    where
+        K: 'a,
+    {
+        let mut unready = Self {
+            loading: Vec::new(),
+            failed: Vec::new(),
+        };
+        for (id, handle) in handles {
+            match load(handle) {
+                AudioLoad::Loaded => {}
+                AudioLoad::Loading => unready.loading.push(*id),
+                AudioLoad::Failed => unready.failed.push(*id),
+            }
+        }
+        unready
+    }
+}
+
+/// 开始播放前的下一步
+#[derive(Debug, PartialEq, Eq)]
+enum StartStep {
+    /// 继续等待音频加载或预热
+    Wait,
+    /// 开始预热
+    Prewarm,
+    /// 开始播放
+    Start,
+}
+
+/// 根据音频加载与预热进度决定下一步，加载失败的音频不阻止开始播放
+const fn start_step<K>(
+    unready: &UnreadyAudio<K>,
+    prewarm_enabled: bool,
+    prewarm: &KeysoundPrewarm,
+) -> StartStep {
+    if !unready.loading.is_empty() {
+        return StartStep::Wait;
+    }
+    if !prewarm_enabled {
+        return StartStep::Start;
+    }
+    match prewarm {
+        KeysoundPrewarm::Idle => StartStep::Prewarm,
+        KeysoundPrewarm::Running(_) => StartStep::Wait,
+        KeysoundPrewarm::Done => StartStep::Start,
+    }
+}
+
+/// 等待音频资源就绪（开启预热时还需预热完成）后开始播放
 fn start_when_audio_ready(
     status: Option<ResMut<BmsProcessorResource>>,
     assets: Res<Assets<bevy_kira_audio::AudioSource>>,
+    asset_server: Res<AssetServer>,
+    (cache, config, mut prewarm): (Res<AudioCache>, Res<SysConfig>, ResMut<KeysoundPrewarm>),
     now_stamp: Res<NowStamp>,
 ) {
     let Some(mut status) = status else {
@@ -53,63 +562,186 @@ fn start_when_audio_ready(
         return;
     }
 
-    // 检查所有音频是否已加载
-    let mut missing: Vec<bms_rs::chart_process::prelude::WavId> = Vec::new();
-    for (id, handle) in &status.audio_handles {
-        if assets.get(handle).is_none() {
-            missing.push(*id);
-        }
-    }
-
-    if missing.is_empty() {
-        // 所有音频已加载,开始播放
-        println!("✓ 所有音频资源已加载完成,开始播放");
-        status.processor.start_play(now_stamp.0);
-        status.started = true;
-    } else if !status.warned_missing {
-        // 警告缺失的音频
-        for id in missing {
-            if let Some(p) = status.audio_paths.get(&id) {
-                eprintln!("音频未载入: #WAV{:03} -> {}", id.0, p.to_string_lossy());
-            } else {
-                eprintln!("音频未载入: #WAV{:03}", id.0);
+    // 等待预加载完成（内存预算用尽时不再等待剩余音频）
+    if status.has_pending_audio_loads() && !cache.is_full() {
+        return;
+    }
+
+    // 检查所有音频是否已加载，解码失败的音频不再等待
+    let unready = UnreadyAudio::collect(&status.audio_handles, |handle| {
+        if assets.get(handle).is_some() {
+            AudioLoad::Loaded
+        } else if asset_server.load_state(handle).is_failed() {
+            AudioLoad::Failed
+        } else {
+            AudioLoad::Loading
+        }
+    });
+    // 移除路径，避免播放时重新请求加载
+    for id in &unready.failed {
+        status.audio_handles.remove(id);
+        if let Some(p) = status.audio_paths.remove(id) {
+            error!(
+                "音频加载失败,静音处理: #WAV{:03} -> {}",
+                id.0,
+                p.to_string_lossy()
+            );
+        }
+    }
+
+    match start_step(&unready, config.audio.prewarm, &prewarm) {
+        StartStep::Prewarm => {
+            println!("🔥 预热 {} 个音频", status.audio_handles.len());
+            *prewarm = KeysoundPrewarm::Running(status.audio_handles.values().cloned().collect());
+        }
+        StartStep::Start => {
+            // 所有音频已加载,开始播放
+            println!("✓ 所有音频资源已加载完成,开始播放");
+            status.processor.start_play(now_stamp.0);
+            status.started = true;
+        }
+        StartStep::Wait if !unready.loading.is_empty() && !status.warned_missing => {
+            // 警告缺失的音频
+            for id in unready.loading {
+                if let Some(p) = status.audio_paths.get(&id) {
+                    warn!("音频未载入: #WAV{:03} -> {}", id.0, p.to_string_lossy());
+                } else {
+                    warn!("音频未载入: #WAV{:03}", id.0);
+                }
             }
+            status.warned_missing = true;
         }
-        status.warned_missing = true;
+        StartStep::Wait => {}
     }
 }
 
 /// 处理音频播放消息
 fn handle_audio_messages(
-    status: Option<Res<BmsProcessorResource>>,
+    status: Option<ResMut<BmsProcessorResource>>,
     assets: Res<Assets<bevy_kira_audio::AudioSource>>,
+    mut cache: ResMut<AudioCache>,
     bgm_channel: Res<AudioChannel<crate::plugins::bms_processor::BgmChannel>>,
     sfx_channel: Res<AudioChannel<crate::plugins::bms_processor::SfxChannel>>,
     mut messages: MessageReader<AudioPlayMessage>,
+    (mut voices, mut instances): (ResMut<KeysoundVoices>, ResMut<Assets<AudioInstance>>),
 ) {
-    let Some(status) = status else {
+    let Some(mut status) = status else {
         return;
     };
     if !status.started {
         return;
     }
+    voices.prune(&instances);
 
     for message in messages.read() {
+        cache.touch(message.wav_id);
         let Some(handle) = status.audio_handles.get(&message.wav_id) else {
+            // 已被淘汰的音频重新加载，本次跳过播放
+            if status.audio_paths.contains_key(&message.wav_id) {
+                status.request_audio_load(message.wav_id);
+            }
             continue;
         };
         if assets.get(handle).is_none() {
             continue;
         }
 
+        // BGM 不受发声数限制
         if message.is_bgm {
             bgm_channel.play(handle.clone());
         } else {
-            sfx_channel.play(handle.clone());
+            let instance = sfx_channel.play(handle.clone()).handle();
+            voices.push(instance, &mut instances);
+        }
+    }
+}
+
+/// 根据暂停状态暂停/继续所有音频通道
+fn sync_audio_pause(
+    pause: Res<PauseState>,
+    bgm_channel: Res<AudioChannel<crate::plugins::bms_processor::BgmChannel>>,
+    sfx_channel: Res<AudioChannel<crate::plugins::bms_processor::SfxChannel>>,
+) {
+    if !pause.is_changed() || pause.is_added() {
+        return;
+    }
+    if pause.is_paused() {
+        bgm_channel.pause();
+        sfx_channel.pause();
+    } else {
+        bgm_channel.resume();
+        sfx_channel.resume();
+    }
+}
+
+/// 将播放速率同步到所有音频通道
+///
+/// 通过改变播放速度实现，音高随速率一同变化
+fn sync_playback_rate(
+    rate: Res<PlaybackRate>,
+    bgm_channel: Res<AudioChannel<crate::plugins::bms_processor::BgmChannel>>,
+    sfx_channel: Res<AudioChannel<crate::plugins::bms_processor::SfxChannel>>,
+) {
+    if !rate.is_changed() {
+        return;
+    }
+    bgm_channel.set_playback_rate(rate.rate());
+    sfx_channel.set_playback_rate(rate.rate());
+}
+
+/// 重新开始或区间循环回到起点时停止所有正在播放的音频
+fn stop_audio_on_restart(
+    mut controls: MessageReader<ControlMessage>,
+    mut loops: MessageReader<SectionLoopMessage>,
+    mut stop_messages: MessageWriter<AudioStopMessage>,
+) {
+    let looped = loops.read().count() > 0;
+    if ControlMessage::received(&mut controls, ControlMessage::Restart) || looped {
+        stop_messages.write(AudioStopMessage::StopAll);
+    }
+}
+
+/// 返回选曲时停止所有正在播放的音频
+fn stop_audio_on_leave(mut stop_messages: MessageWriter<AudioStopMessage>) {
+    stop_messages.write(AudioStopMessage::StopAll);
+}
+
+/// 处理音频停止消息
+fn handle_stop_messages(
+    mut messages: MessageReader<AudioStopMessage>,
+    bgm_channel: Res<AudioChannel<crate::plugins::bms_processor::BgmChannel>>,
+    sfx_channel: Res<AudioChannel<crate::plugins::bms_processor::SfxChannel>>,
+    preview_channel: Res<AudioChannel<PreviewChannel>>,
+) {
+    for message in messages.read() {
+        match message {
+            AudioStopMessage::StopAll => {
+                bgm_channel.stop();
+                sfx_channel.stop();
+                preview_channel.stop();
+            }
+            AudioStopMessage::FadeOut(duration) => {
+                bgm_channel.stop().fade_out(AudioTween::linear(*duration));
+                sfx_channel.stop().fade_out(AudioTween::linear(*duration));
+                preview_channel
+                    .stop()
+                    .fade_out(AudioTween::linear(*duration));
+            }
         }
     }
 }
 
+/// 播放选曲预览
+fn handle_preview_messages(
+    mut messages: MessageReader<PreviewPlayMessage>,
+    preview_channel: Res<AudioChannel<PreviewChannel>>,
+) {
+    for message in messages.read() {
+        preview_channel.stop();
+        preview_channel.play(message.handle.clone()).looped();
+    }
+}
+
 /// 播放状态资源
 #[derive(Resource, Default)]
 struct PlaybackStatusTimer {
@@ -120,13 +752,16 @@ struct PlaybackStatusTimer {
 fn print_playback_status(
     status: Option<Res<crate::plugins::bms_processor::BmsProcessorResource>>,
     time: Res<Time>,
+    pause: Res<PauseState>,
+    now_stamp: Res<NowStamp>,
+    voices: Res<KeysoundVoices>,
     mut timer: Local<PlaybackStatusTimer>,
 ) {
     let Some(status) = status else {
         return;
     };
 
-    if !status.started {
+    if !status.started || pause.is_paused() {
         return;
     }
 
@@ -135,17 +770,70 @@ fn print_playback_status(
     if timer.last_print >= 1.0 {
         timer.last_print = 0.0;
 
-        if let Some(_started_at) = status.processor.started_at() {
-            let elapsed = time.elapsed().as_secs_f64();
+        if let Some(started_at) = status.processor.started_at() {
+            // 使用游戏时钟计算，暂停时长不计入
+            let elapsed = signed_secs(now_stamp.0, started_at);
             let playback_ratio = status.processor.playback_ratio();
             let bpm = status.processor.current_bpm();
 
             println!(
-                "▶ 播放中 | 时间: {:.1}s | 播放比例: {:.3} | BPM: {:.1}",
+                "▶ 播放中 | 时间: {:.1}s | 播放比例: {:.3} | BPM: {:.1} | 按键音发声: {}/{}",
                 elapsed,
                 playback_ratio.to_f64().unwrap_or(0.0),
-                bpm.to_f64().unwrap_or(120.0)
+                bpm.to_f64().unwrap_or(120.0),
+                voices.len(),
+                voices.max_voices
             );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 加载结果全部相同的若干音频
+    fn unready_audio(count: usize, load: AudioLoad) -> UnreadyAudio<usize> {
+        let handles: HashMap<usize, Handle<KiraAudioSource>> =
+            (0..count).map(|i| (i, Handle::default())).collect();
+        UnreadyAudio::collect(&handles, |_| load)
+    }
+
+    #[test]
+    fn starts_when_every_audio_load_fails() {
+        let unready = unready_audio(16, AudioLoad::Failed);
+        assert!(unready.loading.is_empty());
+        assert_eq!(unready.failed.len(), 16);
+        assert_eq!(
+            start_step(&unready, false, &KeysoundPrewarm::Idle),
+            StartStep::Start
+        );
+        // 开启预热时预热完成后开始
+        assert_eq!(
+            start_step(&unready, true, &KeysoundPrewarm::Idle),
+            StartStep::Prewarm
+        );
+        assert_eq!(
+            start_step(&unready, true, &KeysoundPrewarm::Done),
+            StartStep::Start
+        );
+    }
+
+    #[test]
+    fn waits_while_any_audio_is_loading() {
+        let unready = unready_audio(3, AudioLoad::Loading);
+        assert_eq!(unready.loading.len(), 3);
+        assert_eq!(
+            start_step(&unready, false, &KeysoundPrewarm::Done),
+            StartStep::Wait
+        );
+        assert_eq!(
+            start_step(
+                &unready_audio(3, AudioLoad::Loaded),
+                false,
+                &KeysoundPrewarm::Idle
+            ),
+            StartStep::Start
+        );
+    }
+}
@@ -6,6 +6,7 @@ use bevy::prelude::*;
 use bms_rs::chart_process::prelude::WavId;
 
 use crate::plugins::audio_manager::AudioPlayMessage;
+use crate::resources::ExecArgs;
 use crate::schedule::AudioSchedule;
 
 /// 音符触发消息
@@ -32,11 +33,17 @@ impl Plugin for AudioTriggerPlugin {
 }
 
 /// 将触发消息转换为音频播放消息
+///
+/// BGM 模式下按键音已随谱面按时播放，丢弃击中触发的按键音
 fn convert_events_to_messages(
     mut triggered_events: MessageReader<TriggeredNoteEvent>,
     mut audio_messages: MessageWriter<AudioPlayMessage>,
+    args: Res<ExecArgs>,
 ) {
     for event in triggered_events.read() {
+        if args.bgm_mode && !event.is_bgm {
+            continue;
+        }
         audio_messages.write(AudioPlayMessage {
             wav_id: event.wav_id,
             is_bgm: event.is_bgm,
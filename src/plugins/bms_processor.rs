@@ -3,12 +3,12 @@
 //! 负责BMS文件的异步加载、解析和处理
 
 use std::{
+    hash::{BuildHasher, RandomState},
     path::{Path, PathBuf},
     time::Duration,
 };
 
 use anyhow::Result;
-use async_fs as afs;
 use bevy::{
     asset::AssetPath,
     platform::collections::HashMap,
@@ -18,12 +18,36 @@ use bevy::{
 use bevy_kira_audio::AudioSource as KiraAudioSource;
 use bms_rs::{bms::prelude::*, chart_process::prelude::*};
 use chardetng::EncodingDetector;
-use gametime::TimeSpan;
+use gametime::{TimeSpan, TimeStamp};
+use num_traits::ToPrimitive;
 
+use crate::config::SysConfig;
 use crate::schedule::LogicSchedule;
 
+use crate::archive;
 use crate::filesystem;
+use crate::lane::{KeyLayout, KeyMode, LaneShuffle};
+use crate::plugins::audio_manager::AudioCache;
+use crate::plugins::input_handler::ControlMessage;
+use crate::plugins::judge::NoteCrossedEvent;
+use crate::plugins::time_system::signed_secs;
 use crate::resources::{ExecArgs, NowStamp};
+use crate::state::AppState;
+
+/// 基准BPM下音符从出现到抵达判定线的默认时长（可通过 `judge.visible_travel_ms` 配置）
+pub const VISIBLE_TRAVEL: Duration = Duration::from_millis(600);
+
+/// 渲染外推的最大时长（秒）
+const MAX_RENDER_LEAD: f64 = 0.1;
+
+/// 音符密度统计的最长时长（秒）
+const MAX_DENSITY_SECS: u64 = 3600;
+
+/// 统计音符越过判定线时刻的采样间隔（误差不超过其一半）
+const NOTE_TIME_STEP: Duration = Duration::from_millis(1);
+
+/// 区间循环回到起点时提前的时长，便于看清起点处的音符
+const LOOP_LEAD_IN: Duration = Duration::from_secs(1);
 
 /// 系统集合
 #[derive(SystemSet, Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -49,25 +73,314 @@ pub struct BgmChannel;
 #[derive(Resource)]
 pub struct SfxChannel;
 
+/// 选曲预览通道标记
+#[derive(Resource)]
+pub struct PreviewChannel;
+
+/// 区间循环回到起点时发送的消息
+#[derive(Message, Clone, Copy, Debug)]
+pub struct SectionLoopMessage;
+
+/// 练习用的循环区间（小节号，含两端）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SectionLoop {
+    /// 起始小节（未设置时从第 1 小节开始）
+    pub start: Option<usize>,
+    /// 结束小节（未设置时不循环）
+    pub end: Option<usize>,
+}
+
+/// 选曲列表中的谱面扩展名（`.pms` 使用 PMS 9 键布局）
+pub const CHART_EXTS: [&str; 4] = ["bms", "bme", "bml", "pms"];
+
+/// 支持的音频扩展名（按优先级排列）
+pub const AUDIO_EXTS: [&str; 4] = ["flac", "wav", "ogg", "mp3"];
+
+/// 谱面的难度信息
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChartInfo {
+    /// 难度等级（`#PLAYLEVEL`）
+    pub level: Option<u8>,
+    /// 难度分类（`#DIFFICULTY`，1–5）
+    pub difficulty: Option<u8>,
+    /// 可判定音符总数
+    pub note_count: u32,
+}
+
+impl ChartInfo {
+    /// 难度分类名称
+    #[must_use]
+    pub const fn difficulty_label(self) -> Option<&'static str> {
+        match self.difficulty {
+            Some(1) => Some("BEGINNER"),
+            Some(2) => Some("NORMAL"),
+            Some(3) => Some("HYPER"),
+            Some(4) => Some("ANOTHER"),
+            Some(5) => Some("INSANE"),
+            _ => None,
+        }
+    }
+}
+
+/// 映射到轨道的音符
+#[derive(Debug, Clone, Copy)]
+pub struct ChartNote {
+    /// 事件 ID
+    pub id: ChartEventId,
+    /// 玩家侧
+    pub side: PlayerSide,
+    /// 按键
+    pub key: Key,
+    /// 越过判定线的时刻（相对谱面开始）
+    pub at: Duration,
+}
+
+/// BMS加载结果
+pub struct LoadedBms {
+    /// 解析后的BMS数据
+    pub bms: Bms,
+    /// BMS处理器
+    pub processor: BmsProcessor,
+    /// 音频文件路径映射
+    pub audio_paths: HashMap<WavId, PathBuf>,
+    /// 找不到的音频文件（谱面中声明的路径）
+    pub missing_audio: Vec<(WavId, PathBuf)>,
+    /// 解析警告
+    pub warnings: Vec<BmsWarning>,
+    /// 基准BPM
+    pub base_bpm: f64,
+    /// 每秒可判定音符数量
+    pub note_density: Vec<u32>,
+    /// 各音频最后一次被谱面使用的时刻（相对谱面开始）
+    pub audio_last_use: HashMap<WavId, Duration>,
+    /// 映射到轨道的音符（按越过判定线的时刻排列）
+    pub notes: Vec<ChartNote>,
+    /// 难度信息
+    pub info: ChartInfo,
+    /// 谱面内容摘要
+    pub hash: String,
+    /// 选择 `#RANDOM` 分支使用的随机种子
+    pub seed: i64,
+    /// 创建处理器使用的可见时长
+    pub visible_travel: Duration,
+}
+
 /// BMS加载任务资源
 #[derive(Resource)]
-pub struct BmsLoadTask(pub Task<Result<(BmsProcessor, HashMap<WavId, PathBuf>)>>);
+pub struct BmsLoadTask(pub Task<Result<LoadedBms>>);
 
 /// BMS处理器资源
 #[derive(Resource)]
 pub struct BmsProcessorResource {
+    /// 解析后的BMS数据（用于重新开始时重建处理器）
+    pub bms: Bms,
     /// BMS处理器
     pub processor: BmsProcessor,
     /// 音频文件路径映射
     pub audio_paths: HashMap<WavId, PathBuf>,
     /// 音频资源句柄
     pub audio_handles: HashMap<WavId, Handle<KiraAudioSource>>,
+    /// 基准BPM
+    pub base_bpm: f64,
+    /// 基准BPM下音符从出现到抵达判定线的时长（重建处理器时沿用）
+    pub visible_travel: Duration,
     /// 待加载的音频ID列表
     pending_audio_loads: Vec<WavId>,
     /// 是否已开始播放
     pub started: bool,
+    /// 是否已播放到谱面结尾
+    pub finished: bool,
     /// 是否已警告缺失音频
     pub warned_missing: bool,
+    /// 已经过的小节线数量（当前小节号）
+    pub measure: usize,
+    /// 各小节线相对谱面开始的时长（第 i 项为进入第 i + 1 小节的时刻）
+    bar_offsets: Vec<Duration>,
+    /// 练习用的循环区间
+    pub section_loop: SectionLoop,
+    /// 每秒可判定音符数量（加载时预先统计）
+    pub note_density: Vec<u32>,
+    /// 各音频最后一次被谱面使用的时刻（相对谱面开始，加载时预先统计）
+    audio_last_use: HashMap<WavId, Duration>,
+    /// 映射到轨道的音符（加载时预先统计，按越过判定线的时刻排列）
+    pub notes: Vec<ChartNote>,
+    /// 各音符越过判定线的时刻（相对谱面开始）
+    note_times: HashMap<ChartEventId, Duration>,
+    /// 难度信息
+    pub info: ChartInfo,
+    /// 谱面内容摘要（见 [`chart_hash`]）
+    pub hash: String,
+    /// 选择 `#RANDOM` 分支使用的随机种子（回放据此还原同样的谱面）
+    pub seed: i64,
+    /// 处理器最近一次推进使用的游戏时刻
+    updated_at: Option<TimeStamp>,
+    /// 当前的 `#SCROLL` 滚动倍率
+    pub scroll_factor: f64,
+    /// 最近一次推进后首个可见事件的显示比例，用于判断谱面是否在滚动
+    scroll_sample: Option<(ChartEventId, f64)>,
+    /// 最近一次推进时该事件显示比例的变化量（`#STOP` 期间为 0，未知时为 `None`）
+    scroll_delta: Option<f64>,
+}
+
+impl BmsProcessorResource {
+    /// 是否还有待加载的音频
+    #[must_use]
+    pub const fn has_pending_audio_loads(&self) -> bool {
+        !self.pending_audio_loads.is_empty()
+    }
+
+    /// 音符移动一个可见区间所需的秒数（随当前 BPM 变化）
+    #[must_use]
+    pub fn travel_secs(&self) -> f64 {
+        let bpm = self.processor.current_bpm().to_f64().unwrap_or(120.0);
+        self.visible_travel.as_secs_f64() * self.base_bpm / bpm
+    }
+
+    /// 渲染时刻相对处理器状态的超前量（以显示比例计）
+    ///
+    /// 处理器在 `LogicSchedule` 中以上一帧的时间戳推进，`visible_events` 给出的
+    /// 显示比例对应 `updated_at`；渲染时以当前时间戳减去此超前量即可得到平滑的位置
+    #[must_use]
+    pub fn ratio_lead(&self, now: TimeStamp) -> f64 {
+        let Some(updated_at) = self.updated_at else {
+            return 0.0;
+        };
+        // `#STOP` 期间谱面静止，外推会让音符在停止处来回抖动
+        if self
+            .scroll_delta
+            .is_some_and(|delta| delta.abs() <= f64::EPSILON)
+        {
+            return 0.0;
+        }
+        // 处理器停止推进（如结算画面）时不再继续外推
+        signed_secs(now, updated_at).clamp(0.0, MAX_RENDER_LEAD) * self.scroll_factor
+            / self.travel_secs()
+    }
+
+    /// 可见范围内的可判定音符及其越过判定线的游戏时刻
+    ///
+    /// 时刻由加载时统计的谱面时刻得出，与 `#SCROLL` 等只影响显示的设置无关
+    pub fn visible_notes(&mut self) -> Vec<(PlayheadEvent, TimeStamp)> {
+        let Some(started_at) = self.processor.started_at() else {
+            return Vec::new();
+        };
+        let note_times = &self.note_times;
+        self.processor
+            .visible_events()
+            .filter_map(|(evp, _)| {
+                let at = note_times.get(&evp.id())?;
+                Some((evp, started_at + TimeSpan::from_duration(*at)))
+            })
+            .collect()
+    }
+
+    /// 可见事件及其按 `#SCROLL` 分段修正后的显示比例
+    ///
+    /// 处理器给出的显示比例整体乘以当前的滚动倍率；这里将每个尚未到达的滚动变化点之后的部分
+    /// 按该段倍率重新缩放，使音符间距随各段倍率变化。只用于显示，判定使用音符的谱面时刻
+    pub fn scrolled_visible_events(&mut self) -> Vec<(PlayheadEvent, f64)> {
+        let events: Vec<(PlayheadEvent, f64)> = self
+            .processor
+            .visible_events()
+            .map(|(evp, range)| {
+                let ratio = range.start().as_ref().to_f64().unwrap_or(0.0);
+                (evp, ratio)
+            })
+            .collect();
+        // 倍率为 0 时处理器给出的比例全部为 0，无法按段还原
+        let current = self.scroll_factor;
+        if current.abs() <= f64::EPSILON {
+            return events;
+        }
+        let mut boundaries: Vec<(f64, f64)> = events
+            .iter()
+            .filter_map(|(evp, ratio)| match evp.event() {
+                ChartEvent::ScrollChange { factor } if *ratio > 0.0 => {
+                    factor.to_f64().map(|f| (*ratio, f / current))
+                }
+                _ => None,
+            })
+            .collect();
+        boundaries.sort_by(|a, b| a.0.total_cmp(&b.0));
+        events
+            .into_iter()
+            .map(|(evp, ratio)| (evp, scroll_adjusted(ratio, &boundaries)))
+            .collect()
+    }
+
+    /// 重建处理器并跳到谱面开始后 `offset` 处
+    ///
+    /// 处理器只能从头播放，因此以提前 `offset` 的时刻开始播放并立即推进到当前时刻，
+    /// 跳过的事件（包括 BGM 与音符）不再触发，起点之前开始的长音频不会发声
+    fn seek(&mut self, layout: KeyLayout, now: TimeStamp, offset: Duration) {
+        let (processor, base_bpm) = create_processor(&self.bms, layout, self.visible_travel);
+        self.processor = processor;
+        self.base_bpm = base_bpm;
+        self.finished = false;
+        self.updated_at = None;
+        self.scroll_sample = None;
+        self.processor
+            .start_play(now - TimeSpan::from_duration(offset));
+        let mut measure = 0;
+        let mut scroll_factor = 1.0;
+        for evp in self.processor.update(now) {
+            match evp.event() {
+                ChartEvent::BarLine => measure += 1,
+                ChartEvent::ScrollChange { factor } => {
+                    scroll_factor = factor.to_f64().unwrap_or(scroll_factor);
+                }
+                _ => {}
+            }
+        }
+        self.measure = measure;
+        self.scroll_factor = scroll_factor;
+        self.reload_evicted_audio();
+    }
+
+    /// 请求（重新）加载指定音频
+    pub fn request_audio_load(&mut self, id: WavId) {
+        if !self.pending_audio_loads.contains(&id) {
+            self.pending_audio_loads.push(id);
+        }
+    }
+
+    /// 重新加载已被淘汰的音频（谱面回到较早位置时调用）
+    fn reload_evicted_audio(&mut self) {
+        let evicted: Vec<WavId> = self
+            .audio_paths
+            .keys()
+            .filter(|&id| !self.audio_handles.contains_key(id))
+            .copied()
+            .collect();
+        for id in evicted {
+            self.request_audio_load(id);
+        }
+    }
+
+    /// 谱面开始后经过的时长（未开始时为 0）
+    #[must_use]
+    pub fn chart_elapsed(&self, now: TimeStamp) -> Duration {
+        self.processor
+            .started_at()
+            .map_or(Duration::ZERO, |started_at| {
+                Duration::from_secs_f64(signed_secs(now, started_at).max(0.0))
+            })
+    }
+
+    /// 谱面在 `elapsed` 之后是否还会使用该音频（不确定时视为仍需使用）
+    #[must_use]
+    pub fn audio_needed_after(&self, id: WavId, elapsed: Duration) -> bool {
+        self.audio_last_use
+            .get(&id)
+            .is_none_or(|&last_use| last_use >= elapsed)
+    }
+}
+
+/// 加载谱面消息
+#[derive(Message, Clone, Debug)]
+pub struct LoadChartMessage {
+    /// BMS文件路径
+    pub path: PathBuf,
 }
 
 /// BMS处理插件
@@ -75,13 +388,20 @@ pub struct BMSProcessorPlugin;
 
 impl Plugin for BMSProcessorPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, load_bms_file.in_set(BmsSystemSet::BmsLoad))
+        app.add_message::<LoadChartMessage>()
+            .add_message::<SectionLoopMessage>()
+            .add_systems(Startup, request_initial_chart.in_set(BmsSystemSet::BmsLoad))
+            .add_systems(OnEnter(AppState::SongSelect), unload_chart)
             .add_systems(
                 LogicSchedule,
                 (
+                    load_bms_file,
                     poll_bms_load_task,
                     batch_load_audio_assets,
-                    update_processor_state,
+                    restart_processor,
+                    // 结算期间（包括中途失败）不再推进谱面
+                    update_processor_state.run_if(not(in_state(AppState::Result))),
+                    (set_section_loop, loop_section).run_if(in_state(AppState::Playing)),
                 )
                     .chain()
                     .in_set(BmsSystemSet::EventProcess),
@@ -89,78 +409,306 @@ impl Plugin for BMSProcessorPlugin {
     }
 }
 
-/// 启动BMS文件加载
-fn load_bms_file(mut commands: Commands, args: Res<ExecArgs>) {
-    let Some(bms_path) = args.bms_path.clone() else {
+/// 命令行指定了谱面时直接加载（校准模式除外）
+fn request_initial_chart(args: Res<ExecArgs>, mut loads: MessageWriter<LoadChartMessage>) {
+    if args.calibrate {
+        return;
+    }
+    if let Some(path) = args.bms_path.clone() {
+        loads.write(LoadChartMessage { path });
+    }
+}
+
+/// 启动BMS文件加载，按谱面扩展名切换键位布局（`.pms` 为 PMS 9 键）
+fn load_bms_file(
+    mut commands: Commands,
+    mut loads: MessageReader<LoadChartMessage>,
+    (mut layout, mut lanes): (ResMut<KeyLayout>, ResMut<LaneShuffle>),
+    config: Res<SysConfig>,
+    args: Res<ExecArgs>,
+) {
+    let Some(message) = loads.read().last() else {
         return;
     };
+    let chart_layout = KeyLayout::new(config.keys.bms_mode().for_chart(&message.path), args.double);
+    if layout.set_if_neq(chart_layout) {
+        lanes.set_layout(chart_layout);
+        println!("🎹 键位布局: {} 轨道", chart_layout.lane_count());
+    }
+    let visible_travel = Duration::from_millis(config.judge.visible_travel_ms);
     let pool = IoTaskPool::get();
-    let task = pool.spawn(load_bms_and_collect_paths(bms_path));
+    let task = pool.spawn(load_bms_and_collect_paths(
+        message.path.clone(),
+        *layout,
+        visible_travel,
+        args.seed,
+    ));
+    commands.remove_resource::<BmsProcessorResource>();
     commands.insert_resource(BmsLoadTask(task));
 }
 
-/// 异步加载BMS文件并收集音频路径
-async fn load_bms_and_collect_paths(
-    bms_path: PathBuf,
-) -> Result<(BmsProcessor, HashMap<WavId, PathBuf>)> {
-    // 读取BMS文件
-    let bms_bytes = afs::read(&bms_path).await?;
+/// 返回选曲时卸载当前谱面
+fn unload_chart(mut commands: Commands) {
+    commands.remove_resource::<BmsLoadTask>();
+    commands.remove_resource::<BmsProcessorResource>();
+}
 
-    // 检测字符编码
-    let mut det = EncodingDetector::new();
-    det.feed(&bms_bytes, true);
-    let enc = det.guess(None, true);
-    let (bms_str, _, _) = enc.decode(&bms_bytes);
+/// 读取并解析BMS文件（自动检测字符编码）
+///
+/// # Errors
+///
+/// 文件读取失败或解析失败时返回错误
+pub async fn read_bms(bms_path: &Path) -> Result<Bms> {
+    read_bms_with_warnings(bms_path).await.map(|(bms, _)| bms)
+}
+
+/// 读取并解析BMS文件，同时返回解析警告
+///
+/// # Errors
+///
+/// 文件读取失败或解析失败时返回错误
+pub async fn read_bms_with_warnings(bms_path: &Path) -> Result<(Bms, Vec<BmsWarning>)> {
+    // 读取BMS文件（支持压缩包内的谱面）
+    let bms_bytes = archive::read_file(bms_path).await?;
+    parse_chart(&bms_bytes, None)
+}
+
+/// 解码并解析谱面文件内容
+///
+/// 指定 `seed` 时以与 Java 版播放器相同的随机数生成器选择 `#RANDOM` 分支，
+/// 同一种子总是得到相同的音符；未指定时每次加载随机选择。
+/// 只影响含 `#RANDOM`/`#IF` 的谱面，其他谱面的解析结果与种子无关
+fn parse_chart(bytes: &[u8], seed: Option<i64>) -> Result<(Bms, Vec<BmsWarning>)> {
+    let bms_str = decode_chart_text(bytes);
 
     // 解析BMS文件
-    let BmsOutput { bms, warnings: _ } = bms_rs::bms::parse_bms(&bms_str, default_config());
-    let bms = bms?;
+    let BmsOutput { bms, warnings } = seed.map_or_else(
+        || bms_rs::bms::parse_bms(&bms_str, default_config()),
+        |seed| bms_rs::bms::parse_bms(&bms_str, default_config().rng(JavaRandom::new(seed))),
+    );
+    Ok((bms?, warnings))
+}
 
-    // 生成基础BPM
-    let base_bpm = StartBpmGenerator
-        .generate(&bms)
-        .unwrap_or_else(|| BaseBpm(120.0.into()));
+/// 谱面文件内容的 64 位 FNV-1a 摘要（16 位十六进制），与文件名无关，用于识别同一谱面
+///
+/// 不使用标准库的 `DefaultHasher`，其算法不保证在不同版本间一致
+#[must_use]
+pub fn chart_hash(bytes: &[u8]) -> String {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    let hash = bytes.iter().fold(OFFSET, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    });
+    format!("{hash:016x}")
+}
+
+/// 检测字符编码并解码谱面文本
+#[must_use]
+pub fn decode_chart_text(bytes: &[u8]) -> String {
+    let mut det = EncodingDetector::new();
+    det.feed(bytes, true);
+    let enc = det.guess(None, true);
+    let (text, _, _) = enc.decode(bytes);
+    text.into_owned()
+}
+
+/// 异步加载BMS文件并收集音频路径
+///
+/// 未指定 `seed` 时随机生成，实际使用的种子记录在结果中
+///
+/// # Errors
+///
+/// 文件读取失败或解析失败时返回错误
+pub async fn load_bms_and_collect_paths(
+    bms_path: PathBuf,
+    layout: KeyLayout,
+    visible_travel: Duration,
+    seed: Option<i64>,
+) -> Result<LoadedBms> {
+    let bms_bytes = archive::read_file(&bms_path).await?;
+    let hash = chart_hash(&bms_bytes);
+    let seed = seed.unwrap_or_else(|| RandomState::new().hash_one(()) as i64);
+    let (bms, warnings) = parse_chart(&bms_bytes, Some(seed))?;
 
     // 创建处理器
-    let processor = BmsProcessor::new::<KeyLayoutBeat>(
-        &bms,
-        VisibleRangePerBpm::new(
-            &base_bpm,
-            TimeSpan::from_duration(Duration::from_secs_f32(0.6)),
-        ),
-    );
+    let (processor, base_bpm) = create_processor(&bms, layout, visible_travel);
 
     // 收集音频文件路径
-    let bms_dir = bms_path
-        .parent()
-        .unwrap_or_else(|| Path::new("."))
-        .to_path_buf();
+    let (ids, child_list): (Vec<WavId>, Vec<PathBuf>) = processor
+        .audio_files()
+        .into_iter()
+        .map(|(id, path)| (id, path.to_path_buf()))
+        .unzip();
+    let resolved = filesystem::resolve_chart_files(&bms_path, &child_list, &AUDIO_EXTS).await;
     let mut audio_paths: HashMap<WavId, PathBuf> = HashMap::new();
+    let mut missing_audio: Vec<(WavId, PathBuf)> = Vec::new();
+    for ((id, child), path) in ids.into_iter().zip(child_list).zip(resolved) {
+        match path {
+            Some(path) => {
+                audio_paths.insert(id, path);
+            }
+            None => missing_audio.push((id, child)),
+        }
+    }
+    missing_audio.sort();
+    let ChartTimeline {
+        notes,
+        note_density,
+        audio_last_use,
+    } = chart_timeline(&bms, layout);
+    let info = ChartInfo {
+        level: bms.header.play_level,
+        difficulty: bms.header.difficulty,
+        note_count: note_density.iter().sum(),
+    };
 
-    let child_list: Vec<PathBuf> = processor
-        .audio_files()
-        .into_values()
-        .map(std::path::Path::to_path_buf)
-        .collect();
+    Ok(LoadedBms {
+        bms,
+        processor,
+        audio_paths,
+        missing_audio,
+        warnings,
+        base_bpm,
+        note_density,
+        audio_last_use,
+        notes,
+        info,
+        hash,
+        seed,
+        visible_travel,
+    })
+}
+
+/// 谱面时间轴的统计结果
+struct ChartTimeline {
+    /// 映射到轨道的音符（按越过判定线的时刻排列）
+    notes: Vec<ChartNote>,
+    /// 每秒可判定音符数量（第 i 项为第 i 秒内越过判定线的音符数）
+    note_density: Vec<u32>,
+    /// 各音频最后一次被谱面使用的时刻（按秒取整到其后的整秒）
+    audio_last_use: HashMap<WavId, Duration>,
+}
+
+/// 统计谱面时间轴
+///
+/// 先以 1 秒为步长推进一遍，记录每秒越过判定线的音符数与各音频的使用时刻；
+/// 再以 [`NOTE_TIME_STEP`] 为间隔只细分含有音符的秒，该秒的音符全部出现后直接推进到秒末。
+/// 音符时刻取其被推进出的采样区间的中点，`#STOP` 与变速都已反映在处理器的推进中
+fn chart_timeline(bms: &Bms, layout: KeyLayout) -> ChartTimeline {
+    let start = TimeStamp::now();
+    let stamp = |at: Duration| start + TimeSpan::from_duration(at);
+
+    let (mut coarse, _) = create_processor(bms, layout, VISIBLE_TRAVEL);
+    coarse.start_play(start);
+    let mut counts: Vec<usize> = Vec::new();
+    let mut audio_last_use = HashMap::new();
+    for second in 1..=MAX_DENSITY_SECS {
+        let at = Duration::from_secs(second);
+        let mut count = 0;
+        let mut ended = false;
+        for evp in coarse.update(stamp(at)) {
+            match evp.event() {
+                ChartEvent::Note {
+                    side, key, wav_id, ..
+                } => {
+                    // 与演奏时一致，映射到轨道的音符都交由判定
+                    if layout.key_to_lane(*side, *key).is_some() {
+                        count += 1;
+                    }
+                    if let Some(wav) = wav_id {
+                        audio_last_use.insert(*wav, at);
+                    }
+                }
+                ChartEvent::Bgm { wav_id: Some(wav) } => {
+                    audio_last_use.insert(*wav, at);
+                }
+                ChartEvent::ChartEnd => ended = true,
+                _ => {}
+            }
+        }
+        counts.push(count);
+        if ended {
+            break;
+        }
+    }
 
-    let index = filesystem::choose_paths_by_ext_async(
-        &bms_dir,
-        &child_list,
-        &["flac", "wav", "ogg", "mp3"],
-    )
-    .await;
+    let (mut fine, _) = create_processor(bms, layout, VISIBLE_TRAVEL);
+    fine.start_play(start);
+    let mut notes = Vec::new();
+    for (second, &count) in counts.iter().enumerate() {
+        let second_end = Duration::from_secs(second as u64 + 1);
+        let expected = notes.len() + count;
+        let mut sampled = second_end - Duration::from_secs(1);
+        while sampled < second_end {
+            sampled = if notes.len() < expected {
+                sampled + NOTE_TIME_STEP
+            } else {
+                second_end
+            };
+            let at = sampled - NOTE_TIME_STEP / 2;
+            for evp in fine.update(stamp(sampled)) {
+                let ChartEvent::Note { side, key, .. } = evp.event() else {
+                    continue;
+                };
+                if layout.key_to_lane(*side, *key).is_some() {
+                    notes.push(ChartNote {
+                        id: evp.id(),
+                        side: *side,
+                        key: *key,
+                        at,
+                    });
+                }
+            }
+        }
+    }
 
-    for (id, audio_path) in processor.audio_files().into_iter() {
-        let stem = audio_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .map(std::string::ToString::to_string);
-        let base = bms_dir.join(audio_path);
-        let chosen = stem.and_then(|s| index.get(&s).cloned()).unwrap_or(base);
-        audio_paths.insert(id, chosen);
+    let mut note_density = vec![0; counts.len()];
+    for note in &notes {
+        if let Some(count) = note_density.get_mut(note.at.as_secs() as usize) {
+            *count += 1;
+        }
+    }
+    ChartTimeline {
+        notes,
+        note_density,
+        audio_last_use,
     }
+}
+
+/// 查找谱面中第一个 BGM 音频的相对路径
+#[must_use]
+pub fn first_bgm_audio(bms: &Bms) -> Option<PathBuf> {
+    let (mut processor, _) = create_processor(bms, KeyLayout::default(), VISIBLE_TRAVEL);
+    let start = gametime::TimeStamp::now();
+    processor.start_play(start);
+    let wav_id = processor
+        .update(start + TimeSpan::from_duration(Duration::from_secs(3600)))
+        .find_map(|evp| match evp.event() {
+            ChartEvent::Bgm { wav_id: Some(wav) } => Some(*wav),
+            _ => None,
+        })?;
+    processor
+        .audio_files()
+        .get(&wav_id)
+        .map(|path| path.to_path_buf())
+}
+
+/// 根据BMS数据创建处理器，返回处理器和基准BPM
+///
+/// 按键位布局选择通道映射（PMS 使用 `KeyLayoutPms`）。
+/// `BmsProcessor` 没有重置播放头或修改可见范围的接口，重新开始时也通过此函数重建
+fn create_processor(bms: &Bms, layout: KeyLayout, visible_travel: Duration) -> (BmsProcessor, f64) {
+    // 生成基础BPM
+    let base_bpm = StartBpmGenerator
+        .generate(bms)
+        .unwrap_or_else(|| BaseBpm(120.0.into()));
 
-    Ok((processor, audio_paths))
+    let visible_range = VisibleRangePerBpm::new(&base_bpm, TimeSpan::from_duration(visible_travel));
+    let processor = match layout.mode {
+        KeyMode::Pms9K => BmsProcessor::new::<KeyLayoutPms>(bms, visible_range),
+        KeyMode::Beat5K | KeyMode::Beat7K => BmsProcessor::new::<KeyLayoutBeat>(bms, visible_range),
+    };
+    (processor, base_bpm.0.to_f64().unwrap_or(120.0))
 }
 
 /// 轮询BMS加载任务状态
@@ -168,6 +716,7 @@ fn poll_bms_load_task(
     mut commands: Commands,
     _asset_server: Res<AssetServer>,
     task_res: Option<ResMut<BmsLoadTask>>,
+    mut lanes: ResMut<LaneShuffle>,
 ) {
     let Some(mut task) = task_res else {
         return;
@@ -175,33 +724,114 @@ fn poll_bms_load_task(
 
     if let Some(result) = check_ready(&mut task.0) {
         match result {
-            Ok((processor, audio_paths)) => {
+            Ok(LoadedBms {
+                bms,
+                processor,
+                audio_paths,
+                missing_audio,
+                warnings,
+                base_bpm,
+                note_density,
+                audio_last_use,
+                notes,
+                info,
+                hash,
+                seed,
+                visible_travel,
+            }) => {
+                for warning in &warnings {
+                    warn!("谱面解析警告: {warning}");
+                }
+                for (id, child) in missing_audio {
+                    warn!(
+                        "找不到音频文件: #WAV{:03} -> {}",
+                        id.0,
+                        child.to_string_lossy()
+                    );
+                }
+                if info.note_count == 0 {
+                    println!("ℹ 谱面没有可判定的音符，只播放 BGM");
+                }
                 // 收集所有音频ID,稍后分批加载
                 let all_audio_ids: Vec<_> = audio_paths.keys().copied().collect();
 
+                lanes.reroll(&notes);
                 // 创建处理器资源（音频句柄为空,稍后分批加载）
                 commands.insert_resource(BmsProcessorResource {
+                    bms,
                     processor,
                     audio_handles: HashMap::new(),
                     audio_paths,
+                    base_bpm,
+                    visible_travel,
                     pending_audio_loads: all_audio_ids,
                     started: false,
+                    finished: false,
                     warned_missing: false,
+                    measure: 0,
+                    bar_offsets: Vec::new(),
+                    section_loop: SectionLoop::default(),
+                    note_density,
+                    audio_last_use,
+                    note_times: notes.iter().map(|note| (note.id, note.at)).collect(),
+                    notes,
+                    info,
+                    hash,
+                    seed,
+                    updated_at: None,
+                    scroll_factor: 1.0,
+                    scroll_sample: None,
+                    scroll_delta: None,
                 });
             }
             Err(e) => {
-                eprintln!("{}", e);
+                error!("{e:#}");
             }
         }
         commands.remove_resource::<BmsLoadTask>();
     }
 }
 
+/// 收到重新开始消息时重建处理器并从头播放
+fn restart_processor(
+    mut controls: MessageReader<ControlMessage>,
+    status: Option<ResMut<BmsProcessorResource>>,
+    now_stamp: Res<NowStamp>,
+    layout: Res<KeyLayout>,
+    mut lanes: ResMut<LaneShuffle>,
+) {
+    let restart = ControlMessage::received(&mut controls, ControlMessage::Restart);
+    let Some(mut status) = status else {
+        return;
+    };
+    if !restart || !status.started {
+        return;
+    }
+
+    let (processor, base_bpm) = create_processor(&status.bms, *layout, status.visible_travel);
+    status.processor = processor;
+    status.base_bpm = base_bpm;
+    status.finished = false;
+    status.measure = 0;
+    status.updated_at = None;
+    status.scroll_factor = 1.0;
+    status.scroll_sample = None;
+    status.processor.start_play(now_stamp.0);
+    status.reload_evicted_audio();
+    lanes.reroll(&status.notes);
+    println!("↺ 重新开始");
+}
+
 /// 更新处理器状态并发送触发消息
+///
+/// BGM 模式下音符的按键音在越过判定线时按 BGM 播放，不依赖击中
 fn update_processor_state(
     status: Option<ResMut<BmsProcessorResource>>,
     mut triggered_events: MessageWriter<crate::plugins::audio_trigger::TriggeredNoteEvent>,
+    mut crossed_notes: MessageWriter<NoteCrossedEvent>,
     now_stamp: Res<NowStamp>,
+    lanes: Res<LaneShuffle>,
+    args: Res<ExecArgs>,
 ) {
     let Some(mut status) = status else {
         return;
@@ -210,16 +840,57 @@ fn update_processor_state(
         return;
     }
 
-    // 先收集音频句柄
-    let audio_ids: Vec<_> = status.audio_handles.keys().copied().collect();
+    status.updated_at = Some(now_stamp.0);
+
+    // 先收集可播放的音频（包括已被淘汰、播放时重新加载的音频）
+    let audio_ids: Vec<_> = status.audio_paths.keys().copied().collect();
 
     // 更新处理器并发送触发事件
+    let mut chart_ended = false;
+    let mut bar_lines = 0;
+    let mut scroll_factor = None;
+    let started_at = status.processor.started_at();
+    let status = &mut *status;
+    let note_times = &status.note_times;
+    let note_time = |id: ChartEventId| {
+        let at = note_times.get(&id)?;
+        started_at.map(|started_at| started_at + TimeSpan::from_duration(*at))
+    };
     for evp in status.processor.update(now_stamp.0) {
         let (wav, is_bgm) = match evp.event() {
             ChartEvent::Bgm { wav_id: Some(wav) } => (wav, true),
             ChartEvent::Note {
-                wav_id: Some(wav), ..
-            } => (wav, false),
+                side, key, wav_id, ..
+            } => {
+                // 可判定的音符交由判定插件处理
+                if let Some(lane) = lanes.note_lane(*side, *key, evp.id()) {
+                    crossed_notes.write(NoteCrossedEvent {
+                        event_id: evp.id(),
+                        lane,
+                        wav_id: *wav_id,
+                        crossed_at: note_time(evp.id()).unwrap_or(now_stamp.0),
+                    });
+                    if !args.bgm_mode {
+                        continue;
+                    }
+                }
+                let Some(wav) = wav_id else {
+                    continue;
+                };
+                (wav, args.bgm_mode)
+            }
+            ChartEvent::ChartEnd => {
+                chart_ended = true;
+                continue;
+            }
+            ChartEvent::BarLine => {
+                bar_lines += 1;
+                continue;
+            }
+            ChartEvent::ScrollChange { factor } => {
+                scroll_factor = factor.to_f64().or(scroll_factor);
+                continue;
+            }
             _ => continue,
         };
 
@@ -232,17 +903,136 @@ fn update_processor_state(
             });
         }
     }
+
+    status.measure += bar_lines;
+    if let Some(factor) = scroll_factor {
+        status.scroll_factor = factor;
+    }
+    update_scrolling(status);
+    // 记录首次经过各小节线的时刻，供区间循环跳转
+    if let Some(started_at) = started_at {
+        let elapsed = Duration::from_secs_f64(signed_secs(now_stamp.0, started_at).max(0.0));
+        while status.bar_offsets.len() < status.measure {
+            status.bar_offsets.push(elapsed);
+        }
+    }
+    if chart_ended {
+        status.finished = true;
+    }
+}
+
+/// 按滚动变化点分段缩放显示比例（`boundaries` 为按比例升序的变化点与相对倍率）
+fn scroll_adjusted(ratio: f64, boundaries: &[(f64, f64)]) -> f64 {
+    let mut position = 0.0;
+    let mut start = 0.0;
+    let mut factor = 1.0;
+    for &(at, next) in boundaries {
+        if at >= ratio {
+            break;
+        }
+        position += (at - start) * factor;
+        start = at;
+        factor = next;
+    }
+    position + (ratio - start) * factor
+}
+
+/// 比较同一可见事件前后两次推进的显示比例，判断谱面是否处于 `#STOP` 中
+///
+/// 停止期间处理器给出的显示比例保持不变，音符已随之停住，这里只需停止渲染外推
+fn update_scrolling(status: &mut BmsProcessorResource) {
+    let sample = status
+        .processor
+        .visible_events()
+        .next()
+        .and_then(|(evp, range)| range.start().as_ref().to_f64().map(|r| (evp.id(), r)));
+    status.scroll_delta = match (status.scroll_sample, sample) {
+        (Some((prev_id, prev)), Some((id, ratio))) if prev_id == id => Some(ratio - prev),
+        _ => None,
+    };
+    status.scroll_sample = sample;
+}
+
+/// 按控制消息设置或清除循环区间（以当前小节为端点）
+fn set_section_loop(
+    mut controls: MessageReader<ControlMessage>,
+    status: Option<ResMut<BmsProcessorResource>>,
+) {
+    let Some(mut status) = status else {
+        controls.clear();
+        return;
+    };
+    let measure = status.measure.max(1);
+    let section_loop = &mut status.section_loop;
+    for control in controls.read() {
+        match control {
+            ControlMessage::SetLoopStart => {
+                section_loop.start = Some(measure);
+                if section_loop.end.is_some_and(|end| end < measure) {
+                    section_loop.end = None;
+                }
+            }
+            ControlMessage::SetLoopEnd => {
+                if section_loop.start.is_some_and(|start| start > measure) {
+                    section_loop.start = None;
+                }
+                section_loop.end = Some(measure);
+            }
+            ControlMessage::ClearLoop => *section_loop = SectionLoop::default(),
+            _ => continue,
+        }
+        match (section_loop.start, section_loop.end) {
+            (None, None) => println!("🔁 已取消区间循环"),
+            (start, None) => println!("🔁 循环起点: 第 {} 小节", start.unwrap_or(1)),
+            (start, Some(end)) => {
+                println!("🔁 循环区间: 第 {}-{end} 小节", start.unwrap_or(1));
+            }
+        }
+    }
+}
+
+/// 越过循环终点（或谱面结束）时跳回循环起点
+fn loop_section(
+    status: Option<ResMut<BmsProcessorResource>>,
+    now_stamp: Res<NowStamp>,
+    layout: Res<KeyLayout>,
+    mut loops: MessageWriter<SectionLoopMessage>,
+) {
+    let Some(mut status) = status else {
+        return;
+    };
+    let Some(end) = status.section_loop.end else {
+        return;
+    };
+    if !status.started || (status.measure <= end && !status.finished) {
+        return;
+    }
+
+    let start = status.section_loop.start.unwrap_or(1);
+    let offset = status
+        .bar_offsets
+        .get(start - 1)
+        .map_or(Duration::ZERO, |offset| offset.saturating_sub(LOOP_LEAD_IN));
+    status.seek(*layout, now_stamp.0, offset);
+    loops.write(SectionLoopMessage);
+    println!("🔁 回到第 {start} 小节");
 }
 
 /// 分批加载音频资源
 fn batch_load_audio_assets(
     status: Option<ResMut<BmsProcessorResource>>,
     asset_server: Res<AssetServer>,
+    cache: Res<AudioCache>,
 ) {
     let Some(mut status) = status else {
         return;
     };
 
+    // 预加载阶段遵守内存预算，剩余音频在首次播放时按需加载
+    if !status.started && cache.is_full() {
+        return;
+    }
+
     // 每帧加载最多10个音频文件
     const BATCH_SIZE: usize = 10;
     let mut loaded_count = 0;
@@ -251,8 +1041,7 @@ fn batch_load_audio_assets(
     while !status.pending_audio_loads.is_empty() && loaded_count < BATCH_SIZE {
         let id = status.pending_audio_loads.remove(0);
         if let Some(path) = status.audio_paths.get(&id) {
-            let path_str = path.to_string_lossy().to_string();
-            let asset_str = format!("fs://{}", path_str);
+            let asset_str = archive::asset_path_string(path);
             let ap = AssetPath::parse(&asset_str);
             let handle: Handle<KiraAudioSource> = asset_server.load_override(ap);
             status.audio_handles.insert(id, handle);
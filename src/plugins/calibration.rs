@@ -0,0 +1,127 @@
+//! 校准插件
+//!
+//! 播放节拍器并统计按键相对节拍的平均偏差，用于设置判定偏移
+
+use std::{collections::VecDeque, path::Path, time::Duration};
+
+use bevy::{asset::AssetPath, prelude::*};
+use bevy_kira_audio::{AudioChannel, AudioControl, AudioSource as KiraAudioSource};
+use gametime::{TimeSpan, TimeStamp};
+
+use crate::config::SysConfig;
+use crate::plugins::bms_processor::SfxChannel;
+use crate::plugins::input_handler::LaneInputMessage;
+use crate::plugins::time_system::signed_secs;
+use crate::resources::NowStamp;
+use crate::state::AppState;
+
+/// 节拍间隔（120 BPM）
+const BEAT_INTERVAL: Duration = Duration::from_millis(500);
+/// 节拍器开始前的等待时长
+const LEAD_IN: Duration = Duration::from_secs(1);
+/// 计算平均偏差使用的最近按键数
+const SAMPLE_COUNT: usize = 16;
+
+/// 校准状态
+#[derive(Resource, Default)]
+struct CalibrationState {
+    /// 节拍器音效
+    sound: Option<Handle<KiraAudioSource>>,
+    /// 第 0 拍的时刻（音效就绪后确定）
+    first_beat: Option<TimeStamp>,
+    /// 下一个要播放的拍子序号
+    next_beat: u32,
+    /// 最近的按键偏差（秒，正值为偏晚）
+    samples: VecDeque<f64>,
+}
+
+/// 校准插件
+pub struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CalibrationState>()
+            .add_systems(OnEnter(AppState::Calibration), setup_calibration)
+            .add_systems(
+                Update,
+                (play_metronome, record_calibration_hits)
+                    .chain()
+                    .run_if(in_state(AppState::Calibration)),
+            );
+    }
+}
+
+/// 加载节拍器音效
+fn setup_calibration(
+    asset_server: Res<AssetServer>,
+    config: Res<SysConfig>,
+    mut state: ResMut<CalibrationState>,
+) {
+    let path = &config.judge.metronome_sound;
+    if Path::new(path).exists() {
+        let asset_str = format!("fs://{}", path.to_string_lossy());
+        state.sound = Some(asset_server.load_override(AssetPath::parse(&asset_str)));
+    } else {
+        warn!("节拍器音效不存在: {}", path.display());
+    }
+    println!("🎯 校准模式: 跟随节拍按任意轨道键，平均偏差即为建议的 judge.offset_ms");
+}
+
+/// 按节拍播放节拍器音效
+fn play_metronome(
+    now_stamp: Res<NowStamp>,
+    mut state: ResMut<CalibrationState>,
+    assets: Res<Assets<KiraAudioSource>>,
+    sfx_channel: Res<AudioChannel<SfxChannel>>,
+) {
+    let Some(first_beat) = state.first_beat else {
+        // 音效就绪（或不存在）后开始计拍
+        if state.sound.as_ref().is_none_or(|h| assets.get(h).is_some()) {
+            state.first_beat = Some(now_stamp.0 + TimeSpan::from_duration(LEAD_IN));
+        }
+        return;
+    };
+
+    let beat_at = first_beat + TimeSpan::from_duration(BEAT_INTERVAL * state.next_beat);
+    if now_stamp.0 < beat_at {
+        return;
+    }
+    state.next_beat += 1;
+    if let Some(sound) = &state.sound {
+        sfx_channel.play(sound.clone());
+    }
+}
+
+/// 记录按键相对最近拍子的偏差并报告平均值
+fn record_calibration_hits(
+    mut inputs: MessageReader<LaneInputMessage>,
+    now_stamp: Res<NowStamp>,
+    mut state: ResMut<CalibrationState>,
+) {
+    let Some(first_beat) = state.first_beat else {
+        inputs.clear();
+        return;
+    };
+    let interval = BEAT_INTERVAL.as_secs_f64();
+
+    for input in inputs.read() {
+        if !input.pressed {
+            continue;
+        }
+        let elapsed = signed_secs(input.time.unwrap_or(now_stamp.0), first_beat);
+        let nearest = (elapsed / interval).round().max(0.0);
+        let dt = elapsed - nearest * interval;
+
+        state.samples.push_back(dt);
+        if state.samples.len() > SAMPLE_COUNT {
+            state.samples.pop_front();
+        }
+        let average = state.samples.iter().sum::<f64>() / state.samples.len() as f64;
+        println!(
+            "偏差 {:+.1}ms | 平均 {:+.1}ms（最近 {} 次）",
+            dt * 1000.0,
+            average * 1000.0,
+            state.samples.len()
+        );
+    }
+}
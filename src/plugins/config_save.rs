@@ -0,0 +1,57 @@
+//! 配置延迟保存插件
+//!
+//! 游戏中通过按键修改的设置（判定偏移、可见时长、轨道遮挡、音符方向）不在每次修改时写入文件，
+//! 停止调整一段时间后或退出程序时只保存一次
+
+use std::{path::Path, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::config::{self, SYS_CONFIG_PATH, SysConfig};
+
+/// 最后一次调整后等待多久保存
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// 待保存的配置修改
+#[derive(Resource, Default)]
+pub struct PendingConfigSave {
+    /// 预定的保存时刻（程序启动后的真实时间），没有待保存的修改时为 `None`
+    due: Option<Duration>,
+}
+
+impl PendingConfigSave {
+    /// 记录一次配置修改，在 `now` 之后等待 [`SAVE_DELAY`] 再保存
+    pub fn request(&mut self, now: Duration) {
+        self.due = Some(now + SAVE_DELAY);
+    }
+}
+
+/// 配置延迟保存插件
+pub struct ConfigSavePlugin;
+
+impl Plugin for ConfigSavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingConfigSave>()
+            .add_systems(Last, save_pending_config);
+    }
+}
+
+/// 到达预定时刻或程序退出时保存配置
+fn save_pending_config(
+    time: Res<Time<Real>>,
+    mut exits: MessageReader<AppExit>,
+    mut pending: ResMut<PendingConfigSave>,
+    config: Res<SysConfig>,
+) {
+    let exiting = exits.read().count() > 0;
+    let Some(due) = pending.due else {
+        return;
+    };
+    if !exiting && time.elapsed() < due {
+        return;
+    }
+    pending.due = None;
+    if let Err(e) = config::save_sys(Path::new(SYS_CONFIG_PATH), &config) {
+        eprintln!("{e:#}");
+    }
+}
@@ -0,0 +1,149 @@
+//! 热重载插件
+//!
+//! 配置 `debug.hot_reload` 开启后，每秒检查皮肤与系统配置文件的修改时间，变化时重新读取：
+//! 皮肤立即生效（场地按新皮肤重建）；系统配置整体替换内存中的配置，之后游戏内保存设置时不会覆盖
+//! 文件中的修改。多数设置立即或在下一次加载谱面时生效，画面输出、日志控制台与音频缓存等启动时读取的
+//! 设置仍需重启。游戏中修改的键位推迟到回到选曲画面时生效，避免按住的按键收不到松开。
+//! 文件无法解析或被删除时保留当前设置
+
+use std::{path::Path, time::SystemTime};
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::config::{self, KeysConfig, SYS_CONFIG_PATH, SysConfig};
+use crate::plugins::judge::JudgeParams;
+use crate::skin::{self, SKIN_PATH, Skin};
+use crate::state::AppState;
+
+/// 检查文件修改的间隔（秒）
+const POLL_INTERVAL: f32 = 1.0;
+
+/// 上次读取时的文件修改时间
+#[derive(Resource)]
+struct WatchedFiles {
+    /// 皮肤文件
+    skin: Option<SystemTime>,
+    /// 系统配置文件
+    config: Option<SystemTime>,
+}
+
+/// 游戏中重新加载、等待回到选曲画面时生效的键位
+#[derive(Resource, Default)]
+struct DeferredKeys(Option<KeysConfig>);
+
+/// 热重载插件
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WatchedFiles {
+            skin: modified_time(Path::new(SKIN_PATH)),
+            config: modified_time(Path::new(SYS_CONFIG_PATH)),
+        })
+        .init_resource::<DeferredKeys>()
+        .add_systems(
+            Update,
+            reload_changed_files.run_if(|config: Res<SysConfig>| config.debug.hot_reload),
+        )
+        .add_systems(OnEnter(AppState::SongSelect), apply_deferred_keys);
+    }
+}
+
+/// 文件的修改时间，文件不存在时返回 `None`
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// 文件存在且修改时间与上次不同时记录新的修改时间并返回 `true`
+fn file_changed(path: &Path, last: &mut Option<SystemTime>) -> bool {
+    let Some(modified) = modified_time(path) else {
+        return false;
+    };
+    if *last == Some(modified) {
+        return false;
+    }
+    *last = Some(modified);
+    true
+}
+
+/// 两个值序列化后是否一致
+fn same_toml<T: Serialize>(a: &T, b: &T) -> bool {
+    toml::to_string(a).ok() == toml::to_string(b).ok()
+}
+
+/// 定期检查文件修改并重新加载皮肤与系统配置
+fn reload_changed_files(
+    time: Res<Time>,
+    mut elapsed: Local<f32>,
+    mut watched: ResMut<WatchedFiles>,
+    mut skin: ResMut<Skin>,
+    (mut config, mut params): (ResMut<SysConfig>, ResMut<JudgeParams>),
+    state: Res<State<AppState>>,
+    mut deferred: ResMut<DeferredKeys>,
+) {
+    *elapsed += time.delta_secs();
+    if *elapsed < POLL_INTERVAL {
+        return;
+    }
+    *elapsed = 0.0;
+
+    let skin_path = Path::new(SKIN_PATH);
+    if file_changed(skin_path, &mut watched.skin) {
+        match skin::load_skin(skin_path) {
+            Ok(new_skin) => {
+                *skin = new_skin;
+                println!("🎨 已重新加载皮肤: {}", skin_path.display());
+            }
+            Err(e) => eprintln!("皮肤重新加载失败,保留当前皮肤: {e:#}"),
+        }
+    }
+
+    let config_path = Path::new(SYS_CONFIG_PATH);
+    if file_changed(config_path, &mut watched.config) {
+        let mut new_config = match config::load_sys(config_path) {
+            Ok(new_config) => new_config,
+            Err(e) => {
+                eprintln!("配置重新加载失败,保留当前配置: {e:#}");
+                return;
+            }
+        };
+        // 全屏由 F11 切换窗口时一并保存，这里不改变窗口模式
+        new_config.display.fullscreen = config.display.fullscreen;
+        // 游戏中替换键位会使按住的按键收不到松开，保留当前键位直到回到选曲画面
+        if *state.get() == AppState::Playing {
+            let new_keys = std::mem::replace(&mut new_config.keys, config.keys.clone());
+            if same_toml(&new_keys, &config.keys) {
+                deferred.0 = None;
+            } else {
+                println!("ℹ 键位设置将在回到选曲画面后生效");
+                deferred.0 = Some(new_keys);
+            }
+        }
+        // 游戏内保存配置也会修改文件，内容一致时不替换，避免触发配置变化
+        if same_toml(&new_config, &*config) {
+            return;
+        }
+        if !same_toml(&new_config.video, &config.video)
+            || new_config.debug.log_console != config.debug.log_console
+            || new_config.debug.log_console_lines != config.debug.log_console_lines
+            || new_config.audio.cache_budget_mb != config.audio.cache_budget_mb
+            || new_config.audio.max_voices != config.audio.max_voices
+        {
+            println!("ℹ 画面输出、日志控制台与音频缓存设置需重启后生效");
+        }
+        *config = new_config;
+        *params = JudgeParams::from_config(&config);
+        println!("⚙ 已重新加载配置: {}", config_path.display());
+    }
+}
+
+/// 回到选曲画面时应用游戏中重新加载的键位
+fn apply_deferred_keys(mut deferred: ResMut<DeferredKeys>, mut config: ResMut<SysConfig>) {
+    if let Some(keys) = deferred.0.take() {
+        config.keys = keys;
+        println!("⌨ 已应用重新加载的键位");
+    }
+}
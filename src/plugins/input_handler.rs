@@ -0,0 +1,388 @@
+//! 输入处理插件
+//!
+//! 将键盘、手柄以及（开启时）鼠标与触摸输入映射为轨道按键消息，将键盘输入映射为游戏控制消息
+
+use std::time::Duration;
+
+use bevy::{
+    input::InputSystems, platform::collections::HashMap, prelude::*, window::PrimaryWindow,
+};
+use gametime::TimeStamp;
+
+use crate::config::SysConfig;
+use crate::lane::KeyLayout;
+use crate::plugins::note_renderer::lane_at;
+use crate::plugins::time_system::{PauseState, PlaybackRate, current_game_time};
+use crate::resources::ExecArgs;
+use crate::skin::Skin;
+
+/// 轨道输入的来源
+///
+/// 判定按来源分别记录按下状态：同一来源重复的按下不会再次判定，
+/// 绑定到同一轨道的其他来源松开时也不影响仍按住的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    /// 键盘按键
+    Keyboard(KeyCode),
+    /// 手柄按钮
+    GamepadButton(Entity, GamepadButton),
+    /// 手柄转盘轴
+    ScratchAxis(Entity),
+    /// 鼠标左键
+    Mouse,
+    /// 触摸点（触摸 ID）
+    Touch(u64),
+    /// 回放（录制时按出现顺序编号的来源）
+    Replay(u16),
+}
+
+/// 轨道输入消息
+///
+/// 轨道按键按下或松开时发送
+#[derive(Message, Clone, Copy, Debug)]
+pub struct LaneInputMessage {
+    /// 轨道索引
+    pub lane: usize,
+    /// 输入来源
+    pub source: InputSource,
+    /// 是否按下
+    pub pressed: bool,
+    /// 输入发生的时刻（读取输入时的游戏时刻，精度为一次更新的间隔；回放时精确到纳秒），
+    /// `None` 表示处理时的当前时刻
+    pub time: Option<TimeStamp>,
+}
+
+/// 游戏控制消息
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlMessage {
+    /// 切换暂停/继续
+    TogglePause,
+    /// 重新开始当前谱面
+    Restart,
+    /// 切换性能统计显示
+    ToggleStats,
+    /// 切换全屏/窗口模式
+    ToggleFullscreen,
+    /// 切换日志控制台显示
+    ToggleLogConsole,
+    /// 将当前小节设为循环起点
+    SetLoopStart,
+    /// 将当前小节设为循环终点
+    SetLoopEnd,
+    /// 取消区间循环
+    ClearLoop,
+}
+
+impl ControlMessage {
+    /// 读取全部控制消息，返回其中是否包含 `target`
+    pub fn received(controls: &mut MessageReader<Self>, target: Self) -> bool {
+        let mut found = false;
+        for control in controls.read() {
+            found |= *control == target;
+        }
+        found
+    }
+}
+
+/// 输入处理插件
+pub struct InputHandlerPlugin;
+
+impl Plugin for InputHandlerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<LaneInputMessage>()
+            .add_message::<ControlMessage>()
+            .add_systems(
+                PreUpdate,
+                (
+                    (read_keyboard_input, read_gamepad_input).run_if(accepts_live_input),
+                    read_pointer_input.run_if(accepts_live_input.and(touch_enabled)),
+                    read_control_input,
+                )
+                    .after(InputSystems),
+            );
+    }
+}
+
+/// 是否读取键盘轨道输入（回放时由回放插件提供输入）
+fn accepts_live_input(args: Res<ExecArgs>) -> bool {
+    args.replay.is_none()
+}
+
+/// 读取键盘输入并发送轨道输入消息
+fn read_keyboard_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    config: Res<SysConfig>,
+    layout: Res<KeyLayout>,
+    (pause, rate): (Res<PauseState>, Res<PlaybackRate>),
+    mut lane_inputs: MessageWriter<LaneInputMessage>,
+) {
+    let stamp = Some(current_game_time(&pause, &rate));
+    let side_lanes = layout.side_lane_count();
+    let p1_keys = config.keys.lanes_for(layout.mode).iter().take(side_lanes);
+    let p2_keys = config
+        .keys
+        .lanes_2p
+        .iter()
+        .take(if layout.double { side_lanes } else { 0 });
+    // P1 按键不足时 P2 轨道索引仍从单侧轨道数开始
+    let lane_keys = p1_keys
+        .enumerate()
+        .chain(p2_keys.enumerate().map(|(i, key)| (side_lanes + i, key)));
+    for (lane, key) in lane_keys {
+        let source = InputSource::Keyboard(*key);
+        if keys.just_pressed(*key) {
+            lane_inputs.write(LaneInputMessage {
+                lane,
+                source,
+                pressed: true,
+                time: stamp,
+            });
+        }
+        if keys.just_released(*key) {
+            lane_inputs.write(LaneInputMessage {
+                lane,
+                source,
+                pressed: false,
+                time: stamp,
+            });
+        }
+    }
+}
+
+/// 是否开启鼠标与触摸输入
+fn touch_enabled(config: Res<SysConfig>) -> bool {
+    config.input.touch_enabled
+}
+
+/// 读取鼠标与触摸输入，按所在的轨道列发送轨道输入消息
+///
+/// 每个指针在按下时确定轨道，松开前滑动到其他轨道不改变
+fn read_pointer_input(
+    (mouse, touches): (Res<ButtonInput<MouseButton>>, Res<Touches>),
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    (skin, layout): (Res<Skin>, Res<KeyLayout>),
+    (pause, rate): (Res<PauseState>, Res<PlaybackRate>),
+    mut lane_inputs: MessageWriter<LaneInputMessage>,
+    mut held: Local<HashMap<InputSource, usize>>,
+) {
+    let (Ok(window), Ok((camera, camera_tf))) = (q_window.single(), q_camera.single()) else {
+        return;
+    };
+    // 窗口坐标所在的轨道
+    let lane_at_cursor = |position: Vec2| {
+        let world = camera.viewport_to_world_2d(camera_tf, position).ok()?;
+        lane_at(&skin.field, *layout, world.x)
+    };
+
+    let mut presses: Vec<(InputSource, usize)> = Vec::new();
+    let mut releases: Vec<InputSource> = Vec::new();
+    if mouse.just_pressed(MouseButton::Left)
+        && let Some(lane) = window.cursor_position().and_then(lane_at_cursor)
+    {
+        presses.push((InputSource::Mouse, lane));
+    }
+    if mouse.just_released(MouseButton::Left) {
+        releases.push(InputSource::Mouse);
+    }
+    for touch in touches.iter_just_pressed() {
+        if let Some(lane) = lane_at_cursor(touch.position()) {
+            presses.push((InputSource::Touch(touch.id()), lane));
+        }
+    }
+    for touch in touches
+        .iter_just_released()
+        .chain(touches.iter_just_canceled())
+    {
+        releases.push(InputSource::Touch(touch.id()));
+    }
+
+    // 先处理按下再处理松开，同一帧内的轻触也会产生一次完整的按下与松开
+    let stamp = Some(current_game_time(&pause, &rate));
+    for (source, lane) in presses {
+        held.insert(source, lane);
+        lane_inputs.write(LaneInputMessage {
+            lane,
+            source,
+            pressed: true,
+            time: stamp,
+        });
+    }
+    for source in releases {
+        let Some(lane) = held.remove(&source) else {
+            continue;
+        };
+        lane_inputs.write(LaneInputMessage {
+            lane,
+            source,
+            pressed: false,
+            time: stamp,
+        });
+    }
+}
+
+/// 转盘停止转动后松开皿的延迟
+const SCRATCH_RELEASE_DELAY: Duration = Duration::from_millis(100);
+
+/// 转盘状态变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScratchEdge {
+    /// 开始转动或转向，转向时需先松开
+    Press {
+        /// 是否需要先松开
+        release_first: bool,
+    },
+    /// 停止转动
+    Release,
+}
+
+/// 转盘模拟轴状态机
+///
+/// 每次开始转动或转向视为一次皿按下，停止转动一段时间后松开
+#[derive(Debug, Default)]
+struct ScratchAxisState {
+    /// 上次记录的轴值
+    last_value: Option<f32>,
+    /// 当前转动方向（正向为 `true`），未转动时为 `None`
+    direction: Option<bool>,
+    /// 未转动的累计时长
+    idle: Duration,
+}
+
+impl ScratchAxisState {
+    /// 输入新的轴值，返回皿的状态变化
+    fn update(&mut self, value: f32, min_delta: f32, delta_time: Duration) -> Option<ScratchEdge> {
+        let Some(last) = self.last_value else {
+            self.last_value = Some(value);
+            return None;
+        };
+        let mut delta = value - last;
+        // 转盘越过轴的端点时数值会从一端跳到另一端
+        if delta.abs() > 1.0 {
+            delta -= 2.0 * delta.signum();
+        }
+
+        if delta.abs() < min_delta {
+            // 缓慢转动时累积变化量，不更新上次轴值
+            self.idle += delta_time;
+            if self.direction.is_some() && self.idle >= SCRATCH_RELEASE_DELAY {
+                self.direction = None;
+                return Some(ScratchEdge::Release);
+            }
+            return None;
+        }
+
+        self.last_value = Some(value);
+        self.idle = Duration::ZERO;
+        let forward = delta > 0.0;
+        let previous = self.direction.replace(forward);
+        (previous != Some(forward)).then_some(ScratchEdge::Press {
+            release_first: previous.is_some(),
+        })
+    }
+}
+
+/// 读取手柄输入并发送轨道输入消息
+///
+/// 按钮使用 `just_pressed`/`just_released`，按住不会重复触发；
+/// 皿的模拟轴每次开始转动或转向都发送一次按下
+fn read_gamepad_input(
+    time: Res<Time>,
+    q_gamepads: Query<(Entity, &Gamepad)>,
+    config: Res<SysConfig>,
+    (pause, rate): (Res<PauseState>, Res<PlaybackRate>),
+    mut lane_inputs: MessageWriter<LaneInputMessage>,
+    mut scratch_states: Local<HashMap<Entity, ScratchAxisState>>,
+) {
+    let stamp = Some(current_game_time(&pause, &rate));
+    let gamepad_config = &config.gamepad;
+    for (entity, gamepad) in &q_gamepads {
+        for (lane, button) in gamepad_config.lanes.iter().enumerate() {
+            let source = InputSource::GamepadButton(entity, *button);
+            if gamepad.just_pressed(*button) {
+                lane_inputs.write(LaneInputMessage {
+                    lane,
+                    source,
+                    pressed: true,
+                    time: stamp,
+                });
+            }
+            if gamepad.just_released(*button) {
+                lane_inputs.write(LaneInputMessage {
+                    lane,
+                    source,
+                    pressed: false,
+                    time: stamp,
+                });
+            }
+        }
+
+        let Some(axis) = gamepad_config.scratch_axis else {
+            continue;
+        };
+        let value = gamepad.get(axis).unwrap_or(0.0);
+        let edge = scratch_states.entry(entity).or_default().update(
+            value,
+            gamepad_config.scratch_min_delta,
+            time.delta(),
+        );
+        let Some(edge) = edge else {
+            continue;
+        };
+        if matches!(
+            edge,
+            ScratchEdge::Release
+                | ScratchEdge::Press {
+                    release_first: true
+                }
+        ) {
+            lane_inputs.write(LaneInputMessage {
+                lane: 0,
+                source: InputSource::ScratchAxis(entity),
+                pressed: false,
+                time: stamp,
+            });
+        }
+        if matches!(edge, ScratchEdge::Press { .. }) {
+            lane_inputs.write(LaneInputMessage {
+                lane: 0,
+                source: InputSource::ScratchAxis(entity),
+                pressed: true,
+                time: stamp,
+            });
+        }
+    }
+    // 移除已断开的手柄
+    scratch_states.retain(|entity, _| q_gamepads.contains(*entity));
+}
+
+/// 读取控制按键并发送游戏控制消息
+fn read_control_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut controls: MessageWriter<ControlMessage>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        controls.write(ControlMessage::TogglePause);
+    }
+    if keys.just_pressed(KeyCode::KeyR) {
+        controls.write(ControlMessage::Restart);
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        controls.write(ControlMessage::ToggleStats);
+    }
+    if keys.just_pressed(KeyCode::F10) {
+        controls.write(ControlMessage::ToggleLogConsole);
+    }
+    if keys.just_pressed(KeyCode::F11) {
+        controls.write(ControlMessage::ToggleFullscreen);
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        controls.write(ControlMessage::SetLoopStart);
+    }
+    if keys.just_pressed(KeyCode::BracketRight) {
+        controls.write(ControlMessage::SetLoopEnd);
+    }
+    if keys.just_pressed(KeyCode::Backslash) {
+        controls.write(ControlMessage::ClearLoop);
+    }
+}
@@ -0,0 +1,1015 @@
+//! 判定插件
+//!
+//! 负责按键判定、连击统计与血条结算
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{ecs::system::SystemParam, platform::collections::HashSet, prelude::*};
+use bms_rs::chart_process::prelude::*;
+use clap::ValueEnum;
+use gametime::{TimeSpan, TimeStamp};
+use serde::{Deserialize, Serialize};
+
+use crate::config::SysConfig;
+use crate::lane::{KeyLayout, LaneShuffle, layout_changed};
+use crate::plugins::audio_trigger::TriggeredNoteEvent;
+use crate::plugins::bms_processor::{BmsProcessorResource, BmsSystemSet, SectionLoopMessage};
+use crate::plugins::config_save::PendingConfigSave;
+use crate::plugins::input_handler::{ControlMessage, InputSource, LaneInputMessage};
+use crate::plugins::time_system::{PauseState, signed_secs};
+use crate::resources::{ExecArgs, NowStamp};
+use crate::schedule::LogicSchedule;
+use crate::state::AppState;
+
+/// 每次按键调整判定偏移的步长（毫秒）
+const OFFSET_STEP_MS: f64 = 1.0;
+/// 计算平均时间偏差使用的最近击中数
+const TIMING_SAMPLE_COUNT: usize = 50;
+/// 自动演奏时轨道保持按下的时长
+const AUTOPLAY_PRESS_DURATION: Duration = Duration::from_millis(80);
+
+/// 判定等级
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JudgeLevel {
+    /// 完美
+    Perfect,
+    /// 优秀
+    Great,
+    /// 良好
+    Good,
+    /// 差
+    Bad,
+    /// 漏击
+    Poor,
+}
+
+impl JudgeLevel {
+    /// 判定等级数量
+    pub const COUNT: usize = 5;
+
+    /// 全部判定等级，按从好到差排列
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::Perfect,
+        Self::Great,
+        Self::Good,
+        Self::Bad,
+        Self::Poor,
+    ];
+
+    /// 显示名称
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Perfect => "PERFECT",
+            Self::Great => "GREAT",
+            Self::Good => "GOOD",
+            Self::Bad => "BAD",
+            Self::Poor => "POOR",
+        }
+    }
+
+    /// 该判定是否维持连击
+    const fn keeps_combo(self) -> bool {
+        matches!(self, Self::Perfect | Self::Great | Self::Good)
+    }
+}
+
+/// 血条类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GaugeKind {
+    /// 普通血条，结束时达到通关线即通关
+    #[default]
+    Groove,
+    /// 困难血条，扣血更多，归零立即失败
+    Hard,
+    /// 简单血条，扣血较少
+    Easy,
+}
+
+impl GaugeKind {
+    /// 显示名称
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Groove => "GROOVE",
+            Self::Hard => "HARD",
+            Self::Easy => "EASY",
+        }
+    }
+
+    /// 默认的血条参数（GROOVE 与 EASY 从 20% 起步，HARD 从满血条开始扣减）
+    const fn default_profile(self) -> GaugeProfile {
+        let (initial, deltas) = match self {
+            Self::Groove => (0.2, [0.02, 0.02, 0.01, -0.03, -0.05]),
+            Self::Hard => (1.0, [0.01, 0.01, 0.005, -0.06, -0.1]),
+            Self::Easy => (0.2, [0.025, 0.025, 0.0125, -0.024, -0.04]),
+        };
+        GaugeProfile {
+            initial,
+            min: 0.0,
+            max: 1.0,
+            deltas,
+        }
+    }
+
+    /// 血条归零时是否立即失败
+    const fn fails_at_zero(self) -> bool {
+        matches!(self, Self::Hard)
+    }
+
+    /// 结束时的血条是否满足通关条件（HARD 血条未归零即通关）
+    #[must_use]
+    pub fn is_cleared(self, gauge: f32, clear: ClearGauge) -> bool {
+        match self {
+            Self::Groove => gauge >= clear.groove,
+            Self::Easy => gauge >= clear.easy,
+            Self::Hard => gauge > 0.0,
+        }
+    }
+}
+
+/// 血条参数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GaugeProfile {
+    /// 初始血条
+    pub initial: f32,
+    /// 血条下限
+    pub min: f32,
+    /// 血条上限
+    pub max: f32,
+    /// 各判定对血条的影响，按 PERFECT、GREAT、GOOD、BAD、POOR 顺序排列
+    pub deltas: [f32; JudgeLevel::COUNT],
+}
+
+impl GaugeProfile {
+    /// 按判定等级调整血条，结果限制在上下限之间
+    #[must_use]
+    pub fn apply(&self, gauge: f32, level: JudgeLevel) -> f32 {
+        let delta = self.deltas.get(level as usize).copied().unwrap_or(0.0);
+        // 不使用 clamp，上下限配置颠倒时不会 panic
+        (gauge + delta).max(self.min).min(self.max)
+    }
+}
+
+/// 各血条类型的血条参数
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GaugeProfiles {
+    /// 普通血条
+    pub groove: GaugeProfile,
+    /// 困难血条
+    pub hard: GaugeProfile,
+    /// 简单血条
+    pub easy: GaugeProfile,
+}
+
+impl GaugeProfiles {
+    /// 血条类型对应的参数
+    #[must_use]
+    pub const fn get(&self, kind: GaugeKind) -> GaugeProfile {
+        match kind {
+            GaugeKind::Groove => self.groove,
+            GaugeKind::Hard => self.hard,
+            GaugeKind::Easy => self.easy,
+        }
+    }
+}
+
+impl Default for GaugeProfiles {
+    fn default() -> Self {
+        Self {
+            groove: GaugeKind::Groove.default_profile(),
+            hard: GaugeKind::Hard.default_profile(),
+            easy: GaugeKind::Easy.default_profile(),
+        }
+    }
+}
+
+/// 各血条类型通关所需的最低血条
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClearGauge {
+    /// 普通血条
+    pub groove: f32,
+    /// 简单血条
+    pub easy: f32,
+}
+
+impl Default for ClearGauge {
+    fn default() -> Self {
+        Self {
+            groove: 0.8,
+            easy: 0.8,
+        }
+    }
+}
+
+/// 判定参数
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct JudgeParams {
+    /// PERFECT 判定窗口
+    pub perfect: Duration,
+    /// GREAT 判定窗口
+    pub great: Duration,
+    /// GOOD 判定窗口
+    pub good: Duration,
+    /// BAD 判定窗口
+    pub bad: Duration,
+    /// 判定时间偏移（秒），从时间偏差中扣除，只影响判定不影响显示
+    pub offset: f64,
+    /// 空按是否按 POOR 中断连击并扣减血条
+    pub empty_hit_penalty: bool,
+}
+
+impl Default for JudgeParams {
+    fn default() -> Self {
+        Self {
+            perfect: Duration::from_millis(20),
+            great: Duration::from_millis(50),
+            good: Duration::from_millis(100),
+            bad: Duration::from_millis(200),
+            offset: 0.0,
+            empty_hit_penalty: false,
+        }
+    }
+}
+
+impl JudgeParams {
+    /// 根据配置创建判定参数
+    #[must_use]
+    pub fn from_config(config: &SysConfig) -> Self {
+        Self {
+            offset: config.judge.offset_ms / 1000.0,
+            empty_hit_penalty: config.judge.empty_hit_penalty,
+            ..Default::default()
+        }
+    }
+
+    /// 根据时间偏差（秒）求判定等级，超出窗口时返回 `None`
+    #[must_use]
+    pub fn level_for(&self, dt: f64) -> Option<JudgeLevel> {
+        let dt = dt.abs();
+        if dt <= self.perfect.as_secs_f64() {
+            Some(JudgeLevel::Perfect)
+        } else if dt <= self.great.as_secs_f64() {
+            Some(JudgeLevel::Great)
+        } else if dt <= self.good.as_secs_f64() {
+            Some(JudgeLevel::Good)
+        } else if dt <= self.bad.as_secs_f64() {
+            Some(JudgeLevel::Bad)
+        } else {
+            None
+        }
+    }
+}
+
+/// 音符越过判定线消息
+///
+/// 当 BMS 处理器推进到可判定音符时发送此消息
+#[derive(Message, Clone, Copy, Debug)]
+pub struct NoteCrossedEvent {
+    /// 图表事件ID
+    pub event_id: ChartEventId,
+    /// 轨道索引
+    pub lane: usize,
+    /// 音频 ID
+    pub wav_id: Option<WavId>,
+    /// 按谱面时刻计算的越过判定线的游戏时刻
+    pub crossed_at: TimeStamp,
+}
+
+/// 早晚指示消息
+///
+/// 每次击中音符时发送，包括 PERFECT
+#[derive(Message, Clone, Copy, Debug)]
+pub struct FastSlowMessage {
+    /// 轨道索引
+    pub lane: usize,
+    /// 是否偏早
+    pub early: bool,
+    /// 偏差大小（毫秒）
+    pub magnitude_ms: f32,
+}
+
+/// 连击里程碑消息
+///
+/// 连击数达到或越过配置中的里程碑时发送一次
+#[derive(Message, Clone, Copy, Debug)]
+pub struct ComboMilestoneMessage {
+    /// 达到的里程碑连击数
+    pub combo: u32,
+}
+
+/// 判定结果输出
+#[derive(SystemParam)]
+struct JudgeOutput<'w> {
+    /// 按键音触发
+    triggered: MessageWriter<'w, TriggeredNoteEvent>,
+    /// 早晚指示
+    fast_slow: MessageWriter<'w, FastSlowMessage>,
+}
+
+/// 尚未越过判定线、可被提前击中的音符
+#[derive(Debug, Clone, Copy)]
+pub struct UpcomingNote {
+    /// 图表事件ID
+    pub event_id: ChartEventId,
+    /// 轨道索引
+    pub lane: usize,
+    /// 音频 ID
+    pub wav_id: Option<WavId>,
+    /// 按谱面时刻计算的越过判定线的游戏时刻
+    pub at: TimeStamp,
+}
+
+/// 击中音符的判定结果
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    /// 时间偏差（秒，正值为偏晚）
+    pub dt: f64,
+    /// 音频 ID
+    pub wav_id: Option<WavId>,
+}
+
+/// 已越过判定线但尚未判定的音符
+#[derive(Debug, Clone, Copy)]
+struct PassedNote {
+    event_id: ChartEventId,
+    lane: usize,
+    wav_id: Option<WavId>,
+    crossed_at: TimeStamp,
+}
+
+/// 单条轨道的判定统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LaneStats {
+    /// 各判定等级计数，按 [`JudgeLevel`] 顺序排列
+    pub judge_counts: [u32; JudgeLevel::COUNT],
+    /// 击中时间偏差之和（秒）
+    timing_sum: f64,
+    /// 击中次数（不含漏判）
+    pub hits: u32,
+}
+
+impl LaneStats {
+    /// 平均时间偏差（毫秒，正值为偏晚），没有击中时返回 `None`
+    #[must_use]
+    pub fn average_timing_ms(&self) -> Option<f64> {
+        (self.hits > 0).then(|| self.timing_sum / f64::from(self.hits) * 1000.0)
+    }
+}
+
+/// 游戏状态
+#[derive(Resource, Debug)]
+pub struct GameState {
+    /// 各轨道按下状态（任一来源按住即为按下）
+    pub pressed: Vec<bool>,
+    /// 各轨道正被按住的输入来源
+    held: Vec<HashSet<InputSource>>,
+    /// 当前连击数
+    pub combo: u32,
+    /// 最大连击数
+    pub max_combo: u32,
+    /// 血条类型
+    pub gauge_kind: GaugeKind,
+    /// 血条参数
+    pub gauge_profile: GaugeProfile,
+    /// 血条（0.0 ~ 1.0）
+    pub gauge: f32,
+    /// 血条降到此值以下时立即失败（`None` 时只在结束时判断是否通关）
+    pub fail_gauge: Option<f32>,
+    /// 是否已因血条归零而失败
+    pub failed: bool,
+    /// 谱面结束时是否通关（结束前为 `false`）
+    pub cleared: bool,
+    /// 各判定等级计数，按 [`JudgeLevel`] 顺序排列
+    pub judge_counts: [u32; JudgeLevel::COUNT],
+    /// 偏早击中数
+    pub fast_count: u32,
+    /// 偏晚击中数
+    pub slow_count: u32,
+    /// 各轨道的判定统计
+    pub lane_stats: Vec<LaneStats>,
+    /// 连击中断处的音符序号（按判定顺序，从 0 开始）
+    pub combo_breaks: Vec<u32>,
+    /// 最近击中的时间偏差（秒，正值为偏晚）
+    timing_samples: VecDeque<f64>,
+    /// 提前击中、尚未越过判定线的音符
+    judged: HashSet<ChartEventId>,
+    /// 已越过判定线但尚未判定的音符
+    passed: VecDeque<PassedNote>,
+    /// 自动演奏模式下各轨道的松开时刻
+    auto_release: Vec<Option<TimeStamp>>,
+}
+
+impl FromWorld for GameState {
+    fn from_world(world: &mut World) -> Self {
+        let layout = world
+            .get_resource::<KeyLayout>()
+            .copied()
+            .unwrap_or_default();
+        // 命令行参数优先于配置
+        let gauge_kind = world
+            .get_resource::<ExecArgs>()
+            .and_then(|args| args.gauge)
+            .or_else(|| {
+                world
+                    .get_resource::<SysConfig>()
+                    .map(|config| config.judge.gauge)
+            })
+            .unwrap_or_default();
+        let config = world.get_resource::<SysConfig>();
+        let gauge_profile = config.map_or_else(
+            || gauge_kind.default_profile(),
+            |config| config.judge.gauge_profiles.get(gauge_kind),
+        );
+        let fail_on_empty = config.and_then(|config| {
+            config
+                .judge
+                .fail_on_empty
+                .then_some(config.judge.fail_gauge)
+        });
+        let fail_gauge = fail_on_empty.or_else(|| gauge_kind.fails_at_zero().then_some(0.0));
+        Self::new(layout.lane_count(), gauge_kind, gauge_profile, fail_gauge)
+    }
+}
+
+impl GameState {
+    /// 创建指定轨道数量、血条类型、血条参数和失败阈值的初始游戏状态
+    #[must_use]
+    pub fn new(
+        lane_count: usize,
+        gauge_kind: GaugeKind,
+        gauge_profile: GaugeProfile,
+        fail_gauge: Option<f32>,
+    ) -> Self {
+        Self {
+            pressed: vec![false; lane_count],
+            held: vec![HashSet::new(); lane_count],
+            combo: 0,
+            max_combo: 0,
+            gauge_kind,
+            gauge_profile,
+            gauge: gauge_profile.initial,
+            fail_gauge,
+            failed: false,
+            cleared: false,
+            judge_counts: [0; JudgeLevel::COUNT],
+            fast_count: 0,
+            slow_count: 0,
+            lane_stats: vec![LaneStats::default(); lane_count],
+            combo_breaks: Vec::new(),
+            timing_samples: VecDeque::new(),
+            judged: HashSet::new(),
+            passed: VecDeque::new(),
+            auto_release: vec![None; lane_count],
+        }
+    }
+
+    /// 记录输入来源在轨道上的按下或松开，返回是否为该来源新的按下
+    ///
+    /// 来源已按住时再次按下（如按键重复）返回 `false`；轨道在所有来源都松开后才算松开
+    fn press(&mut self, lane: usize, source: InputSource, pressed: bool) -> bool {
+        let Some(held) = self.held.get_mut(lane) else {
+            return false;
+        };
+        let changed = if pressed {
+            held.insert(source)
+        } else {
+            held.remove(&source)
+        };
+        if let Some(lane_pressed) = self.pressed.get_mut(lane) {
+            *lane_pressed = !held.is_empty();
+        }
+        pressed && changed
+    }
+
+    /// 音符越过判定线：已提前击中的音符直接移除，否则加入待判定队列
+    pub fn queue_crossed(&mut self, note: &NoteCrossedEvent) {
+        if self.judged.remove(&note.event_id) {
+            return;
+        }
+        self.passed.push_back(PassedNote {
+            event_id: note.event_id,
+            lane: note.lane,
+            wav_id: note.wav_id,
+            crossed_at: note.crossed_at,
+        });
+    }
+
+    /// 判定一次轨道输入，击中音符时返回判定结果
+    ///
+    /// 只判定输入来源新的按下：在已越过判定线的音符与 `upcoming` 中选取该轨道上时间偏差最小的音符，
+    /// 超出判定窗口时按空按处理。偏差由输入与音符各自的时刻得出，与帧间隔和 `#SCROLL` 无关
+    pub fn judge_input(
+        &mut self,
+        input: &LaneInputMessage,
+        now: TimeStamp,
+        upcoming: &[UpcomingNote],
+        params: &JudgeParams,
+    ) -> Option<Hit> {
+        if !self.press(input.lane, input.source, input.pressed) {
+            return None;
+        }
+        let input_time = input.time.unwrap_or(now);
+
+        // 候选音符: (时间偏差, 事件ID, 音频ID, 待判定队列下标)
+        let mut best: Option<(f64, ChartEventId, Option<WavId>, Option<usize>)> = None;
+
+        // 已越过判定线的音符（迟）
+        for (idx, note) in self.passed.iter().enumerate() {
+            if note.lane != input.lane {
+                continue;
+            }
+            let dt = signed_secs(input_time, note.crossed_at) - params.offset;
+            if best.is_none_or(|(b, ..)| dt.abs() < b.abs()) {
+                best = Some((dt, note.event_id, note.wav_id, Some(idx)));
+            }
+        }
+
+        // 尚未越过判定线的音符（早）
+        for note in upcoming {
+            if note.lane != input.lane || self.judged.contains(&note.event_id) {
+                continue;
+            }
+            let dt = signed_secs(input_time, note.at) - params.offset;
+            if best.is_none_or(|(b, ..)| dt.abs() < b.abs()) {
+                best = Some((dt, note.event_id, note.wav_id, None));
+            }
+        }
+
+        // 判定窗口内没有音符时为空按
+        let Some((dt, event_id, wav_id, passed_idx, level)) =
+            best.and_then(|(dt, id, wav, idx)| {
+                params.level_for(dt).map(|level| (dt, id, wav, idx, level))
+            })
+        else {
+            if params.empty_hit_penalty {
+                self.apply_empty_hit();
+            }
+            return None;
+        };
+
+        match passed_idx {
+            Some(idx) => {
+                self.passed.remove(idx);
+            }
+            None => {
+                self.judged.insert(event_id);
+            }
+        }
+        self.apply_judgment(input.lane, level);
+        self.record_timing(input.lane, dt);
+        Some(Hit { dt, wav_id })
+    }
+
+    /// 将超出判定窗口仍未击中的音符结算为 POOR
+    pub fn sweep_missed(&mut self, now: TimeStamp, params: &JudgeParams) {
+        let bad = params.bad.as_secs_f64();
+        while let Some(note) = self.passed.front() {
+            if signed_secs(now, note.crossed_at) - params.offset <= bad {
+                break;
+            }
+            let lane = note.lane;
+            self.passed.pop_front();
+            self.apply_judgment(lane, JudgeLevel::Poor);
+        }
+    }
+
+    /// 音符是否已被判定（用于渲染时隐藏）
+    #[must_use]
+    pub fn is_judged(&self, event_id: ChartEventId) -> bool {
+        self.judged.contains(&event_id)
+    }
+
+    /// 是否还有越过判定线但尚未结算的音符
+    #[must_use]
+    pub fn has_pending_notes(&self) -> bool {
+        !self.passed.is_empty()
+    }
+
+    /// 最近击中的平均时间偏差（毫秒，正值为偏晚），没有击中时返回 `None`
+    #[must_use]
+    pub fn average_timing_ms(&self) -> Option<f64> {
+        if self.timing_samples.is_empty() {
+            return None;
+        }
+        let sum: f64 = self.timing_samples.iter().sum();
+        Some(sum / self.timing_samples.len() as f64 * 1000.0)
+    }
+
+    /// 记录一次击中的时间偏差
+    fn record_timing(&mut self, lane: usize, dt: f64) {
+        if let Some(stats) = self.lane_stats.get_mut(lane) {
+            stats.timing_sum += dt;
+            stats.hits += 1;
+        }
+        if dt < 0.0 {
+            self.fast_count += 1;
+        } else if dt > 0.0 {
+            self.slow_count += 1;
+        }
+        self.timing_samples.push_back(dt);
+        if self.timing_samples.len() > TIMING_SAMPLE_COUNT {
+            self.timing_samples.pop_front();
+        }
+    }
+
+    /// 结算一次判定
+    fn apply_judgment(&mut self, lane: usize, level: JudgeLevel) {
+        if let Some(count) = self.judge_counts.get_mut(level as usize) {
+            *count += 1;
+        }
+        if let Some(count) = self
+            .lane_stats
+            .get_mut(lane)
+            .and_then(|stats| stats.judge_counts.get_mut(level as usize))
+        {
+            *count += 1;
+        }
+        if level.keeps_combo() {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        } else {
+            self.break_combo();
+        }
+        self.apply_gauge(level);
+    }
+
+    /// 结算一次空按：按 POOR 中断连击并扣减血条，不计入判定分布
+    fn apply_empty_hit(&mut self) {
+        self.break_combo();
+        self.apply_gauge(JudgeLevel::Poor);
+    }
+
+    /// 中断连击，并记录中断处（最近判定的音符序号）
+    fn break_combo(&mut self) {
+        if self.combo > 0 {
+            let judged: u32 = self.judge_counts.iter().sum();
+            self.combo_breaks.push(judged.saturating_sub(1));
+        }
+        self.combo = 0;
+    }
+
+    /// 按判定等级增减血条，降到失败阈值时标记失败
+    fn apply_gauge(&mut self, level: JudgeLevel) {
+        // 失败后血条保持不变，直到进入结算画面
+        if self.failed {
+            return;
+        }
+        self.gauge = self.gauge_profile.apply(self.gauge, level);
+        if self.fail_gauge.is_some_and(|fail| self.gauge <= fail) {
+            self.failed = true;
+        }
+    }
+}
+
+/// 判定插件
+pub struct JudgePlugin;
+
+impl Plugin for JudgePlugin {
+    fn build(&self, app: &mut App) {
+        let params = app
+            .world()
+            .get_resource::<SysConfig>()
+            .map_or_else(JudgeParams::default, JudgeParams::from_config);
+        app.insert_resource(params)
+            .init_resource::<GameState>()
+            .add_message::<NoteCrossedEvent>()
+            .add_message::<FastSlowMessage>()
+            .add_message::<ComboMilestoneMessage>()
+            .add_systems(Update, adjust_judge_offset)
+            .add_systems(OnEnter(AppState::SongSelect), reset_game_state)
+            // 切换键位布局后轨道数量随之改变
+            .add_systems(Update, reset_game_state.run_if(layout_changed))
+            .add_systems(
+                LogicSchedule,
+                (
+                    reset_on_restart,
+                    handle_crossed_notes,
+                    handle_lane_input.run_if(accepts_player_input),
+                    sweep_missed_notes,
+                    release_autoplay_lanes,
+                    detect_combo_milestones,
+                )
+                    .chain()
+                    .after(BmsSystemSet::EventProcess),
+            );
+    }
+}
+
+/// 连击数越过里程碑时发送消息，每次越过只发送一次
+fn detect_combo_milestones(
+    state: Res<GameState>,
+    config: Res<SysConfig>,
+    mut last_combo: Local<u32>,
+    mut milestones: MessageWriter<ComboMilestoneMessage>,
+) {
+    let combo = state.combo;
+    let previous = std::mem::replace(&mut *last_combo, combo);
+    if combo <= previous {
+        return;
+    }
+    if let Some(&reached) = config
+        .judge
+        .combo_milestones
+        .iter()
+        .filter(|&&m| previous < m && m <= combo)
+        .max()
+    {
+        milestones.write(ComboMilestoneMessage { combo: reached });
+    }
+}
+
+/// 是否接受玩家输入（自动演奏模式、暂停期间和结算画面忽略）
+pub fn accepts_player_input(
+    args: Res<ExecArgs>,
+    pause: Res<PauseState>,
+    app_state: Res<State<AppState>>,
+) -> bool {
+    !args.autoplay && !pause.is_paused() && *app_state.get() == AppState::Playing
+}
+
+/// 收到重新开始消息或区间循环回到起点时重置游戏状态
+fn reset_on_restart(
+    mut controls: MessageReader<ControlMessage>,
+    mut loops: MessageReader<SectionLoopMessage>,
+    mut state: ResMut<GameState>,
+    layout: Res<KeyLayout>,
+) {
+    let looped = loops.read().count() > 0;
+    if ControlMessage::received(&mut controls, ControlMessage::Restart) || looped {
+        *state = GameState::new(
+            layout.lane_count(),
+            state.gauge_kind,
+            state.gauge_profile,
+            state.fail_gauge,
+        );
+    }
+}
+
+/// 通过按键调整判定偏移并保存到配置（-: 减小，=: 增大），连续调整时停止后才写入文件
+fn adjust_judge_offset(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut params: ResMut<JudgeParams>,
+    mut config: ResMut<SysConfig>,
+    (time, mut pending): (Res<Time<Real>>, ResMut<PendingConfigSave>),
+) {
+    let mut offset_ms = config.judge.offset_ms;
+    if keys.just_pressed(KeyCode::Minus) {
+        offset_ms -= OFFSET_STEP_MS;
+    }
+    if keys.just_pressed(KeyCode::Equal) {
+        offset_ms += OFFSET_STEP_MS;
+    }
+    if (offset_ms - config.judge.offset_ms).abs() < f64::EPSILON {
+        return;
+    }
+
+    config.judge.offset_ms = offset_ms;
+    params.offset = offset_ms / 1000.0;
+    println!("判定偏移: {offset_ms:+.0}ms");
+    pending.request(time.elapsed());
+}
+
+/// 返回选曲时重置游戏状态
+fn reset_game_state(mut state: ResMut<GameState>, layout: Res<KeyLayout>) {
+    *state = GameState::new(
+        layout.lane_count(),
+        state.gauge_kind,
+        state.gauge_profile,
+        state.fail_gauge,
+    );
+}
+
+/// 处理越过判定线的音符
+///
+/// 自动演奏模式下直接以 PERFECT 结算并播放按键音，否则加入待判定队列
+fn handle_crossed_notes(
+    mut crossed: MessageReader<NoteCrossedEvent>,
+    mut state: ResMut<GameState>,
+    mut triggered_events: MessageWriter<TriggeredNoteEvent>,
+    args: Res<ExecArgs>,
+    now_stamp: Res<NowStamp>,
+) {
+    for note in crossed.read() {
+        if !args.autoplay {
+            state.queue_crossed(note);
+            continue;
+        }
+
+        state.apply_judgment(note.lane, JudgeLevel::Perfect);
+        if let Some(pressed) = state.pressed.get_mut(note.lane) {
+            *pressed = true;
+        }
+        if let Some(release) = state.auto_release.get_mut(note.lane) {
+            *release = Some(now_stamp.0 + TimeSpan::from_duration(AUTOPLAY_PRESS_DURATION));
+        }
+        if let Some(wav_id) = note.wav_id {
+            triggered_events.write(TriggeredNoteEvent {
+                wav_id,
+                is_bgm: false,
+            });
+        }
+    }
+}
+
+/// 处理轨道按键并进行判定
+fn handle_lane_input(
+    mut inputs: MessageReader<LaneInputMessage>,
+    status: Option<ResMut<BmsProcessorResource>>,
+    mut state: ResMut<GameState>,
+    params: Res<JudgeParams>,
+    mut output: JudgeOutput,
+    now_stamp: Res<NowStamp>,
+    lanes: Res<LaneShuffle>,
+) {
+    let Some(mut status) = status else {
+        inputs.clear();
+        return;
+    };
+    if inputs.is_empty() {
+        return;
+    }
+
+    let upcoming = upcoming_notes(&mut status, &lanes);
+    for input in inputs.read() {
+        // 开始播放前只记录按下状态
+        if !status.started {
+            state.press(input.lane, input.source, input.pressed);
+            continue;
+        }
+        let Some(hit) = state.judge_input(input, now_stamp.0, &upcoming, &params) else {
+            continue;
+        };
+        output.fast_slow.write(FastSlowMessage {
+            lane: input.lane,
+            early: hit.dt < 0.0,
+            magnitude_ms: (hit.dt.abs() * 1000.0) as f32,
+        });
+
+        if let Some(wav_id) = hit.wav_id {
+            output.triggered.write(TriggeredNoteEvent {
+                wav_id,
+                is_bgm: false,
+            });
+        }
+    }
+}
+
+/// 可见范围内尚未越过判定线的音符
+fn upcoming_notes(status: &mut BmsProcessorResource, lanes: &LaneShuffle) -> Vec<UpcomingNote> {
+    status
+        .visible_notes()
+        .into_iter()
+        .filter_map(|(playhead_event, at)| {
+            let ChartEvent::Note {
+                side, key, wav_id, ..
+            } = playhead_event.event()
+            else {
+                return None;
+            };
+            let event_id = playhead_event.id();
+            Some(UpcomingNote {
+                event_id,
+                lane: lanes.note_lane(*side, *key, event_id)?,
+                wav_id: *wav_id,
+                at,
+            })
+        })
+        .collect()
+}
+
+/// 将超出判定窗口仍未击中的音符结算为 POOR
+fn sweep_missed_notes(
+    mut state: ResMut<GameState>,
+    params: Res<JudgeParams>,
+    now_stamp: Res<NowStamp>,
+) {
+    state.sweep_missed(now_stamp.0, &params);
+}
+
+/// 自动演奏模式下到时松开轨道
+fn release_autoplay_lanes(mut state: ResMut<GameState>, now_stamp: Res<NowStamp>) {
+    let state = &mut *state;
+    for (pressed, release) in state.pressed.iter_mut().zip(state.auto_release.iter_mut()) {
+        if release.is_some_and(|at| now_stamp.0 >= at) {
+            *pressed = false;
+            *release = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8 轨道、默认血条的初始游戏状态
+    fn game_state() -> GameState {
+        GameState::new(
+            8,
+            GaugeKind::Groove,
+            GaugeKind::Groove.default_profile(),
+            None,
+        )
+    }
+
+    #[test]
+    fn level_for_includes_each_window_boundary() {
+        let params = JudgeParams::default();
+        let windows = [
+            (params.perfect, JudgeLevel::Perfect),
+            (params.great, JudgeLevel::Great),
+            (params.good, JudgeLevel::Good),
+            (params.bad, JudgeLevel::Bad),
+        ];
+        // 窗口边界属于该窗口，稍微超出即落入下一档
+        let beyond = [
+            Some(JudgeLevel::Great),
+            Some(JudgeLevel::Good),
+            Some(JudgeLevel::Bad),
+            None,
+        ];
+        for ((window, level), next) in windows.into_iter().zip(beyond) {
+            let edge = window.as_secs_f64();
+            assert_eq!(params.level_for(edge), Some(level));
+            assert_eq!(params.level_for(-edge), Some(level));
+            assert_eq!(params.level_for(edge + 1e-6), next);
+            assert_eq!(params.level_for(-edge - 1e-6), next);
+        }
+        assert_eq!(params.level_for(0.0), Some(JudgeLevel::Perfect));
+    }
+
+    #[test]
+    fn gauge_stays_within_bounds_for_every_profile() {
+        let profiles = GaugeProfiles::default();
+        for kind in [GaugeKind::Groove, GaugeKind::Hard, GaugeKind::Easy] {
+            let profile = profiles.get(kind);
+            // 连续 PERFECT 直至封顶、连续 POOR 直至归零，再交替各判定
+            let runs = JudgeLevel::ALL
+                .iter()
+                .flat_map(|&level| std::iter::repeat_n(level, 200))
+                .chain(
+                    JudgeLevel::ALL
+                        .iter()
+                        .rev()
+                        .flat_map(|&level| std::iter::repeat_n(level, 200)),
+                )
+                .chain(JudgeLevel::ALL.iter().copied().cycle().take(1000));
+            let mut gauge = profile.initial;
+            for level in runs {
+                gauge = profile.apply(gauge, level);
+                assert!(
+                    (0.0..=1.0).contains(&gauge),
+                    "{} 血条在 {} 后为 {gauge}",
+                    kind.label(),
+                    level.label()
+                );
+            }
+            // 封顶与归零时停在上下限
+            assert!((profile.apply(1.0, JudgeLevel::Perfect) - 1.0).abs() <= f32::EPSILON);
+            assert!(profile.apply(0.0, JudgeLevel::Poor).abs() <= f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn key_repeat_is_not_a_new_press() {
+        let mut state = game_state();
+        let key = InputSource::Keyboard(KeyCode::KeyS);
+        assert!(state.press(1, key, true));
+        // 按住期间系统发送的重复按下
+        assert!(!state.press(1, key, true));
+        assert!(!state.press(1, key, true));
+        assert_eq!(state.pressed.get(1), Some(&true));
+
+        assert!(!state.press(1, key, false));
+        assert_eq!(state.pressed.get(1), Some(&false));
+        assert!(state.press(1, key, true));
+    }
+
+    #[test]
+    fn lane_stays_pressed_until_every_source_releases() {
+        let mut state = game_state();
+        let key = InputSource::Keyboard(KeyCode::KeyS);
+        let button = InputSource::Replay(1);
+        assert!(state.press(1, key, true));
+        assert!(state.press(1, button, true));
+
+        state.press(1, button, false);
+        assert_eq!(state.pressed.get(1), Some(&true));
+        // 另一来源松开后仍按住的按键重复不会再次判定
+        assert!(!state.press(1, key, true));
+
+        state.press(1, key, false);
+        assert_eq!(state.pressed.get(1), Some(&false));
+    }
+
+    #[test]
+    fn release_of_unknown_source_is_ignored() {
+        let mut state = game_state();
+        assert!(!state.press(1, InputSource::Mouse, false));
+        assert!(!state.press(99, InputSource::Mouse, true));
+        assert_eq!(state.pressed.get(1), Some(&false));
+    }
+}
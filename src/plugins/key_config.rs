@@ -0,0 +1,173 @@
+//! 键位设置插件
+//!
+//! 在选曲画面按 F8 进入，依次按下每条轨道的按键，完成后写回配置文件并立即生效。
+//! 设置的是当前键位布局（最近加载的谱面，PMS 谱面写入 `keys.lanes_pms`）的按键
+
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::config::{self, RESERVED_KEYS, SYS_CONFIG_PATH, SysConfig};
+use crate::lane::{KeyLayout, KeyMode};
+use crate::state::AppState;
+
+/// 键位设置进度
+#[derive(Resource, Default)]
+struct KeyConfigSession {
+    /// 已设置的按键（按轨道顺序）
+    bindings: Vec<KeyCode>,
+    /// 提示信息（如按键冲突）
+    notice: Option<String>,
+}
+
+/// 键位设置文本
+#[derive(Component)]
+struct KeyConfigText;
+
+/// 键位设置插件
+pub struct KeyConfigPlugin;
+
+impl Plugin for KeyConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeyConfigSession>()
+            .add_systems(
+                Update,
+                open_key_config.run_if(in_state(AppState::SongSelect)),
+            )
+            .add_systems(OnEnter(AppState::KeyConfig), spawn_key_config)
+            .add_systems(
+                Update,
+                (capture_key_binding, render_key_config)
+                    .chain()
+                    .run_if(in_state(AppState::KeyConfig)),
+            );
+    }
+}
+
+/// 选曲画面按 F8 进入键位设置
+fn open_key_config(keys: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<AppState>>) {
+    if keys.just_pressed(KeyCode::F8) {
+        next_state.set(AppState::KeyConfig);
+    }
+}
+
+/// 创建键位设置画面
+fn spawn_key_config(mut commands: Commands, mut session: ResMut<KeyConfigSession>) {
+    *session = KeyConfigSession::default();
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            padding: UiRect::all(Val::Px(24.0)),
+            ..Default::default()
+        },
+        BackgroundColor(Color::srgb(0.05, 0.05, 0.07)),
+        GlobalZIndex(10),
+        DespawnOnExit(AppState::KeyConfig),
+        children![(Text::new(""), KeyConfigText)],
+    ));
+}
+
+/// 轨道名称（双人模式下区分 1P/2P）
+fn lane_label(lane: usize, layout: KeyLayout) -> String {
+    let side = layout.side_lane_count();
+    let (player, idx) = if lane < side {
+        (1, lane)
+    } else {
+        (2, lane - side)
+    };
+    // 5K/7K 的皿位于 1P 最左、2P 最右，按键编号从 1 开始
+    let name = match (layout.mode, player) {
+        (KeyMode::Pms9K, _) => format!("KEY {}", idx + 1),
+        (_, 1) if idx == 0 => "SCRATCH".to_string(),
+        (_, 1) => format!("KEY {idx}"),
+        _ if idx == side - 1 => "SCRATCH".to_string(),
+        _ => format!("KEY {}", idx + 1),
+    };
+    if layout.double {
+        format!("{player}P {name}")
+    } else {
+        name
+    }
+}
+
+/// 记录按下的按键，冲突或占用时拒绝，全部设置后保存
+fn capture_key_binding(
+    keys: Res<ButtonInput<KeyCode>>,
+    layout: Res<KeyLayout>,
+    mut session: ResMut<KeyConfigSession>,
+    mut config: ResMut<SysConfig>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        println!("键位设置已取消");
+        next_state.set(AppState::SongSelect);
+        return;
+    }
+    let Some(key) = keys.get_just_pressed().next().copied() else {
+        return;
+    };
+
+    if RESERVED_KEYS.contains(&key) {
+        session.notice = Some(format!("{key:?} IS RESERVED, TRY ANOTHER KEY"));
+        return;
+    }
+    if let Some(lane) = session.bindings.iter().position(|k| *k == key) {
+        session.notice = Some(format!(
+            "{key:?} IS ALREADY BOUND TO {}, TRY ANOTHER KEY",
+            lane_label(lane, *layout)
+        ));
+        return;
+    }
+    // 只设置 P1 侧时，不能与保留的 P2 侧按键重复（双人模式下两侧同时生效）
+    if !layout.double && layout.mode != KeyMode::Pms9K && config.keys.lanes_2p.contains(&key) {
+        session.notice = Some(format!("{key:?} IS ALREADY BOUND TO 2P, TRY ANOTHER KEY"));
+        return;
+    }
+    session.notice = None;
+    session.bindings.push(key);
+    if session.bindings.len() < layout.lane_count() {
+        return;
+    }
+
+    let side = layout.side_lane_count();
+    *config.keys.lanes_for_mut(layout.mode) = session.bindings.iter().take(side).copied().collect();
+    if layout.double {
+        config.keys.lanes_2p = session.bindings.iter().skip(side).copied().collect();
+    }
+    match config::save_sys(Path::new(SYS_CONFIG_PATH), &config) {
+        Ok(()) => println!("✓ 键位已保存"),
+        Err(e) => eprintln!("{e:#}"),
+    }
+    next_state.set(AppState::SongSelect);
+}
+
+/// 更新键位设置文本
+fn render_key_config(
+    session: Res<KeyConfigSession>,
+    layout: Res<KeyLayout>,
+    mut q_text: Query<&mut Text, With<KeyConfigText>>,
+) {
+    if !session.is_changed() {
+        return;
+    }
+    let Ok(mut text) = q_text.single_mut() else {
+        return;
+    };
+
+    let mut lines = vec!["KEY CONFIG   ESC: CANCEL".to_string(), String::new()];
+    for lane in 0..layout.lane_count() {
+        let label = lane_label(lane, *layout);
+        let line = match session.bindings.get(lane) {
+            Some(key) => format!("  {label}: {key:?}"),
+            None if lane == session.bindings.len() => format!("> {label}: PRESS A KEY"),
+            None => format!("  {label}: -"),
+        };
+        lines.push(line);
+    }
+    if let Some(notice) = &session.notice {
+        lines.push(String::new());
+        lines.push(notice.clone());
+    }
+    text.0 = lines.join("\n");
+}
@@ -0,0 +1,133 @@
+//! 加载画面插件
+//!
+//! 进入演奏后到开始播放前显示加载进度：先解析谱面，再按已载入的音频数量推进进度条
+
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource as KiraAudioSource;
+
+use crate::plugins::bms_processor::{BmsLoadTask, BmsProcessorResource};
+use crate::state::AppState;
+
+/// 进度条宽度
+const BAR_WIDTH: f32 = 480.0;
+/// 进度条高度
+const BAR_HEIGHT: f32 = 12.0;
+/// 进度条颜色
+const BAR_COLOR: Color = Color::srgb(0.5, 0.6, 0.8);
+/// 进度条底色
+const TRACK_COLOR: Color = Color::srgb(0.15, 0.15, 0.2);
+
+/// 加载画面根节点
+#[derive(Component)]
+struct LoadingRoot;
+
+/// 加载进度文本
+#[derive(Component)]
+struct LoadingText;
+
+/// 加载进度条
+#[derive(Component)]
+struct LoadingBar;
+
+/// 加载画面插件
+pub struct LoadingScreenPlugin;
+
+impl Plugin for LoadingScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Playing), spawn_loading_screen)
+            .add_systems(
+                Update,
+                update_loading_screen.run_if(in_state(AppState::Playing)),
+            );
+    }
+}
+
+/// 创建加载画面
+fn spawn_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..Default::default()
+            },
+            BackgroundColor(Color::srgb(0.05, 0.05, 0.07)),
+            GlobalZIndex(15),
+            DespawnOnExit(AppState::Playing),
+            LoadingRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new("LOADING"), LoadingText));
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(BAR_WIDTH),
+                        height: Val::Px(BAR_HEIGHT),
+                        ..Default::default()
+                    },
+                    BackgroundColor(TRACK_COLOR),
+                ))
+                .with_children(|track| {
+                    track.spawn((
+                        Node {
+                            width: Val::Px(0.0),
+                            height: Val::Percent(100.0),
+                            ..Default::default()
+                        },
+                        BackgroundColor(BAR_COLOR),
+                        LoadingBar,
+                    ));
+                });
+        });
+}
+
+/// 按谱面解析与音频载入进度更新加载画面，开始播放后隐藏
+fn update_loading_screen(
+    status: Option<Res<BmsProcessorResource>>,
+    task: Option<Res<BmsLoadTask>>,
+    assets: Res<Assets<KiraAudioSource>>,
+    mut q_root: Query<&mut Visibility, With<LoadingRoot>>,
+    mut q_text: Query<&mut Text, With<LoadingText>>,
+    mut q_bar: Query<&mut Node, With<LoadingBar>>,
+) {
+    let Ok(mut vis) = q_root.single_mut() else {
+        return;
+    };
+    if status.as_ref().is_some_and(|status| status.started) {
+        if *vis != Visibility::Hidden {
+            *vis = Visibility::Hidden;
+        }
+        return;
+    }
+
+    let (label, progress) = match &status {
+        Some(status) => {
+            let total = status.audio_paths.len();
+            let loaded = status
+                .audio_handles
+                .values()
+                .filter(|handle| assets.get(*handle).is_some())
+                .count();
+            let progress = if total == 0 {
+                1.0
+            } else {
+                loaded as f32 / total as f32
+            };
+            (format!("LOADING AUDIO {loaded}/{total}"), progress)
+        }
+        None if task.is_some() => ("LOADING CHART".to_string(), 0.0),
+        None => ("NO CHART".to_string(), 0.0),
+    };
+    if let Ok(mut text) = q_text.single_mut()
+        && text.0 != label
+    {
+        text.0 = label;
+    }
+    if let Ok(mut node) = q_bar.single_mut() {
+        node.width = Val::Px(progress.min(1.0) * BAR_WIDTH);
+    }
+}
@@ -0,0 +1,213 @@
+//! 日志控制台插件
+//!
+//! 配置 `debug.log_console` 开启后，在 `tracing` 中追加一个日志层，将最近的日志保留在内存中，
+//! 按 F10 在画面底部显示，全屏时无需终端也能查看资源缺失、解码失败等警告。
+//! 只收集经 `tracing`（`warn!`、`error!` 等）输出的日志，直接写入终端的输出不会显示。
+//! 未开启时不注册日志层，也不创建控制台
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    log::{
+        BoxedLayer,
+        tracing::{
+            Event, Subscriber,
+            field::{Field, Visit},
+        },
+        tracing_subscriber::{Layer, layer::Context},
+    },
+    prelude::*,
+};
+
+use crate::config::SysConfig;
+use crate::plugins::input_handler::ControlMessage;
+
+/// 控制台同时显示的行数
+const VISIBLE_LINES: usize = 20;
+/// 控制台背景颜色
+const CONSOLE_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.75);
+
+/// 内存中的日志行
+#[derive(Default)]
+struct LogLines {
+    /// 最近的日志（旧的在前）
+    lines: VecDeque<String>,
+    /// 累计写入的行数，用于判断是否需要刷新显示
+    written: u64,
+}
+
+/// 最近日志的环形缓冲区（日志来自多个线程，以互斥锁保护）
+#[derive(Resource, Clone)]
+pub struct LogBuffer {
+    /// 日志行
+    inner: Arc<Mutex<LogLines>>,
+    /// 保留的最大行数
+    capacity: usize,
+}
+
+impl LogBuffer {
+    /// 创建保留指定行数的缓冲区
+    fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::default(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// 追加一行，超出容量时丢弃最旧的行
+    fn push(&self, line: String) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if inner.lines.len() >= self.capacity {
+            inner.lines.pop_front();
+        }
+        inner.lines.push_back(line);
+        inner.written += 1;
+    }
+
+    /// 累计写入行数与最近 `count` 行
+    fn tail(&self, count: usize) -> (u64, Vec<String>) {
+        let Ok(inner) = self.inner.lock() else {
+            return (0, Vec::new());
+        };
+        let skip = inner.lines.len().saturating_sub(count);
+        (
+            inner.written,
+            inner.lines.iter().skip(skip).cloned().collect(),
+        )
+    }
+}
+
+/// 将日志事件写入缓冲区的日志层
+struct BufferLayer(LogBuffer);
+
+impl<S: Subscriber> Layer<S> for BufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut line = format!("{} {}:", meta.level(), meta.target());
+        event.record(&mut LineVisitor(&mut line));
+        self.0.push(line);
+    }
+}
+
+/// 将事件字段拼接为一行文本（`message` 字段不带字段名）
+struct LineVisitor<'a>(&'a mut String);
+
+impl Visit for LineVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value}");
+        } else {
+            let _ = write!(self.0, " {}={value}", field.name());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// 供 `LogPlugin::custom_layer` 使用：开启日志控制台时创建缓冲区并返回日志层
+pub fn log_layer(app: &mut App) -> Option<BoxedLayer> {
+    let debug = &app.world().get_resource::<SysConfig>()?.debug;
+    if !debug.log_console {
+        return None;
+    }
+    let buffer = LogBuffer::new(debug.log_console_lines);
+    app.insert_resource(buffer.clone());
+    Some(Box::new(BufferLayer(buffer)))
+}
+
+/// 日志控制台文本
+#[derive(Component)]
+struct LogConsoleText;
+
+/// 日志控制台插件
+pub struct LogConsolePlugin;
+
+impl Plugin for LogConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            spawn_log_console.run_if(resource_exists::<LogBuffer>),
+        )
+        .add_systems(
+            Update,
+            (toggle_log_console, update_log_console)
+                .chain()
+                .run_if(resource_exists::<LogBuffer>),
+        );
+    }
+}
+
+/// 创建日志控制台（默认隐藏）
+fn spawn_log_console(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(0.0),
+            right: Val::Px(0.0),
+            bottom: Val::Px(0.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..Default::default()
+        },
+        BackgroundColor(CONSOLE_COLOR),
+        Text::new(""),
+        TextFont {
+            font_size: 14.0,
+            ..Default::default()
+        },
+        GlobalZIndex(30),
+        Visibility::Hidden,
+        LogConsoleText,
+    ));
+}
+
+/// 切换日志控制台显示
+fn toggle_log_console(
+    mut controls: MessageReader<ControlMessage>,
+    mut q_console: Query<&mut Visibility, With<LogConsoleText>>,
+) {
+    if !ControlMessage::received(&mut controls, ControlMessage::ToggleLogConsole) {
+        return;
+    }
+    for mut vis in &mut q_console {
+        *vis = match *vis {
+            Visibility::Hidden => Visibility::Visible,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+/// 控制台可见且有新日志时刷新文本
+fn update_log_console(
+    buffer: Res<LogBuffer>,
+    mut q_console: Query<(&mut Text, &Visibility), With<LogConsoleText>>,
+    mut shown: Local<u64>,
+) {
+    let Ok((mut text, vis)) = q_console.single_mut() else {
+        return;
+    };
+    if *vis == Visibility::Hidden {
+        return;
+    }
+    let (written, lines) = buffer.tail(VISIBLE_LINES);
+    if written == *shown && !text.0.is_empty() {
+        return;
+    }
+    *shown = written;
+    text.0 = if lines.is_empty() {
+        "(NO LOG)".to_string()
+    } else {
+        lines.join("\n")
+    };
+}
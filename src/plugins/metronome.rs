@@ -0,0 +1,148 @@
+//! 节拍器插件
+//!
+//! 使用 `--metronome` 时在演奏中按谱面 BPM 播放节拍音，小节线处的强拍使用更高的音调。
+//! 节拍音在独立通道播放，不占用按键音的发声数，也不受 BGM 音量影响
+
+use std::{path::Path, time::Duration};
+
+use bevy::{asset::AssetPath, prelude::*};
+use bevy_kira_audio::{AudioApp, AudioChannel, AudioControl, AudioSource as KiraAudioSource};
+use bms_rs::chart_process::prelude::*;
+use gametime::{TimeSpan, TimeStamp};
+use num_traits::ToPrimitive;
+
+use crate::config::SysConfig;
+use crate::plugins::bms_processor::BmsProcessorResource;
+use crate::resources::{ExecArgs, NowStamp};
+use crate::schedule::AudioSchedule;
+use crate::state::AppState;
+
+/// 强拍的播放速率（提高音调以区分弱拍）
+const DOWNBEAT_PLAYBACK_RATE: f64 = 1.5;
+
+/// 节拍器音频通道
+#[derive(Resource)]
+pub struct MetronomeChannel;
+
+/// 节拍器音效
+#[derive(Resource, Default)]
+struct MetronomeSound(Option<Handle<KiraAudioSource>>);
+
+/// 节拍计时状态
+#[derive(Default)]
+struct BeatClock {
+    /// 已处理的小节号
+    measure: usize,
+    /// 下一个弱拍的时刻（经过第一条小节线前为 `None`）
+    next_beat: Option<TimeStamp>,
+}
+
+/// 节拍器插件
+pub struct MetronomePlugin;
+
+impl Plugin for MetronomePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<MetronomeChannel>()
+            .init_resource::<MetronomeSound>()
+            .add_systems(Startup, load_metronome_sound.run_if(metronome_enabled))
+            .add_systems(
+                AudioSchedule,
+                play_beats.run_if(metronome_enabled.and(in_state(AppState::Playing))),
+            );
+    }
+}
+
+/// 是否启用节拍器
+fn metronome_enabled(args: Res<ExecArgs>) -> bool {
+    args.metronome
+}
+
+/// 加载节拍器音效并设置通道音量
+fn load_metronome_sound(
+    asset_server: Res<AssetServer>,
+    config: Res<SysConfig>,
+    mut sound: ResMut<MetronomeSound>,
+    channel: Res<AudioChannel<MetronomeChannel>>,
+) {
+    let path = &config.judge.metronome_sound;
+    if !Path::new(path).exists() {
+        warn!("节拍器音效不存在: {}", path.display());
+        return;
+    }
+    let asset_str = format!("fs://{}", path.to_string_lossy());
+    sound.0 = Some(asset_server.load_override(AssetPath::parse(&asset_str)));
+    channel.set_volume(crate::plugins::audio_manager::gain_to_decibels(
+        config.audio.metronome_volume.max(0.0),
+    ));
+}
+
+/// 按当前 BPM 播放节拍音
+///
+/// 每条小节线处播放强拍并重新对齐，小节内按当前 BPM 每拍播放一次弱拍，
+/// 因此变速与变拍子的小节都能保持对齐
+fn play_beats(
+    status: Option<ResMut<BmsProcessorResource>>,
+    now_stamp: Res<NowStamp>,
+    sound: Res<MetronomeSound>,
+    channel: Res<AudioChannel<MetronomeChannel>>,
+    mut clock: Local<BeatClock>,
+) {
+    let Some(mut status) = status else {
+        return;
+    };
+    // 未开始、已结束或重新开始时重置
+    if !status.started || status.finished || status.measure < clock.measure {
+        *clock = BeatClock {
+            measure: status.measure,
+            next_beat: None,
+        };
+        return;
+    }
+    let Some(bpm) = status
+        .processor
+        .current_bpm()
+        .to_f64()
+        .filter(|bpm| *bpm > 0.0)
+    else {
+        return;
+    };
+    let interval_secs = 60.0 / bpm;
+    let interval = TimeSpan::from_duration(Duration::from_secs_f64(interval_secs));
+    let now = now_stamp.0;
+
+    if status.measure > clock.measure {
+        clock.measure = status.measure;
+        clock.next_beat = Some(now + interval);
+        if let Some(handle) = &sound.0 {
+            channel
+                .play(handle.clone())
+                .with_playback_rate(DOWNBEAT_PLAYBACK_RATE);
+        }
+        return;
+    }
+
+    let Some(next_beat) = clock.next_beat.filter(|at| now >= *at) else {
+        return;
+    };
+    // 卡顿后不补播，直接对齐到下一拍
+    clock.next_beat = Some(if next_beat + interval > now {
+        next_beat + interval
+    } else {
+        now + interval
+    });
+
+    // 小节线即将到达时由强拍代替，避免连响两次
+    let travel_secs = status.travel_secs();
+    let bar_soon = status
+        .processor
+        .visible_events()
+        .filter(|(event, _)| matches!(event.event(), ChartEvent::BarLine))
+        .filter_map(|(_, range)| range.start().as_ref().to_f64())
+        .any(|ratio| ratio * travel_secs < interval_secs / 2.0);
+    if bar_soon {
+        return;
+    }
+    if let Some(handle) = &sound.0 {
+        channel.play(handle.clone());
+    }
+}
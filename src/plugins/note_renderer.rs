@@ -2,32 +2,73 @@
 //!
 //! 负责音符的可视化渲染和场景管理
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use bevy::prelude::*;
-use bms_rs::{bms::prelude::*, chart_process::prelude::*};
+use bevy::{asset::AssetPath, camera::ScalingMode, prelude::*};
+use bms_rs::chart_process::prelude::*;
 use num_traits::ToPrimitive;
 
-use crate::components::{NoteMarker, NoteState, PooledNote};
+use crate::components::{
+    BarLineMarker, ComboFlash, FastSlowIndicator, FieldAnchor, LaneBackground, LaneCover,
+    LaneFlash, NoteMarker, NoteScene, NoteState, PooledNote, TempoIndicator,
+};
+use crate::config::SysConfig;
+use crate::lane::{KeyLayout, LaneKind, LaneShuffle, layout_changed};
 use crate::plugins::bms_processor::BmsProcessorResource;
+use crate::plugins::config_save::PendingConfigSave;
+use crate::plugins::judge::{ComboMilestoneMessage, FastSlowMessage, GameState};
+use crate::plugins::time_system::TimeSystemSet;
+use crate::plugins::window_mode::{VIEW_HEIGHT, VIEW_WIDTH};
 use crate::resources::NowStamp;
+use crate::skin::{self, FieldSize, Skin};
+use crate::state::AppState;
 
-/// 轨道数量
-const LANE_COUNT: usize = 8;
-/// 轨道宽度
-const LANE_WIDTH: f32 = 60.0;
-/// 轨道间距
-const LANE_GAP: f32 = 8.0;
 /// 可见高度
 const VISIBLE_HEIGHT: f32 = 600.0;
-/// 音符高度
-const NOTE_HEIGHT: f32 = 12.0;
-/// 对象池初始大小
+/// 轨道两侧保留的最小边距（轨道总宽度超出设计宽度时使用）
+const FIELD_MARGIN: f32 = 80.0;
+/// 小节线高度
+const BAR_LINE_HEIGHT: f32 = 2.0;
+/// 对象池初始大小（同屏音符超出时按需扩容）
 const POOL_INITIAL_SIZE: usize = 500;
+/// 轨道遮挡颜色
+const LANE_COVER_COLOR: Color = Color::srgb(0.05, 0.05, 0.06);
+/// 按键闪光高度
+const LANE_FLASH_HEIGHT: f32 = 120.0;
+/// 按键闪光淡出时长（秒）
+const LANE_FLASH_DURATION: f32 = 0.1;
+/// 按键闪光的最大不透明度
+const LANE_FLASH_ALPHA: f32 = 0.6;
+/// 连击里程碑闪光淡出时长（秒）
+const COMBO_FLASH_DURATION: f32 = 0.25;
+/// 连击里程碑闪光的最大不透明度
+const COMBO_FLASH_ALPHA: f32 = 0.25;
+/// 早晚指示显示时长（秒）
+const FAST_SLOW_DURATION: f32 = 0.3;
+/// 早晚指示距判定线的高度
+const FAST_SLOW_OFFSET: f32 = 40.0;
+/// 偏早指示颜色
+const FAST_COLOR: Color = Color::srgb(0.3, 0.6, 1.0);
+/// 偏晚指示颜色
+const SLOW_COLOR: Color = Color::srgb(1.0, 0.35, 0.3);
+/// BPM 显示距可见区域顶端的高度
+const TEMPO_OFFSET: f32 = 16.0;
+/// BPM 与基准一致时的颜色
+const TEMPO_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
+/// BPM 高于基准时的颜色
+const TEMPO_FAST_COLOR: Color = Color::srgb(1.0, 0.55, 0.3);
+/// BPM 低于基准时的颜色
+const TEMPO_SLOW_COLOR: Color = Color::srgb(0.4, 0.7, 1.0);
+/// 轨道遮挡每次调整的步长
+const LANE_COVER_STEP: f32 = 0.05;
+/// 轨道遮挡的最大比例
+const LANE_COVER_MAX: f32 = 0.9;
 
 /// 音符池状态
 #[derive(Resource, Default)]
 pub struct NotePoolState {
+    /// 已创建的实体总数
+    size: usize,
     /// 可用的实体池
     available: Vec<Entity>,
     /// 活跃音符: `ChartEventId` -> Entity
@@ -36,11 +77,57 @@ pub struct NotePoolState {
     entity_to_event: HashMap<Entity, ChartEventId>,
 }
 
+impl NotePoolState {
+    /// 正在显示的音符数量
+    #[must_use]
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    /// 为新显示的音符分配实体，优先复用池中实体，对象池耗尽时调用 `spawn` 创建新实体扩容
+    ///
+    /// 返回实体及其是否为复用的实体（复用的实体需要更新组件）
+    fn acquire(
+        &mut self,
+        event_id: ChartEventId,
+        spawn: impl FnOnce() -> Entity,
+    ) -> (Entity, bool) {
+        let (entity, reused) = self.available.pop().map_or_else(
+            || {
+                self.size += 1;
+                (spawn(), false)
+            },
+            |entity| (entity, true),
+        );
+        self.active.insert(event_id, entity);
+        self.entity_to_event.insert(entity, event_id);
+        (entity, reused)
+    }
+
+    /// 回收不再显示的音符实体，返回被回收的事件与实体
+    fn release_except(&mut self, alive: &HashSet<ChartEventId>) -> Vec<(ChartEventId, Entity)> {
+        let released: Vec<(ChartEventId, Entity)> = self
+            .active
+            .iter()
+            .filter(|(id, _)| !alive.contains(id))
+            .map(|(&id, &entity)| (id, entity))
+            .collect();
+        for &(event_id, entity) in &released {
+            self.active.remove(&event_id);
+            self.entity_to_event.remove(&entity);
+            self.available.push(entity);
+        }
+        released
+    }
+}
+
 /// 图谱视觉状态
 #[derive(Resource, Default)]
 pub struct ChartVisualState {
     /// 音符事件ID到实体的映射（保留用于兼容）
     pub notes: HashMap<ChartEventId, Entity>,
+    /// 渲染时刻相对处理器状态的位移（像素），音符与小节线均按此下移
+    pub lead_y: f32,
 }
 
 /// 音符渲染插件
@@ -50,94 +137,343 @@ impl Plugin for NoteRendererPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<NotePoolState>()
             .init_resource::<ChartVisualState>()
-            .add_systems(Startup, (setup_note_scene, initialize_note_pool))
-            .add_systems(Update, render_visible_chart)
+            .add_systems(
+                Startup,
+                (setup_note_scene, spawn_field, initialize_note_pool),
+            )
+            .add_systems(
+                Update,
+                (despawn_field, spawn_field, update_camera_scaling)
+                    .chain()
+                    .run_if(skin_reloaded.or(layout_changed)),
+            )
+            .add_systems(OnEnter(AppState::SongSelect), clear_play_field)
+            .add_systems(
+                Update,
+                (update_render_lead, render_visible_chart, render_bar_lines)
+                    .chain()
+                    .after(TimeSystemSet::NowStamp),
+            )
+            .add_systems(Update, update_lane_highlight)
+            .add_systems(Update, update_lane_flash)
+            .add_systems(Update, update_combo_flash)
+            .add_systems(Update, update_fast_slow_indicator)
+            .add_systems(Update, update_tempo_indicator)
+            .add_systems(Update, (adjust_lane_cover, update_lane_cover).chain())
+            .add_systems(
+                Update,
+                (toggle_scroll_direction, update_field_anchors).chain(),
+            )
             .add_systems(Update, print_pool_stats);
     }
 }
 
+/// 轨道宽度（皿轨道使用 `scratch_width`）
+fn lane_width(field: &FieldSize, layout: KeyLayout, idx: usize) -> f32 {
+    if layout.lane_kind(idx) == LaneKind::Scratch {
+        field.scratch_width
+    } else {
+        field.lane_width
+    }
+}
+
 /// 计算总宽度
-fn total_width() -> f32 {
-    LANE_COUNT as f32 * LANE_WIDTH + (LANE_COUNT as f32 - 1.0) * LANE_GAP
+fn total_width(field: &FieldSize, layout: KeyLayout) -> f32 {
+    let lane_count = layout.lane_count();
+    (0..lane_count)
+        .map(|i| lane_width(field, layout, i))
+        .sum::<f32>()
+        + (lane_count as f32 - 1.0) * field.lane_gap
+}
+
+/// 计算轨道左边缘X坐标
+fn lane_left(field: &FieldSize, layout: KeyLayout, idx: usize) -> f32 {
+    let before: f32 = (0..idx)
+        .map(|i| lane_width(field, layout, i) + field.lane_gap)
+        .sum();
+    -total_width(field, layout) / 2.0 + before
 }
 
 /// 计算轨道X坐标
-fn lane_x(idx: usize) -> f32 {
-    let left = -total_width() / 2.0 + LANE_WIDTH / 2.0;
-    left + idx as f32 * (LANE_WIDTH + LANE_GAP)
-}
-
-/// 将Key转换为轨道索引
-const fn key_to_lane(key: Key) -> Option<usize> {
-    match key {
-        Key::Scratch(_) => Some(0),
-        Key::Key(n) => match n {
-            1..=7 => Some(n as usize),
-            _ => None,
-        },
-        _ => None,
+fn lane_x(field: &FieldSize, layout: KeyLayout, idx: usize) -> f32 {
+    lane_left(field, layout, idx) + lane_width(field, layout, idx) / 2.0
+}
+
+/// 世界坐标 X 所在的轨道（轨道间隙两侧各一半归入相邻轨道），在轨道区域外时返回 `None`
+#[must_use]
+pub fn lane_at(field: &FieldSize, layout: KeyLayout, x: f32) -> Option<usize> {
+    (0..layout.lane_count()).find(|&idx| {
+        let left = lane_left(field, layout, idx) - field.lane_gap / 2.0;
+        (left..left + lane_width(field, layout, idx) + field.lane_gap).contains(&x)
+    })
+}
+
+/// 将显示比例转换为Y坐标（0 为判定线，1 为可见区域顶端）
+fn ratio_to_y(ratio: f64) -> f32 {
+    -VISIBLE_HEIGHT / 2.0 + ratio as f32 * VISIBLE_HEIGHT
+}
+
+/// 按下落方向换算显示用的 Y 坐标（上升方向时上下镜像）
+const fn field_y(y: f32, scroll_up: bool) -> f32 {
+    if scroll_up { -y } else { y }
+}
+
+/// 根据当前时间戳计算渲染外推位移
+///
+/// 处理器每帧仅在逻辑阶段推进一次，渲染时按当前时间戳外推，避免高刷新率下的卡顿
+fn update_render_lead(
+    status: Option<Res<BmsProcessorResource>>,
+    now_stamp: Res<NowStamp>,
+    mut vis: ResMut<ChartVisualState>,
+) {
+    vis.lead_y = status.map_or(0.0, |s| s.ratio_lead(now_stamp.0) as f32 * VISIBLE_HEIGHT);
+}
+
+/// 相机缩放方式（按设计分辨率等比缩放，窗口尺寸变化时画面保持居中；
+/// 轨道总宽度超出设计宽度时缩小画面以完整显示）
+fn camera_scaling(field: &FieldSize, layout: KeyLayout) -> ScalingMode {
+    ScalingMode::AutoMin {
+        min_width: VIEW_WIDTH.max(total_width(field, layout) + FIELD_MARGIN * 2.0),
+        min_height: VIEW_HEIGHT,
     }
 }
 
-/// 设置音符场景
-fn setup_note_scene(mut commands: Commands) {
-    // 创建相机
-    commands.spawn((Camera2d, Transform::default(), GlobalTransform::default()));
+/// 设置音符场景相机
+fn setup_note_scene(mut commands: Commands, layout: Res<KeyLayout>, skin: Res<Skin>) {
+    commands.spawn((
+        Camera2d,
+        Projection::Orthographic(OrthographicProjection {
+            scaling_mode: camera_scaling(&skin.field, *layout),
+            ..OrthographicProjection::default_2d()
+        }),
+        Transform::default(),
+        GlobalTransform::default(),
+    ));
+}
+
+/// 按皮肤创建场地元素（背景、轨道、闪光、判定线、遮挡与指示）
+fn spawn_field(
+    mut commands: Commands,
+    layout: Res<KeyLayout>,
+    skin: Res<Skin>,
+    asset_server: Res<AssetServer>,
+) {
+    let lane_count = layout.lane_count();
+    let field = &skin.field;
+
+    // 皮肤背景（清屏颜色与轨道之后的背景图片）
+    commands.insert_resource(
+        skin.background_color
+            .map_or_else(ClearColor::default, |color| ClearColor(skin::srgb(color))),
+    );
+    if let Some(path) = &skin.background_image {
+        if path.exists() {
+            let asset_str = format!("fs://{}", path.to_string_lossy());
+            commands.spawn((
+                NoteScene,
+                Sprite {
+                    image: asset_server.load_override(AssetPath::parse(&asset_str)),
+                    custom_size: Some(Vec2::new(VIEW_WIDTH, VIEW_HEIGHT)),
+                    ..Default::default()
+                },
+                Transform::from_xyz(0.0, 0.0, -1.0),
+            ));
+        } else {
+            warn!("背景图片不存在: {}", path.display());
+        }
+    }
 
     // 创建轨道背景
-    for i in 0..LANE_COUNT {
+    for i in 0..lane_count {
         commands.spawn((
+            NoteScene,
             Sprite {
-                color: Color::srgb(0.15, 0.15, 0.18),
-                custom_size: Some(Vec2::new(LANE_WIDTH, VISIBLE_HEIGHT)),
+                color: skin.lane.color(*layout, i),
+                custom_size: Some(Vec2::new(lane_width(field, *layout, i), VISIBLE_HEIGHT)),
                 ..Default::default()
             },
-            Transform::from_xyz(lane_x(i), 0.0, 0.0),
+            Transform::from_xyz(lane_x(field, *layout, i), 0.0, 0.0),
             GlobalTransform::default(),
             Visibility::default(),
             InheritedVisibility::default(),
+            LaneBackground(i),
+        ));
+    }
+
+    // 创建按键闪光（位于判定线上方，默认隐藏）
+    for i in 0..lane_count {
+        commands.spawn((
+            NoteScene,
+            Sprite {
+                color: skin::srgb(skin.lane_flash).with_alpha(0.0),
+                custom_size: Some(Vec2::new(lane_width(field, *layout, i), LANE_FLASH_HEIGHT)),
+                ..Default::default()
+            },
+            Transform::from_xyz(
+                lane_x(field, *layout, i),
+                -VISIBLE_HEIGHT / 2.0 + LANE_FLASH_HEIGHT / 2.0,
+                0.5,
+            ),
+            GlobalTransform::default(),
+            Visibility::Hidden,
+            InheritedVisibility::default(),
+            LaneFlash {
+                lane: i,
+                remaining: 0.0,
+            },
+            FieldAnchor(-VISIBLE_HEIGHT / 2.0 + LANE_FLASH_HEIGHT / 2.0),
         ));
     }
 
+    // 创建连击里程碑闪光（覆盖整个轨道区域，默认隐藏）
+    commands.spawn((
+        NoteScene,
+        Sprite {
+            color: skin::srgb(skin.combo_flash).with_alpha(0.0),
+            custom_size: Some(Vec2::new(total_width(field, *layout), VISIBLE_HEIGHT)),
+            ..Default::default()
+        },
+        Transform::from_xyz(0.0, 0.0, 0.6),
+        GlobalTransform::default(),
+        Visibility::Hidden,
+        InheritedVisibility::default(),
+        ComboFlash { remaining: 0.0 },
+    ));
+
     // 创建判定线
     commands.spawn((
+        NoteScene,
         Sprite {
-            color: Color::srgb(0.9, 0.9, 0.9),
-            custom_size: Some(Vec2::new(total_width(), 4.0)),
+            color: skin::srgb(skin.judge_line),
+            custom_size: Some(Vec2::new(total_width(field, *layout), 4.0)),
             ..Default::default()
         },
         Transform::from_xyz(0.0, -VISIBLE_HEIGHT / 2.0 + 2.0, 1.0),
         GlobalTransform::default(),
         Visibility::default(),
         InheritedVisibility::default(),
+        FieldAnchor(-VISIBLE_HEIGHT / 2.0 + 2.0),
     ));
+
+    // 创建轨道遮挡（高度由配置决定）
+    commands.spawn((
+        NoteScene,
+        Sprite {
+            color: LANE_COVER_COLOR,
+            custom_size: Some(Vec2::new(total_width(field, *layout), 0.0)),
+            ..Default::default()
+        },
+        Transform::from_xyz(0.0, VISIBLE_HEIGHT / 2.0, 3.0),
+        GlobalTransform::default(),
+        Visibility::default(),
+        InheritedVisibility::default(),
+        LaneCover,
+    ));
+
+    // 创建早晚指示（默认隐藏）
+    commands.spawn((
+        NoteScene,
+        Text2d::new(""),
+        TextFont {
+            font_size: 20.0,
+            ..Default::default()
+        },
+        TextColor(FAST_COLOR),
+        Transform::from_xyz(0.0, -VISIBLE_HEIGHT / 2.0 + FAST_SLOW_OFFSET, 4.0),
+        Visibility::Hidden,
+        FastSlowIndicator { remaining: 0.0 },
+        FieldAnchor(-VISIBLE_HEIGHT / 2.0 + FAST_SLOW_OFFSET),
+    ));
+
+    // 创建 BPM 与小节号显示（位于轨道上方）
+    commands.spawn((
+        NoteScene,
+        Text2d::new(""),
+        TextFont {
+            font_size: 16.0,
+            ..Default::default()
+        },
+        TextColor(TEMPO_COLOR),
+        Transform::from_xyz(0.0, VISIBLE_HEIGHT / 2.0 + TEMPO_OFFSET, 4.0),
+        TempoIndicator,
+        FieldAnchor(VISIBLE_HEIGHT / 2.0 + TEMPO_OFFSET),
+    ));
+}
+
+/// 皮肤重新加载后（启动时的首次加载除外）
+fn skin_reloaded(skin: Res<Skin>) -> bool {
+    skin.is_changed() && !skin.is_added()
+}
+
+/// 移除场地元素，随后按新皮肤或键位布局重新创建
+fn despawn_field(mut commands: Commands, q_scene: Query<Entity, With<NoteScene>>) {
+    for entity in &q_scene {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// 按新皮肤或键位布局更新相机缩放
+fn update_camera_scaling(
+    layout: Res<KeyLayout>,
+    skin: Res<Skin>,
+    mut q_camera: Query<&mut Projection, With<Camera2d>>,
+) {
+    for mut projection in &mut q_camera {
+        if let Projection::Orthographic(ortho) = &mut *projection {
+            ortho.scaling_mode = camera_scaling(&skin.field, *layout);
+        }
+    }
+}
+
+/// 音符精灵尺寸
+fn note_size(field: &FieldSize, width: f32) -> Vec2 {
+    Vec2::new((width - 4.0).max(1.0), field.note_height)
+}
+
+/// 对象池中的音符实体（关联事件时可见）
+fn pooled_note(
+    size: Vec2,
+    color: Color,
+    translation: Vec3,
+    event_id: Option<ChartEventId>,
+) -> impl Bundle {
+    let (visibility, state) = if event_id.is_some() {
+        (Visibility::Visible, NoteState::Active)
+    } else {
+        (Visibility::Hidden, NoteState::Hidden)
+    };
+    (
+        Sprite {
+            color,
+            custom_size: Some(size),
+            ..Default::default()
+        },
+        Transform::from_translation(translation),
+        GlobalTransform::default(),
+        visibility,
+        InheritedVisibility::default(),
+        NoteMarker,
+        PooledNote { state, event_id },
+    )
 }
 
 /// 初始化音符对象池
-fn initialize_note_pool(mut commands: Commands, mut pool: ResMut<NotePoolState>) {
+fn initialize_note_pool(mut commands: Commands, mut pool: ResMut<NotePoolState>, skin: Res<Skin>) {
+    let size = note_size(&skin.field, skin.field.lane_width);
     println!("✓ 初始化音符对象池: {} 个实体", POOL_INITIAL_SIZE);
 
     for _ in 0..POOL_INITIAL_SIZE {
         let entity = commands
-            .spawn((
-                Sprite {
-                    color: Color::srgb(0.3, 0.7, 1.0),
-                    custom_size: Some(Vec2::new(LANE_WIDTH - 4.0, NOTE_HEIGHT)),
-                    ..Default::default()
-                },
-                Transform::from_xyz(0.0, 0.0, 2.0),
-                GlobalTransform::default(),
-                Visibility::Hidden,
-                InheritedVisibility::default(),
-                NoteMarker,
-                PooledNote {
-                    state: NoteState::Hidden,
-                    event_id: None,
-                },
+            .spawn(pooled_note(
+                size,
+                Color::srgb(0.3, 0.7, 1.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                None,
             ))
             .id();
         pool.available.push(entity);
     }
+    pool.size = POOL_INITIAL_SIZE;
 }
 
 /// 渲染可见音符（使用对象池）
@@ -145,8 +481,18 @@ fn render_visible_chart(
     status: Option<ResMut<BmsProcessorResource>>,
     mut pool: ResMut<NotePoolState>,
     mut vis: ResMut<ChartVisualState>,
-    mut q_notes: Query<(&mut Transform, &mut Visibility, &mut PooledNote), With<NoteMarker>>,
-    _now_stamp: Res<NowStamp>,
+    mut q_notes: Query<
+        (
+            &mut Sprite,
+            &mut Transform,
+            &mut Visibility,
+            &mut PooledNote,
+        ),
+        With<NoteMarker>,
+    >,
+    (mut commands, game_state): (Commands, Res<GameState>),
+    (config, skin): (Res<SysConfig>, Res<Skin>),
+    (layout, lanes): (Res<KeyLayout>, Res<LaneShuffle>),
 ) {
     let Some(mut status) = status else {
         return;
@@ -155,90 +501,397 @@ fn render_visible_chart(
         return;
     }
 
-    let mut alive: Vec<ChartEventId> = Vec::new();
+    // 遮挡线以上的音符不显示
+    let cover_line = VISIBLE_HEIGHT / 2.0 - config.display.lane_cover * VISIBLE_HEIGHT;
 
-    // 渲染可见音符
-    for ev in status.processor.visible_events() {
-        let (playhead_event, range) = ev;
+    let mut alive: HashSet<ChartEventId> = HashSet::new();
 
+    // 收集需要显示的音符：（事件ID，轨道索引，Y 坐标）
+    let mut visible: Vec<(ChartEventId, usize, f32)> = Vec::new();
+    for (playhead_event, ratio) in status.scrolled_visible_events() {
         // 只处理音符事件
         let ChartEvent::Note { side, key, .. } = playhead_event.event() else {
             continue;
         };
 
-        // 只处理P1侧
-        if *side != PlayerSide::Player1 {
+        let event_id = playhead_event.id();
+
+        // 获取变换后的轨道索引（未启用的P2侧音符返回 None）
+        let Some(idx) = lanes.note_lane(*side, *key, event_id) else {
+            continue;
+        };
+
+        // 已判定的音符不再显示
+        if game_state.is_judged(event_id) {
             continue;
         }
 
-        // 获取轨道索引
-        let Some(idx) = key_to_lane(*key) else {
+        let y = ratio_to_y(ratio) - vis.lead_y;
+        if y > cover_line {
             continue;
-        };
+        }
+        visible.push((event_id, idx, y));
+    }
 
-        let x = lane_x(idx);
-        let ratio_value = range.start().as_ref();
-        let y = -VISIBLE_HEIGHT / 2.0
-            + ToPrimitive::to_f64(ratio_value).unwrap_or(0.0) as f32 * VISIBLE_HEIGHT;
+    // 限制每条轨道的显示数量，保留离判定线最近（最靠下）的音符
+    let max_per_lane = config.display.max_visible_notes as usize;
+    if max_per_lane > 0 {
+        visible.sort_by(|a, b| a.2.total_cmp(&b.2));
+        let mut lane_counts = vec![0; layout.lane_count()];
+        visible.retain(|&(_, idx, _)| {
+            lane_counts.get_mut(idx).is_some_and(|count| {
+                *count += 1;
+                *count <= max_per_lane
+            })
+        });
+    }
 
-        let event_id = playhead_event.id();
+    // 渲染可见音符
+    for (event_id, idx, y) in visible {
+        let x = lane_x(&skin.field, *layout, idx);
+        let size = note_size(&skin.field, lane_width(&skin.field, *layout, idx));
+        // 从遮挡线（未遮挡时为可见区域顶端）向下淡入，遮挡线以上的音符已被剔除
+        let alpha = if skin.fade_in > 0.0 {
+            ((cover_line - y) / skin.fade_in).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let color = skin.note.color(*layout, idx).with_alpha(alpha);
 
         // 检查音符是否已经在活跃列表中
         if let Some(&entity) = pool.active.get(&event_id) {
             // 更新现有音符的位置和可见性
-            if let Ok((mut tf, mut v, mut note)) = q_notes.get_mut(entity) {
+            if let Ok((mut sprite, mut tf, mut v, mut note)) = q_notes.get_mut(entity) {
+                if skin.fade_in > 0.0 || skin.is_changed() {
+                    sprite.color = color;
+                }
+                if skin.is_changed() {
+                    sprite.custom_size = Some(size);
+                }
                 tf.translation.x = x;
-                tf.translation.y = y;
+                tf.translation.y = field_y(y, config.display.scroll_up);
                 *v = Visibility::Visible;
                 note.state = NoteState::Active;
             }
-            alive.push(event_id);
+            alive.insert(event_id);
             continue;
         }
 
-        // 从对象池中获取一个可用实体
-        if let Some(&entity) = pool.available.last() {
-            pool.available.pop();
+        // 从对象池中获取一个可用实体，对象池耗尽时创建新实体扩容，不丢弃音符
+        let y = field_y(y, config.display.scroll_up);
+        let (entity, reused) = pool.acquire(event_id, || {
+            commands
+                .spawn(pooled_note(
+                    size,
+                    color,
+                    Vec3::new(x, y, 2.0),
+                    Some(event_id),
+                ))
+                .id()
+        });
+        // 更新复用实体的组件
+        if reused && let Ok((mut sprite, mut tf, mut v, mut note)) = q_notes.get_mut(entity) {
+            sprite.color = color;
+            sprite.custom_size = Some(size);
+            tf.translation.x = x;
+            tf.translation.y = y;
+            *v = Visibility::Visible;
+            note.state = NoteState::Active;
+            note.event_id = Some(event_id);
+        }
+        vis.notes.insert(event_id, entity);
+        alive.insert(event_id);
+    }
 
-            // 更新实体组件
-            if let Ok((mut tf, mut v, mut note)) = q_notes.get_mut(entity) {
-                tf.translation.x = x;
+    // 回收过时音符到对象池并隐藏
+    for (event_id, entity) in pool.release_except(&alive) {
+        if let Ok((_, _, mut v, mut note)) = q_notes.get_mut(entity) {
+            *v = Visibility::Hidden;
+            note.state = NoteState::Hidden;
+            note.event_id = None;
+        }
+        vis.notes.remove(&event_id);
+    }
+}
+
+/// 渲染小节线
+///
+/// 小节线实体按需创建并复用，多余的实体隐藏
+fn render_bar_lines(
+    mut commands: Commands,
+    status: Option<ResMut<BmsProcessorResource>>,
+    mut q_lines: Query<(&mut Sprite, &mut Transform, &mut Visibility), With<BarLineMarker>>,
+    (config, skin): (Res<SysConfig>, Res<Skin>),
+    layout: Res<KeyLayout>,
+    vis: Res<ChartVisualState>,
+    mut lines: Local<Vec<Entity>>,
+) {
+    let Some(mut status) = status else {
+        return;
+    };
+    if !status.started {
+        return;
+    }
+
+    let cover_line = VISIBLE_HEIGHT / 2.0 - config.display.lane_cover * VISIBLE_HEIGHT;
+    let [r, g, b] = config.display.bar_line_color;
+    let color = Color::srgb(r, g, b);
+    let size = Vec2::new(total_width(&skin.field, *layout), BAR_LINE_HEIGHT);
+
+    let mut used = 0;
+    for (playhead_event, ratio) in status.scrolled_visible_events() {
+        if !matches!(playhead_event.event(), ChartEvent::BarLine) {
+            continue;
+        }
+        let y = ratio_to_y(ratio) - vis.lead_y;
+        if y > cover_line {
+            continue;
+        }
+        let y = field_y(y, config.display.scroll_up);
+
+        if let Some(&entity) = lines.get(used) {
+            if let Ok((mut sprite, mut tf, mut v)) = q_lines.get_mut(entity) {
+                sprite.color = color;
+                sprite.custom_size = Some(size);
                 tf.translation.y = y;
                 *v = Visibility::Visible;
-                note.state = NoteState::Active;
-                note.event_id = Some(event_id);
             }
+        } else {
+            let entity = commands
+                .spawn((
+                    Sprite {
+                        color,
+                        custom_size: Some(size),
+                        ..Default::default()
+                    },
+                    // 位于轨道背景与音符之间
+                    Transform::from_xyz(0.0, y, 0.5),
+                    GlobalTransform::default(),
+                    Visibility::Visible,
+                    InheritedVisibility::default(),
+                    BarLineMarker,
+                ))
+                .id();
+            lines.push(entity);
+        }
+        used += 1;
+    }
+
+    for &entity in lines.iter().skip(used) {
+        if let Ok((_, _, mut v)) = q_lines.get_mut(entity) {
+            *v = Visibility::Hidden;
+        }
+    }
+}
+
+/// 返回选曲时隐藏所有音符和小节线并回收到对象池
+fn clear_play_field(
+    mut pool: ResMut<NotePoolState>,
+    mut vis: ResMut<ChartVisualState>,
+    mut q_notes: Query<(&mut Visibility, &mut PooledNote), With<NoteMarker>>,
+    mut q_lines: Query<&mut Visibility, (With<BarLineMarker>, Without<NoteMarker>)>,
+) {
+    for (mut v, mut note) in &mut q_notes {
+        *v = Visibility::Hidden;
+        note.state = NoteState::Hidden;
+        note.event_id = None;
+    }
+    for mut v in &mut q_lines {
+        *v = Visibility::Hidden;
+    }
+
+    let pool = &mut *pool;
+    pool.available
+        .extend(pool.active.drain().map(|(_, entity)| entity));
+    pool.entity_to_event.clear();
+    vis.notes.clear();
+}
+
+/// 根据按键状态更新轨道背景颜色
+fn update_lane_highlight(
+    game_state: Res<GameState>,
+    skin: Res<Skin>,
+    layout: Res<KeyLayout>,
+    mut q_lanes: Query<(&LaneBackground, &mut Sprite)>,
+) {
+    for (lane, mut sprite) in &mut q_lanes {
+        let pressed = game_state.pressed.get(lane.0).copied().unwrap_or(false);
+        sprite.color = if pressed {
+            skin::srgb(skin.lane_pressed)
+        } else {
+            skin.lane.color(*layout, lane.0)
+        };
+    }
+}
 
-            // 加入活跃列表
-            pool.active.insert(event_id, entity);
-            pool.entity_to_event.insert(entity, event_id);
-            vis.notes.insert(event_id, entity);
-            alive.push(event_id);
+/// 按下轨道时在判定线上方闪光，并在短时间内淡出
+fn update_lane_flash(
+    time: Res<Time>,
+    game_state: Res<GameState>,
+    skin: Res<Skin>,
+    mut was_pressed: Local<Vec<bool>>,
+    mut q_flash: Query<(&mut LaneFlash, &mut Sprite, &mut Visibility)>,
+) {
+    for (mut flash, mut sprite, mut vis) in &mut q_flash {
+        let pressed = game_state.pressed.get(flash.lane).copied().unwrap_or(false);
+        let was = was_pressed.get(flash.lane).copied().unwrap_or(false);
+        if pressed && !was {
+            flash.remaining = LANE_FLASH_DURATION;
+        } else if flash.remaining > 0.0 {
+            flash.remaining = (flash.remaining - time.delta_secs()).max(0.0);
         }
+
+        if flash.remaining <= 0.0 {
+            *vis = Visibility::Hidden;
+            continue;
+        }
+        let alpha = LANE_FLASH_ALPHA * flash.remaining / LANE_FLASH_DURATION;
+        sprite.color = skin::srgb(skin.lane_flash).with_alpha(alpha);
+        *vis = Visibility::Visible;
     }
+    was_pressed.clone_from(&game_state.pressed);
+}
+
+/// 达到连击里程碑时整个轨道区域短暂闪光
+fn update_combo_flash(
+    time: Res<Time>,
+    skin: Res<Skin>,
+    mut milestones: MessageReader<ComboMilestoneMessage>,
+    mut q_flash: Query<(&mut ComboFlash, &mut Sprite, &mut Visibility)>,
+) {
+    let Ok((mut flash, mut sprite, mut vis)) = q_flash.single_mut() else {
+        milestones.clear();
+        return;
+    };
 
-    // 回收过时音符到对象池
-    let obsolete: Vec<ChartEventId> = pool
-        .active
-        .keys()
-        .filter(|id| !alive.contains(id))
-        .cloned()
-        .collect();
+    if let Some(msg) = milestones.read().last() {
+        println!("🎉 {} COMBO", msg.combo);
+        flash.remaining = COMBO_FLASH_DURATION;
+    } else if flash.remaining > 0.0 {
+        flash.remaining = (flash.remaining - time.delta_secs()).max(0.0);
+    }
 
-    for event_id in obsolete {
-        if let Some(&entity) = pool.active.get(&event_id) {
-            // 隐藏音符
-            if let Ok((_, mut v, mut note)) = q_notes.get_mut(entity) {
-                *v = Visibility::Hidden;
-                note.state = NoteState::Hidden;
-                note.event_id = None;
-            }
+    if flash.remaining <= 0.0 {
+        *vis = Visibility::Hidden;
+        return;
+    }
+    let alpha = COMBO_FLASH_ALPHA * flash.remaining / COMBO_FLASH_DURATION;
+    sprite.color = skin::srgb(skin.combo_flash).with_alpha(alpha);
+    *vis = Visibility::Visible;
+}
+
+/// 击中时在对应轨道上方显示 FAST/SLOW 及偏差，一段时间后隐藏
+fn update_fast_slow_indicator(
+    time: Res<Time>,
+    layout: Res<KeyLayout>,
+    skin: Res<Skin>,
+    mut messages: MessageReader<FastSlowMessage>,
+    mut q_indicator: Query<(
+        &mut FastSlowIndicator,
+        &mut Text2d,
+        &mut TextColor,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    let Ok((mut indicator, mut text, mut color, mut tf, mut vis)) = q_indicator.single_mut() else {
+        messages.clear();
+        return;
+    };
+
+    // 同一帧多次击中时只显示最后一次
+    if let Some(msg) = messages.read().last() {
+        let label = if msg.early { "FAST" } else { "SLOW" };
+        text.0 = format!("{label} {:.0}ms", msg.magnitude_ms);
+        color.0 = if msg.early { FAST_COLOR } else { SLOW_COLOR };
+        tf.translation.x = lane_x(&skin.field, *layout, msg.lane);
+        indicator.remaining = FAST_SLOW_DURATION;
+        *vis = Visibility::Visible;
+        return;
+    }
+
+    if indicator.remaining > 0.0 {
+        indicator.remaining -= time.delta_secs();
+        if indicator.remaining <= 0.0 {
+            *vis = Visibility::Hidden;
+        }
+    }
+}
+
+/// 通过按键调整轨道遮挡高度并保存到配置
+fn adjust_lane_cover(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut config: ResMut<SysConfig>,
+    (time, mut pending): (Res<Time<Real>>, ResMut<PendingConfigSave>),
+) {
+    let mut cover = config.display.lane_cover;
+    if keys.just_pressed(KeyCode::PageDown) {
+        cover += LANE_COVER_STEP;
+    }
+    if keys.just_pressed(KeyCode::PageUp) {
+        cover -= LANE_COVER_STEP;
+    }
+    let cover = cover.clamp(0.0, LANE_COVER_MAX);
+    if (cover - config.display.lane_cover).abs() < f32::EPSILON {
+        return;
+    }
+
+    config.display.lane_cover = cover;
+    println!("轨道遮挡: {:.0}%", cover * 100.0);
+    pending.request(time.elapsed());
+}
+
+/// 根据配置更新轨道遮挡的尺寸和位置
+fn update_lane_cover(
+    config: Res<SysConfig>,
+    layout: Res<KeyLayout>,
+    skin: Res<Skin>,
+    mut q_cover: Query<(Ref<LaneCover>, &mut Sprite, &mut Transform)>,
+) {
+    let changed = config.is_changed();
+    let height = config.display.lane_cover * VISIBLE_HEIGHT;
+    for (cover, mut sprite, mut tf) in &mut q_cover {
+        if !changed && !cover.is_added() {
+            continue;
+        }
+        sprite.custom_size = Some(Vec2::new(total_width(&skin.field, *layout), height));
+        tf.translation.y = field_y(
+            VISIBLE_HEIGHT / 2.0 - height / 2.0,
+            config.display.scroll_up,
+        );
+    }
+}
 
-            // 从活跃列表移除，加入可用池
-            pool.available.push(entity);
-            pool.active.remove(&event_id);
-            pool.entity_to_event.remove(&entity);
-            vis.notes.remove(&event_id);
+/// 按 F7 切换音符移动方向并保存到配置
+fn toggle_scroll_direction(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut config: ResMut<SysConfig>,
+    (time, mut pending): (Res<Time<Real>>, ResMut<PendingConfigSave>),
+) {
+    if !keys.just_pressed(KeyCode::F7) {
+        return;
+    }
+    config.display.scroll_up = !config.display.scroll_up;
+    println!(
+        "音符方向: {}",
+        if config.display.scroll_up {
+            "上升"
+        } else {
+            "下落"
+        }
+    );
+    pending.request(time.elapsed());
+}
+
+/// 根据音符移动方向放置判定线等场地内固定元素
+fn update_field_anchors(
+    config: Res<SysConfig>,
+    mut q_anchors: Query<(Ref<FieldAnchor>, &mut Transform)>,
+) {
+    let changed = config.is_changed();
+    for (anchor, mut tf) in &mut q_anchors {
+        if changed || anchor.is_added() {
+            tf.translation.y = field_y(anchor.0, config.display.scroll_up);
         }
     }
 }
@@ -250,8 +903,7 @@ fn print_pool_stats(pool: Res<NotePoolState>, time: Res<Time>, mut timer: Local<
     if *timer >= 5.0 {
         *timer = 0.0;
 
-        let usage =
-            (POOL_INITIAL_SIZE - pool.available.len()) as f32 / POOL_INITIAL_SIZE as f32 * 100.0;
+        let usage = pool.active.len() as f32 / pool.size.max(1) as f32 * 100.0;
 
         println!(
             "📊 对象池状态 | 活跃: {} | 可用: {} | 使用率: {:.1}%",
@@ -261,3 +913,89 @@ fn print_pool_stats(pool: Res<NotePoolState>, time: Res<Time>, mut timer: Local<
         );
     }
 }
+
+/// 更新 BPM 与小节号显示，颜色表示相对基准 BPM 的快慢
+fn update_tempo_indicator(
+    status: Option<Res<BmsProcessorResource>>,
+    mut q_text: Query<(&mut Text2d, &mut TextColor), With<TempoIndicator>>,
+) {
+    let Ok((mut text, mut color)) = q_text.single_mut() else {
+        return;
+    };
+    let Some(status) = status.filter(|s| s.started) else {
+        text.0.clear();
+        return;
+    };
+
+    let bpm = status
+        .processor
+        .current_bpm()
+        .to_f64()
+        .unwrap_or(status.base_bpm);
+    let ratio = bpm / status.base_bpm;
+    text.0 = format!(
+        "BPM {bpm:.0}  x{ratio:.2}  BASE {:.0}  MEASURE {:03}",
+        status.base_bpm, status.measure
+    );
+    color.0 = if (ratio - 1.0).abs() < 1e-3 {
+        TEMPO_COLOR
+    } else if ratio > 1.0 {
+        TEMPO_FAST_COLOR
+    } else {
+        TEMPO_SLOW_COLOR
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 同屏音符数量
+    const VISIBLE_NOTES: usize = 5000;
+
+    /// 与初始化时同样大小的对象池
+    fn initial_pool(world: &mut World) -> NotePoolState {
+        let mut pool = NotePoolState {
+            size: POOL_INITIAL_SIZE,
+            ..Default::default()
+        };
+        for _ in 0..POOL_INITIAL_SIZE {
+            pool.available.push(world.spawn_empty().id());
+        }
+        pool
+    }
+
+    #[test]
+    fn pool_grows_past_initial_capacity() {
+        let mut world = World::new();
+        let mut pool = initial_pool(&mut world);
+
+        let mut spawned = 0;
+        let mut entities = HashSet::new();
+        for id in 0..VISIBLE_NOTES {
+            let (entity, reused) = pool.acquire(ChartEventId(id), || {
+                spawned += 1;
+                world.spawn_empty().id()
+            });
+            assert_eq!(reused, id < POOL_INITIAL_SIZE);
+            entities.insert(entity);
+        }
+        // 每个音符都有独立的实体，没有音符被丢弃
+        assert_eq!(spawned, VISIBLE_NOTES - POOL_INITIAL_SIZE);
+        assert_eq!(entities.len(), VISIBLE_NOTES);
+        assert_eq!(pool.size, VISIBLE_NOTES);
+        assert_eq!(pool.active_count(), VISIBLE_NOTES);
+        assert!(pool.available.is_empty());
+
+        // 回收后再次显示同样数量的音符时只复用实体，不再扩容
+        let alive: HashSet<ChartEventId> = (0..100).map(ChartEventId).collect();
+        assert_eq!(pool.release_except(&alive).len(), VISIBLE_NOTES - 100);
+        assert_eq!(pool.active_count(), 100);
+        for id in VISIBLE_NOTES..2 * VISIBLE_NOTES - 100 {
+            let (_, reused) = pool.acquire(ChartEventId(id), || world.spawn_empty().id());
+            assert!(reused);
+        }
+        assert_eq!(pool.size, VISIBLE_NOTES);
+        assert_eq!(pool.entity_to_event.len(), VISIBLE_NOTES);
+    }
+}
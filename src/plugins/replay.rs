@@ -0,0 +1,477 @@
+//! 回放插件
+//!
+//! 记录演奏时的轨道输入及其相对谱面开始的时刻，离开游戏或退出时连同谱面摘要、`#RANDOM` 种子
+//! 与轨道变换写入回放文件；回放时沿用录制时的种子与轨道变换，加载谱面后核对摘要与轨道排列，
+//! 一致时按记录的时刻重新发送轨道输入，经过与实际演奏相同的判定流程
+
+use std::time::Duration;
+
+use bevy::{platform::collections::HashMap, prelude::*};
+use bms_rs::chart_process::ChartProcessor;
+use gametime::{TimeSpan, TimeStamp};
+
+use crate::lane::{KeyLayout, LaneShuffle};
+use crate::plugins::bms_processor::{BmsProcessorResource, BmsSystemSet};
+use crate::plugins::input_handler::{ControlMessage, InputSource, LaneInputMessage};
+use crate::plugins::judge::accepts_player_input;
+use crate::plugins::time_system::signed_secs;
+use crate::replay::{Replay, ReplayEvent, ReplayHeader, load_replay, save_replay};
+use crate::resources::{ExecArgs, NowStamp};
+use crate::schedule::LogicSchedule;
+use crate::state::AppState;
+
+/// 回放录制状态
+#[derive(Resource, Default)]
+struct ReplayRecorder {
+    /// 本次演奏已记录的事件
+    events: Vec<ReplayEvent>,
+    /// 已出现的输入来源及其序号
+    sources: HashMap<InputSource, u16>,
+}
+
+impl ReplayRecorder {
+    /// 清空记录
+    fn clear(&mut self) {
+        self.events.clear();
+        self.sources.clear();
+    }
+
+    /// 记录一次轨道输入相对谱面开始的偏移（输入没有时刻时使用 `now`）
+    fn record(&mut self, input: &LaneInputMessage, started_at: TimeStamp, now: TimeStamp) {
+        let next = self.sources.len() as u16;
+        let source = *self.sources.entry(input.source).or_insert(next);
+        self.events.push(ReplayEvent {
+            offset_ns: (signed_secs(input.time.unwrap_or(now), started_at) * 1e9).round() as i64,
+            lane: input.lane as u16,
+            source,
+            pressed: input.pressed,
+        });
+    }
+}
+
+/// 回放播放状态
+#[derive(Resource)]
+struct ReplayPlayer {
+    /// 录制时的谱面与轨道变换（回放文件无法读取时为 `None`）
+    header: Option<ReplayHeader>,
+    /// 按时刻排列的回放事件
+    events: Vec<ReplayEvent>,
+    /// 下一个待发送的事件下标
+    cursor: usize,
+}
+
+/// 回放插件
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(args) = app.world().get_resource::<ExecArgs>() else {
+            return;
+        };
+        let recording = args.record.is_some();
+        let replay_path = args.replay.clone();
+
+        if let Some(path) = replay_path {
+            let (header, mut events) = match load_replay(&path) {
+                Ok(Replay { header, events }) => (Some(header), events),
+                Err(e) => {
+                    eprintln!("{e:#}");
+                    (None, Vec::new())
+                }
+            };
+            if let Some(header) = &header {
+                use_recorded_randomness(app, header);
+            }
+            events.sort_by_key(|event| event.offset_ns);
+            println!("▶ 回放: {}（{} 个事件）", path.display(), events.len());
+            app.insert_resource(ReplayPlayer {
+                header,
+                events,
+                cursor: 0,
+            })
+            .add_systems(OnEnter(AppState::Playing), rewind_replay)
+            .add_systems(
+                LogicSchedule,
+                (verify_replay, play_replay_input)
+                    .chain()
+                    .run_if(in_state(AppState::Playing))
+                    .before(BmsSystemSet::EventProcess),
+            );
+        }
+
+        if !recording {
+            return;
+        }
+        app.init_resource::<ReplayRecorder>()
+            .add_systems(OnEnter(AppState::Playing), clear_recording)
+            .add_systems(OnExit(AppState::Playing), save_recording)
+            .add_systems(
+                LogicSchedule,
+                (
+                    clear_recording_on_restart,
+                    // 与判定使用同一时刻，保证回放可复现
+                    record_lane_input.run_if(accepts_player_input),
+                )
+                    .chain()
+                    .after(BmsSystemSet::EventProcess),
+            )
+            .add_systems(Last, save_recording_on_exit);
+    }
+}
+
+/// 回放时沿用录制时的 `#RANDOM` 种子与轨道变换
+fn use_recorded_randomness(app: &mut App, header: &ReplayHeader) {
+    let layout = app
+        .world()
+        .get_resource::<KeyLayout>()
+        .copied()
+        .unwrap_or_default();
+    if let Some(mut args) = app.world_mut().get_resource_mut::<ExecArgs>() {
+        args.seed = Some(header.chart_seed);
+        args.lane_modifier = header.lane_modifier;
+        args.shuffle_scratch = header.shuffle_scratch;
+    }
+    app.insert_resource(
+        LaneShuffle::new(header.lane_modifier, header.shuffle_scratch, layout)
+            .with_seed(header.shuffle_seed),
+    );
+}
+
+/// 加载谱面后核对回放录制时的谱面与轨道排列，不一致时停止回放
+fn verify_replay(
+    status: Option<Res<BmsProcessorResource>>,
+    lanes: Res<LaneShuffle>,
+    mut player: ResMut<ReplayPlayer>,
+) {
+    let Some(status) = status.filter(DetectChanges::is_added) else {
+        return;
+    };
+    let Some(header) = &player.header else {
+        return;
+    };
+    let permutation: Vec<u16> = lanes.permutation().iter().map(|&l| l as u16).collect();
+    let mismatch = if header.chart_hash != status.hash {
+        Some("谱面内容不同")
+    } else if header.chart_seed != status.seed {
+        Some("#RANDOM 种子不同")
+    } else if header.permutation != permutation {
+        Some("轨道排列不同")
+    } else {
+        None
+    };
+    if let Some(reason) = mismatch {
+        eprintln!("✗ 回放与当前谱面不一致（{reason}），已停止回放");
+        player.events.clear();
+    }
+}
+
+/// 进入游戏时清空记录
+fn clear_recording(mut recorder: ResMut<ReplayRecorder>) {
+    recorder.clear();
+}
+
+/// 重新开始时清空记录
+fn clear_recording_on_restart(
+    mut controls: MessageReader<ControlMessage>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    if ControlMessage::received(&mut controls, ControlMessage::Restart) {
+        recorder.clear();
+    }
+}
+
+/// 记录轨道输入相对谱面开始的偏移
+fn record_lane_input(
+    mut inputs: MessageReader<LaneInputMessage>,
+    status: Option<Res<BmsProcessorResource>>,
+    now_stamp: Res<NowStamp>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let Some(started_at) = status.and_then(|s| s.processor.started_at()) else {
+        inputs.clear();
+        return;
+    };
+    for input in inputs.read() {
+        recorder.record(input, started_at, now_stamp.0);
+    }
+}
+
+/// 写入回放文件（文件头取自当前谱面与轨道变换）
+fn write_recording(
+    args: &ExecArgs,
+    recorder: &ReplayRecorder,
+    status: Option<&BmsProcessorResource>,
+    lanes: &LaneShuffle,
+) {
+    let Some(path) = &args.record else {
+        return;
+    };
+    if recorder.events.is_empty() {
+        return;
+    }
+    let Some(status) = status else {
+        eprintln!("未加载谱面，回放未保存");
+        return;
+    };
+    let replay = Replay {
+        header: ReplayHeader {
+            chart_hash: status.hash.clone(),
+            chart_seed: status.seed,
+            lane_modifier: lanes.modifier(),
+            shuffle_scratch: lanes.include_scratch(),
+            shuffle_seed: lanes.seed(),
+            permutation: lanes.permutation().iter().map(|&l| l as u16).collect(),
+        },
+        events: recorder.events.clone(),
+    };
+    match save_replay(path, &replay) {
+        Ok(()) => println!(
+            "💾 回放已保存: {}（{} 个事件）",
+            path.display(),
+            replay.events.len()
+        ),
+        Err(e) => eprintln!("{e:#}"),
+    }
+}
+
+/// 离开游戏时写入回放文件
+fn save_recording(
+    args: Res<ExecArgs>,
+    recorder: Res<ReplayRecorder>,
+    status: Option<Res<BmsProcessorResource>>,
+    lanes: Res<LaneShuffle>,
+) {
+    write_recording(&args, &recorder, status.as_deref(), &lanes);
+}
+
+/// 在游戏中退出程序时写入回放文件
+fn save_recording_on_exit(
+    mut exits: MessageReader<AppExit>,
+    (args, recorder): (Res<ExecArgs>, Res<ReplayRecorder>),
+    status: Option<Res<BmsProcessorResource>>,
+    lanes: Res<LaneShuffle>,
+    state: Res<State<AppState>>,
+) {
+    if exits.read().count() == 0 || *state.get() != AppState::Playing {
+        return;
+    }
+    write_recording(&args, &recorder, status.as_deref(), &lanes);
+}
+
+/// 进入游戏时从头开始回放
+fn rewind_replay(mut player: ResMut<ReplayPlayer>) {
+    player.cursor = 0;
+}
+
+/// 回放事件对应的轨道输入，时刻为谱面开始时刻加上记录的偏移（开始前的输入偏移为负）
+fn replayed_input(event: &ReplayEvent, started_at: TimeStamp) -> LaneInputMessage {
+    let offset = TimeSpan::from_duration(Duration::from_nanos(event.offset_ns.unsigned_abs()));
+    let time = if event.offset_ns < 0 {
+        started_at - offset
+    } else {
+        started_at + offset
+    };
+    LaneInputMessage {
+        lane: event.lane as usize,
+        source: InputSource::Replay(event.source),
+        pressed: event.pressed,
+        time: Some(time),
+    }
+}
+
+/// 按记录的时刻发送轨道输入
+///
+/// 输入携带精确的发生时刻，判定不受帧间隔影响
+fn play_replay_input(
+    mut controls: MessageReader<ControlMessage>,
+    status: Option<Res<BmsProcessorResource>>,
+    now_stamp: Res<NowStamp>,
+    mut player: ResMut<ReplayPlayer>,
+    mut lane_inputs: MessageWriter<LaneInputMessage>,
+) {
+    // 重新开始的当帧谱面尚未重置开始时刻，下一帧再继续
+    if ControlMessage::received(&mut controls, ControlMessage::Restart) {
+        player.cursor = 0;
+        return;
+    }
+    let Some(started_at) = status.and_then(|s| s.processor.started_at()) else {
+        return;
+    };
+
+    let player = &mut *player;
+    while let Some(event) = player.events.get(player.cursor) {
+        let input = replayed_input(event, started_at);
+        if input.time.is_some_and(|time| time > now_stamp.0) {
+            break;
+        }
+        lane_inputs.write(input);
+        player.cursor += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bms_rs::chart_process::prelude::ChartEventId;
+
+    use super::*;
+    use crate::plugins::judge::{
+        GameState, GaugeKind, GaugeProfiles, JudgeLevel, JudgeParams, NoteCrossedEvent,
+        UpcomingNote,
+    };
+    use crate::replay::load_replay;
+
+    /// 模拟的帧间隔
+    const FRAME: Duration = Duration::from_millis(4);
+    /// 模拟的时长
+    const RUN_LENGTH: Duration = Duration::from_secs(5);
+
+    /// 合成谱面的音符：（轨道，相对谱面开始的毫秒数）
+    const NOTES: [(usize, u64); 7] = [
+        (1, 1000),
+        (2, 1500),
+        (1, 2000),
+        (3, 2000),
+        (4, 2500),
+        (5, 3000),
+        (1, 3500),
+    ];
+
+    /// 相对谱面开始的时刻
+    fn at(started_at: TimeStamp, offset: Duration) -> TimeStamp {
+        started_at + TimeSpan::from_duration(offset)
+    }
+
+    /// 以帧为单位推进合成谱面，按与判定插件相同的顺序处理音符与输入，返回判定分布
+    fn simulate(inputs: &[LaneInputMessage], started_at: TimeStamp) -> [u32; JudgeLevel::COUNT] {
+        let params = JudgeParams::default();
+        let gauge = GaugeProfiles::default().get(GaugeKind::Groove);
+        let mut state = GameState::new(8, GaugeKind::Groove, gauge, None);
+        let notes: Vec<UpcomingNote> = NOTES
+            .iter()
+            .enumerate()
+            .map(|(idx, &(lane, ms))| UpcomingNote {
+                event_id: ChartEventId(idx),
+                lane,
+                wav_id: None,
+                at: at(started_at, Duration::from_millis(ms)),
+            })
+            .collect();
+        let mut crossed = 0;
+        let mut next_input = 0;
+        let mut elapsed = Duration::ZERO;
+        while elapsed <= RUN_LENGTH {
+            let now = at(started_at, elapsed);
+            while let Some(note) = notes.get(crossed).filter(|note| note.at <= now) {
+                state.queue_crossed(&NoteCrossedEvent {
+                    event_id: note.event_id,
+                    lane: note.lane,
+                    wav_id: note.wav_id,
+                    crossed_at: note.at,
+                });
+                crossed += 1;
+            }
+            let upcoming = notes.get(crossed..).unwrap_or_default();
+            while let Some(input) = inputs
+                .get(next_input)
+                .filter(|input| input.time.is_some_and(|time| time <= now))
+            {
+                state.judge_input(input, now, upcoming, &params);
+                next_input += 1;
+            }
+            state.sweep_missed(now, &params);
+            elapsed += FRAME;
+        }
+        state.judge_counts
+    }
+
+    /// 一次合成的演奏输入：（轨道，来源，是否按下，相对谱面开始的微秒数）
+    fn live_inputs(started_at: TimeStamp) -> Vec<LaneInputMessage> {
+        let key = |code| InputSource::Keyboard(code);
+        let pad = InputSource::GamepadButton(Entity::PLACEHOLDER, GamepadButton::South);
+        let mut inputs: Vec<LaneInputMessage> = [
+            // PERFECT（晚 5ms）
+            (1, key(KeyCode::KeyS), true, 1_005_000),
+            (1, key(KeyCode::KeyS), false, 1_080_000),
+            // GREAT（早 35ms），按住时的按键重复不再判定
+            (2, key(KeyCode::KeyD), true, 1_465_000),
+            (2, key(KeyCode::KeyD), true, 1_480_000),
+            (2, key(KeyCode::KeyD), false, 1_540_000),
+            // GOOD（晚 70ms）与 BAD（早 150ms）
+            (1, key(KeyCode::KeyS), true, 2_070_000),
+            (3, key(KeyCode::KeyF), true, 1_850_000),
+            (1, key(KeyCode::KeyS), false, 2_100_000),
+            (3, key(KeyCode::KeyF), false, 1_900_000),
+            // 第 4 轨道不按，POOR；第 5 轨道两个来源先后按下，第二次为空按
+            (5, key(KeyCode::KeyJ), true, 3_003_000),
+            (5, pad, true, 3_010_000),
+            (5, pad, false, 3_050_000),
+            (5, key(KeyCode::KeyJ), false, 3_060_000),
+            // 手柄 PERFECT（晚 0.5ms）
+            (1, pad, true, 3_500_500),
+            (1, pad, false, 3_560_000),
+        ]
+        .into_iter()
+        .map(|(lane, source, pressed, us)| LaneInputMessage {
+            lane,
+            source,
+            pressed,
+            time: Some(at(started_at, Duration::from_micros(us))),
+        })
+        .collect();
+        inputs.sort_by_key(|input| input.time);
+        inputs
+    }
+
+    #[test]
+    fn replay_reproduces_judgment_histogram() {
+        let started_at = TimeStamp::start() + TimeSpan::from_duration(Duration::from_secs(3));
+        let inputs = live_inputs(started_at);
+        let live = simulate(&inputs, started_at);
+        assert_eq!(live, [3, 1, 1, 1, 1]);
+
+        let mut recorder = ReplayRecorder::default();
+        for input in &inputs {
+            recorder.record(input, started_at, started_at);
+        }
+        let path = std::env::temp_dir().join(format!("nebula-tunes-{}.ntrp", std::process::id()));
+        let replay = Replay {
+            header: ReplayHeader {
+                chart_hash: "0000000000000000".to_string(),
+                chart_seed: 0,
+                lane_modifier: None,
+                shuffle_scratch: false,
+                shuffle_seed: 0,
+                permutation: (0..8).collect(),
+            },
+            events: recorder.events,
+        };
+        save_replay(&path, &replay).expect("保存回放");
+        let loaded = load_replay(&path).expect("读取回放");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded, replay);
+
+        // 回放时谱面在另一时刻开始，输入按记录的偏移还原
+        let replay_start =
+            started_at + TimeSpan::from_duration(Duration::from_nanos(7_000_000_123));
+        let replayed: Vec<LaneInputMessage> = loaded
+            .events
+            .iter()
+            .map(|event| replayed_input(event, replay_start))
+            .collect();
+        assert_eq!(simulate(&replayed, replay_start), live);
+    }
+
+    #[test]
+    fn input_before_chart_start_keeps_its_offset() {
+        let started_at = TimeStamp::now() + TimeSpan::from_duration(Duration::from_secs(1));
+        let event = ReplayEvent {
+            offset_ns: -5_000_000,
+            lane: 1,
+            source: 0,
+            pressed: true,
+        };
+        let input = replayed_input(&event, started_at);
+        let time = input.time.expect("回放输入带有时刻");
+        assert!((signed_secs(time, started_at) + 0.005).abs() <= 1e-9);
+    }
+}
@@ -0,0 +1,362 @@
+//! 结算插件
+//!
+//! 谱面播放结束后切换到结算画面，展示谱面难度、判定分布、准确率、最大连击、血条和音符密度图；
+//! 指定 `--results-out` 时同时将成绩写为 JSON
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::config::SysConfig;
+use crate::play_result::{self, PlayResult};
+use crate::plugins::audio_manager::AudioStopMessage;
+use crate::plugins::bms_processor::{BmsProcessorResource, ChartInfo};
+use crate::plugins::input_handler::ControlMessage;
+use crate::plugins::judge::{GameState, JudgeLevel};
+use crate::resources::ExecArgs;
+use crate::skin::{self, Skin};
+use crate::state::AppState;
+
+/// 失败时音频淡出时长
+const FAIL_FADE: Duration = Duration::from_millis(500);
+/// 判定分布条的最大宽度
+const BAR_MAX_WIDTH: f32 = 400.0;
+/// 判定分布条的高度
+const BAR_HEIGHT: f32 = 20.0;
+/// 音符密度图的宽度
+const GRAPH_WIDTH: f32 = 600.0;
+/// 音符密度图的高度
+const GRAPH_HEIGHT: f32 = 80.0;
+/// 音符密度图的柱颜色
+const GRAPH_COLOR: Color = Color::srgb(0.5, 0.6, 0.8);
+/// 结算画面背景颜色
+const BACKGROUND_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.85);
+
+/// 结算插件
+pub struct ResultPlugin;
+
+impl Plugin for ResultPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            enter_result_on_finish.run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            OnEnter(AppState::Result),
+            (spawn_result_screen, export_result),
+        )
+        .add_systems(
+            Update,
+            handle_result_input.run_if(in_state(AppState::Result)),
+        );
+    }
+}
+
+/// 判定等级对应的颜色
+const fn level_color(level: JudgeLevel) -> Color {
+    match level {
+        JudgeLevel::Perfect => Color::srgb(0.4, 0.9, 1.0),
+        JudgeLevel::Great => Color::srgb(1.0, 0.85, 0.3),
+        JudgeLevel::Good => Color::srgb(0.5, 0.9, 0.4),
+        JudgeLevel::Bad => Color::srgb(0.8, 0.4, 0.9),
+        JudgeLevel::Poor => Color::srgb(0.9, 0.3, 0.3),
+    }
+}
+
+/// 计算准确率（EX 分数占理论最高分的比例）
+fn accuracy(state: &GameState) -> f32 {
+    let [perfect, great, ..] = state.judge_counts;
+    let total: u32 = state.judge_counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    (perfect * 2 + great) as f32 / (total * 2) as f32
+}
+
+/// 连击中断的秒数档位（将音符序号按密度图累计数量换算为时间）
+fn break_buckets(density: &[u32], breaks: &[u32]) -> Vec<bool> {
+    let mut marks = vec![false; density.len()];
+    for &note in breaks {
+        let mut total = 0;
+        let bucket = density.iter().position(|&count| {
+            total += count;
+            note < total
+        });
+        if let Some(mark) = bucket.and_then(|b| marks.get_mut(b)) {
+            *mark = true;
+        }
+    }
+    marks
+}
+
+/// 谱面难度信息的显示文字
+fn chart_info_label(info: ChartInfo) -> String {
+    let level = info
+        .level
+        .map_or_else(|| "-".to_string(), |level| level.to_string());
+    let difficulty = info
+        .difficulty_label()
+        .map(|label| format!("{label}   "))
+        .unwrap_or_default();
+    format!("{difficulty}LEVEL {level}   NOTES {}", info.note_count)
+}
+
+/// 以结构化字段记录各轨道的判定统计
+fn log_lane_stats(game_state: &GameState) {
+    for (lane, stats) in game_state.lane_stats.iter().enumerate() {
+        let [perfect, great, good, bad, poor] = stats.judge_counts;
+        info!(
+            target: "lane_stats",
+            lane,
+            perfect,
+            great,
+            good,
+            bad,
+            poor,
+            hits = stats.hits,
+            avg_offset_ms = stats.average_timing_ms().unwrap_or(0.0),
+            "轨道判定统计"
+        );
+    }
+}
+
+/// 谱面结束且所有音符结算完毕后按最终血条判断是否通关并进入结算画面，
+/// 血条归零失败时立即进入
+///
+/// 谱面结束以处理器在最后一个事件之后发出的 `ChartEnd` 为准，
+/// 最后一个音符之后只有 BGM 的段落也会播放完毕
+fn enter_result_on_finish(
+    status: Option<Res<BmsProcessorResource>>,
+    mut game_state: ResMut<GameState>,
+    config: Res<SysConfig>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut stops: MessageWriter<AudioStopMessage>,
+) {
+    if game_state.failed {
+        println!("💀 血条归零，演奏失败");
+        stops.write(AudioStopMessage::FadeOut(FAIL_FADE));
+        next_state.set(AppState::Result);
+        return;
+    }
+    let Some(status) = status else {
+        return;
+    };
+    if status.finished && !game_state.has_pending_notes() {
+        // 没有可判定音符的谱面（只有 BGM）播放完毕即视为通关
+        game_state.cleared = status.info.note_count == 0
+            || game_state
+                .gauge_kind
+                .is_cleared(game_state.gauge, config.judge.clear_gauge);
+        next_state.set(AppState::Result);
+    }
+}
+
+/// 指定了 `--results-out` 时写入演奏结果
+fn export_result(
+    args: Res<ExecArgs>,
+    game_state: Res<GameState>,
+    status: Option<Res<BmsProcessorResource>>,
+) {
+    let Some(path) = &args.results_out else {
+        return;
+    };
+    let header = status.as_ref().map(|status| &status.bms.header);
+    let result = PlayResult {
+        chart_hash: status.as_ref().map(|status| status.hash.clone()),
+        title: header.and_then(|header| header.title.clone()),
+        artist: header.and_then(|header| header.artist.clone()),
+        gauge_kind: game_state.gauge_kind,
+        gauge: game_state.gauge,
+        cleared: !game_state.failed && game_state.cleared,
+        judge_counts: game_state.judge_counts.into(),
+        accuracy: accuracy(&game_state),
+        max_combo: game_state.max_combo,
+        fast: game_state.fast_count,
+        slow: game_state.slow_count,
+        lanes: game_state.lane_stats.iter().map(Into::into).collect(),
+    };
+    match play_result::save_result(path, &result) {
+        Ok(()) => println!("💾 演奏结果已保存: {}", path.display()),
+        Err(e) => eprintln!("{e:#}"),
+    }
+}
+
+/// 创建结算画面
+fn spawn_result_screen(
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    skin: Res<Skin>,
+    status: Option<Res<BmsProcessorResource>>,
+) {
+    let (density, info) = status
+        .map(|s| (s.note_density.clone(), s.info))
+        .unwrap_or_default();
+    let breaks = break_buckets(&density, &game_state.combo_breaks);
+    let peak = density.iter().copied().max().unwrap_or(0);
+    let cleared = !game_state.failed && game_state.cleared;
+    let accuracy = accuracy(&game_state);
+    let max_count = game_state
+        .judge_counts
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    log_lane_stats(&game_state);
+    println!(
+        "🏁 结算 | {} {} | 准确率: {:.2}% | 最大连击: {} | 血条: {:.0}%",
+        game_state.gauge_kind.label(),
+        if cleared { "CLEAR" } else { "FAILED" },
+        accuracy * 100.0,
+        game_state.max_combo,
+        game_state.gauge * 100.0
+    );
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(8.0),
+                ..Default::default()
+            },
+            BackgroundColor(BACKGROUND_COLOR),
+            GlobalZIndex(10),
+            DespawnOnExit(AppState::Result),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(if cleared { "CLEAR" } else { "FAILED" }),
+                TextFont {
+                    font_size: 48.0,
+                    ..Default::default()
+                },
+                TextColor(skin::srgb(if cleared {
+                    skin.gauge.clear
+                } else {
+                    skin.gauge.failed
+                })),
+            ));
+            parent.spawn(Text::new(chart_info_label(info)));
+
+            // 判定分布
+            for (level, count) in JudgeLevel::ALL.iter().zip(game_state.judge_counts) {
+                let color = level_color(*level);
+                parent
+                    .spawn(Node {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(12.0),
+                        ..Default::default()
+                    })
+                    .with_children(|row| {
+                        row.spawn((
+                            Node {
+                                width: Val::Px(100.0),
+                                ..Default::default()
+                            },
+                            Text::new(level.label()),
+                            TextColor(color),
+                        ));
+                        row.spawn((
+                            Node {
+                                width: Val::Px(count as f32 / max_count as f32 * BAR_MAX_WIDTH),
+                                height: Val::Px(BAR_HEIGHT),
+                                ..Default::default()
+                            },
+                            BackgroundColor(color),
+                        ));
+                        row.spawn(Text::new(count.to_string()));
+                    });
+            }
+
+            // 音符密度图（每秒一柱，连击中断处标红）
+            if peak > 0 {
+                let bar_width = GRAPH_WIDTH / density.len() as f32;
+                parent
+                    .spawn(Node {
+                        width: Val::Px(GRAPH_WIDTH),
+                        height: Val::Px(GRAPH_HEIGHT),
+                        align_items: AlignItems::FlexEnd,
+                        ..Default::default()
+                    })
+                    .with_children(|graph| {
+                        for (&count, &broken) in density.iter().zip(&breaks) {
+                            graph.spawn((
+                                Node {
+                                    width: Val::Px(bar_width),
+                                    height: Val::Px(count as f32 / peak as f32 * GRAPH_HEIGHT),
+                                    ..Default::default()
+                                },
+                                BackgroundColor(if broken {
+                                    level_color(JudgeLevel::Poor)
+                                } else {
+                                    GRAPH_COLOR
+                                }),
+                            ));
+                        }
+                    });
+                parent.spawn((
+                    Text::new(format!(
+                        "PEAK {peak} NPS   COMBO BREAKS {}",
+                        game_state.combo_breaks.len()
+                    )),
+                    TextColor(Color::srgb(0.6, 0.6, 0.6)),
+                ));
+            }
+
+            parent.spawn(Text::new(format!(
+                "ACCURACY {:.2}%   MAX COMBO {}   {} GAUGE {:.0}%",
+                accuracy * 100.0,
+                game_state.max_combo,
+                game_state.gauge_kind.label(),
+                game_state.gauge * 100.0
+            )));
+            let average = game_state
+                .average_timing_ms()
+                .map_or_else(|| "-".to_string(), |ms| format!("{ms:+.1}ms"));
+            parent.spawn(Text::new(format!(
+                "FAST {}   SLOW {}   AVG {average}",
+                game_state.fast_count, game_state.slow_count
+            )));
+            let lanes: Vec<String> = game_state
+                .lane_stats
+                .iter()
+                .enumerate()
+                .map(|(lane, stats)| {
+                    stats
+                        .average_timing_ms()
+                        .map_or_else(|| format!("{lane}:-"), |ms| format!("{lane}:{ms:+.0}"))
+                })
+                .collect();
+            parent.spawn((
+                Text::new(format!("LANE AVG(ms) {}", lanes.join(" "))),
+                TextFont {
+                    font_size: 14.0,
+                    ..Default::default()
+                },
+            ));
+            parent.spawn((
+                Text::new("ENTER: SONG SELECT   R: RETRY"),
+                TextColor(Color::srgb(0.6, 0.6, 0.6)),
+            ));
+        });
+}
+
+/// 结算画面按键处理（Enter 返回选曲，重新开始时返回游戏）
+fn handle_result_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut controls: MessageReader<ControlMessage>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if ControlMessage::received(&mut controls, ControlMessage::Restart) {
+        next_state.set(AppState::Playing);
+        return;
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        next_state.set(AppState::SongSelect);
+    }
+}
@@ -0,0 +1,548 @@
+//! 选曲插件
+//!
+//! 扫描曲库目录中的谱面，逐步解析头信息并提供选曲列表，光标停留时播放预览。
+//! 头信息缓存在曲库索引中，未修改的谱面重新扫描时不再读取
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use bevy::{
+    asset::AssetPath,
+    prelude::*,
+    tasks::{IoTaskPool, Task, futures::check_ready},
+};
+use bevy_kira_audio::AudioSource as KiraAudioSource;
+
+use crate::archive;
+use crate::config::SysConfig;
+use crate::filesystem;
+use crate::plugins::audio_manager::{AudioStopMessage, PreviewPlayMessage};
+use crate::plugins::bms_processor::{
+    AUDIO_EXTS, CHART_EXTS, LoadChartMessage, first_bgm_audio, read_bms,
+};
+use crate::plugins::config_save::PendingConfigSave;
+use crate::song_index::{self, SONG_INDEX_PATH, SongHeader, SongIndex};
+use crate::state::AppState;
+
+/// 同时解析头信息的谱面数量
+const PARSE_BATCH_SIZE: usize = 8;
+/// 列表同时显示的条目数
+const VISIBLE_ENTRIES: usize = 15;
+/// 光标停留超过此时长后开始预览
+const PREVIEW_DELAY: Duration = Duration::from_millis(500);
+/// 预览淡出时长
+const PREVIEW_FADE: Duration = Duration::from_millis(300);
+/// 选中条目的背景颜色
+const SELECTED_COLOR: Color = Color::srgb(0.25, 0.35, 0.6);
+/// 普通条目的背景颜色
+const ENTRY_COLOR: Color = Color::srgb(0.12, 0.12, 0.15);
+/// 每次按键调整可见时长的步长（毫秒）
+const TRAVEL_STEP_MS: u64 = 50;
+/// 可见时长的下限（毫秒）
+const MIN_TRAVEL_MS: u64 = 100;
+/// 可见时长的上限（毫秒）
+const MAX_TRAVEL_MS: u64 = 3000;
+
+/// 选曲列表条目
+#[derive(Debug, Clone)]
+pub struct SongEntry {
+    /// 谱面路径
+    pub path: PathBuf,
+    /// 标题
+    pub title: String,
+    /// 艺术家
+    pub artist: String,
+    /// 流派
+    pub genre: Option<String>,
+    /// 难度等级
+    pub level: Option<u32>,
+    /// 血条总量
+    pub total: Option<f64>,
+}
+
+impl SongEntry {
+    /// 由头信息创建条目，没有标题时使用文件名
+    fn new(path: PathBuf, header: SongHeader) -> Self {
+        let title = header.title.unwrap_or_else(|| {
+            path.file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        Self {
+            path,
+            title,
+            artist: header.artist.unwrap_or_default(),
+            genre: header.genre,
+            level: header.level,
+            total: header.total,
+        }
+    }
+}
+
+/// 头信息解析结果
+struct ParsedSong {
+    /// 谱面路径
+    path: PathBuf,
+    /// 修改时间（读取失败时为 `None`，不写入索引）
+    modified: Option<u64>,
+    /// 头信息
+    header: SongHeader,
+}
+
+/// 选曲列表
+///
+/// 扫描结果在整个运行期间保留，返回选曲时不会重复扫描
+#[derive(Resource, Default)]
+pub struct SongList {
+    /// 已解析的条目
+    pub entries: Vec<SongEntry>,
+    /// 光标位置
+    pub cursor: usize,
+    /// 目录扫描任务
+    scan_task: Option<Task<Vec<PathBuf>>>,
+    /// 等待解析头信息的谱面
+    pending_paths: Vec<PathBuf>,
+    /// 正在解析的头信息任务
+    parse_tasks: Vec<Task<Option<ParsedSong>>>,
+    /// 上次保存的曲库索引
+    index: Arc<SongIndex>,
+    /// 本次扫描生成的曲库索引
+    fresh_index: SongIndex,
+    /// 是否已开始扫描
+    scanned: bool,
+}
+
+impl SongList {
+    /// 是否仍在扫描或解析
+    #[must_use]
+    pub const fn is_loading(&self) -> bool {
+        self.scan_task.is_some() || !self.pending_paths.is_empty() || !self.parse_tasks.is_empty()
+    }
+
+    /// 当前选中的条目
+    #[must_use]
+    pub fn selected(&self) -> Option<&SongEntry> {
+        self.entries.get(self.cursor)
+    }
+}
+
+/// 选曲预览状态
+#[derive(Resource, Default)]
+struct PreviewState {
+    /// 预览对应的光标位置
+    cursor: Option<usize>,
+    /// 光标停留时长
+    hover: Duration,
+    /// 是否已请求解析预览音频
+    requested: bool,
+    /// 预览音频路径解析任务
+    task: Option<Task<Option<PathBuf>>>,
+    /// 当前预览的音频路径
+    path: Option<PathBuf>,
+    /// 当前预览的音频句柄（仅在选曲期间持有，不影响游戏内的音频加载）
+    handle: Option<Handle<KiraAudioSource>>,
+    /// 是否正在播放
+    playing: bool,
+}
+
+/// 选曲列表根节点
+#[derive(Component)]
+struct SongListRoot;
+
+/// 选曲插件
+pub struct SongSelectPlugin;
+
+impl Plugin for SongSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SongList>()
+            .init_resource::<PreviewState>()
+            .add_systems(
+                OnEnter(AppState::SongSelect),
+                (start_song_scan, spawn_song_list),
+            )
+            .add_systems(OnExit(AppState::SongSelect), stop_preview)
+            .add_systems(
+                Update,
+                (
+                    poll_song_scan,
+                    navigate_song_list,
+                    adjust_visible_travel,
+                    update_preview,
+                    render_song_list,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::SongSelect)),
+            );
+    }
+}
+
+/// 首次进入选曲时开始扫描曲库目录
+///
+/// BMS 与 PMS 谱面都会列出，加载时按扩展名切换键位布局
+fn start_song_scan(mut list: ResMut<SongList>, config: Res<SysConfig>) {
+    if list.scanned {
+        return;
+    }
+    list.scanned = true;
+    list.index = Arc::new(
+        song_index::load_index(Path::new(SONG_INDEX_PATH)).unwrap_or_else(|e| {
+            eprintln!("{e:#}");
+            SongIndex::default()
+        }),
+    );
+
+    let dir = config.songs.dir.clone();
+    let exts = &CHART_EXTS;
+    println!("🔍 扫描曲库: {}", dir.display());
+    let task = IoTaskPool::get().spawn(async move {
+        let mut charts = filesystem::find_files_by_ext_async(&dir, exts).await;
+        // 压缩包内的谱面
+        for archive_path in filesystem::find_files_by_ext_async(&dir, &["zip"]).await {
+            charts.extend(archive::find_entries_by_ext(&archive_path, exts).await);
+        }
+        charts
+    });
+    list.scan_task = Some(task);
+}
+
+/// 读取谱面头信息，修改时间与索引一致时直接使用缓存
+async fn read_song_header(path: PathBuf, index: Arc<SongIndex>) -> Option<ParsedSong> {
+    let modified = song_index::modified_time(&path).await;
+    if let Some(header) = modified.and_then(|modified| index.get(&path, modified)) {
+        return Some(ParsedSong {
+            header: header.clone(),
+            path,
+            modified,
+        });
+    }
+    match song_index::read_header(&path).await {
+        Ok(header) => Some(ParsedSong {
+            path,
+            modified,
+            header,
+        }),
+        Err(e) => {
+            warn!("谱面读取失败 {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// 按路径顺序插入条目（解析任务完成的顺序不固定），插入到光标之前时光标随之后移
+fn insert_entry(entries: &mut Vec<SongEntry>, cursor: &mut usize, entry: SongEntry) {
+    let pos = entries
+        .binary_search_by(|other| other.path.cmp(&entry.path))
+        .unwrap_or_else(|pos| pos);
+    entries.insert(pos, entry);
+    if pos <= *cursor && entries.len() > 1 {
+        *cursor += 1;
+    }
+}
+
+/// 轮询扫描任务并分批解析头信息
+///
+/// 仅在扫描完成或有新条目时标记列表变化，避免每帧重建界面；
+/// 全部解析完成后保存曲库索引
+fn poll_song_scan(mut list_res: ResMut<SongList>) {
+    if !list_res.is_loading() {
+        return;
+    }
+    let list = list_res.bypass_change_detection();
+    let mut changed = false;
+
+    if let Some(task) = list.scan_task.as_mut()
+        && let Some(paths) = check_ready(task)
+    {
+        println!("✓ 找到 {} 个谱面", paths.len());
+        // 倒序存放，使 pop 按路径顺序取出
+        list.pending_paths = paths.into_iter().rev().collect();
+        list.scan_task = None;
+        changed = true;
+    }
+
+    let (entries, cursor, fresh_index) =
+        (&mut list.entries, &mut list.cursor, &mut list.fresh_index);
+    list.parse_tasks.retain_mut(|task| {
+        let Some(parsed) = check_ready(task) else {
+            return true;
+        };
+        if let Some(ParsedSong {
+            path,
+            modified,
+            header,
+        }) = parsed
+        {
+            if let Some(modified) = modified {
+                fresh_index.insert(path.clone(), modified, header.clone());
+            }
+            insert_entry(entries, cursor, SongEntry::new(path, header));
+        }
+        changed = true;
+        false
+    });
+
+    let pool = IoTaskPool::get();
+    while list.parse_tasks.len() < PARSE_BATCH_SIZE {
+        let Some(path) = list.pending_paths.pop() else {
+            break;
+        };
+        let index = Arc::clone(&list.index);
+        list.parse_tasks
+            .push(pool.spawn(read_song_header(path, index)));
+    }
+
+    if !list.is_loading() {
+        let index = std::mem::take(&mut list.fresh_index);
+        if let Err(e) = song_index::save_index(Path::new(SONG_INDEX_PATH), &index) {
+            eprintln!("{e:#}");
+        }
+        list.index = Arc::new(index);
+    }
+
+    if changed {
+        list_res.set_changed();
+    }
+}
+
+/// 上下键移动光标，Enter 开始游戏
+fn navigate_song_list(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut list: ResMut<SongList>,
+    mut loads: MessageWriter<LoadChartMessage>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let len = list.entries.len();
+    if len == 0 {
+        return;
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        list.cursor = (list.cursor + 1) % len;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        list.cursor = (list.cursor + len - 1) % len;
+    }
+    if keys.just_pressed(KeyCode::Enter)
+        && let Some(entry) = list.selected()
+    {
+        println!("▶ 选择谱面: {}", entry.title);
+        loads.write(LoadChartMessage {
+            path: entry.path.clone(),
+        });
+        next_state.set(AppState::Playing);
+    }
+}
+
+/// 左右键调整音符的可见时长并保存到配置，连续调整时停止后才写入文件
+///
+/// 处理器创建后无法修改可见范围，因此只在选曲时调整，从下一次加载谱面起生效
+fn adjust_visible_travel(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut config: ResMut<SysConfig>,
+    mut list: ResMut<SongList>,
+    (time, mut pending): (Res<Time<Real>>, ResMut<PendingConfigSave>),
+) {
+    let current = config.judge.visible_travel_ms;
+    let mut travel = current;
+    if keys.just_pressed(KeyCode::ArrowLeft) {
+        travel = travel.saturating_sub(TRAVEL_STEP_MS);
+    }
+    if keys.just_pressed(KeyCode::ArrowRight) {
+        travel += TRAVEL_STEP_MS;
+    }
+    let travel = travel.clamp(MIN_TRAVEL_MS, MAX_TRAVEL_MS);
+    if travel == current {
+        return;
+    }
+
+    config.judge.visible_travel_ms = travel;
+    println!("可见时长: {travel}ms");
+    pending.request(time.elapsed());
+    // 刷新标题行
+    list.set_changed();
+}
+
+/// 解析谱面的预览音频路径（优先 `#PREVIEW`，否则取第一个 BGM 音频）
+async fn resolve_preview(chart_path: PathBuf) -> Option<PathBuf> {
+    let bms = read_bms(&chart_path).await.ok()?;
+    let rel = match bms.header.preview_music.clone() {
+        Some(path) => path,
+        None => first_bgm_audio(&bms)?,
+    };
+
+    filesystem::resolve_chart_files(&chart_path, &[rel], &AUDIO_EXTS)
+        .await
+        .pop()
+        .flatten()
+}
+
+/// 光标停留时加载并播放预览，光标移动时淡出
+fn update_preview(
+    time: Res<Time>,
+    list: Res<SongList>,
+    mut preview: ResMut<PreviewState>,
+    asset_server: Res<AssetServer>,
+    assets: Res<Assets<KiraAudioSource>>,
+    mut plays: MessageWriter<PreviewPlayMessage>,
+    mut stops: MessageWriter<AudioStopMessage>,
+) {
+    let cursor = list.selected().map(|_| list.cursor);
+    if preview.cursor != cursor {
+        if preview.playing {
+            stops.write(AudioStopMessage::FadeOut(PREVIEW_FADE));
+        }
+        *preview = PreviewState {
+            cursor,
+            ..Default::default()
+        };
+        return;
+    }
+    let Some(entry) = list.selected() else {
+        return;
+    };
+
+    // 防抖：快速移动光标时不加载预览
+    preview.hover += time.delta();
+    if preview.hover < PREVIEW_DELAY {
+        return;
+    }
+
+    if !preview.requested {
+        preview.requested = true;
+        preview.task = Some(IoTaskPool::get().spawn(resolve_preview(entry.path.clone())));
+    }
+
+    if let Some(task) = preview.task.as_mut()
+        && let Some(result) = check_ready(task)
+    {
+        preview.task = None;
+        if let Some(path) = result {
+            let asset_str = archive::asset_path_string(&path);
+            preview.handle = Some(asset_server.load_override(AssetPath::parse(&asset_str)));
+            preview.path = Some(path);
+        }
+    }
+
+    if !preview.playing
+        && let Some(handle) = preview.handle.clone()
+        && assets.get(&handle).is_some()
+    {
+        if let Some(path) = &preview.path {
+            println!("🎵 预览: {}", path.display());
+        }
+        plays.write(PreviewPlayMessage { handle });
+        preview.playing = true;
+    }
+}
+
+/// 离开选曲时淡出预览并释放预览音频
+fn stop_preview(mut preview: ResMut<PreviewState>, mut stops: MessageWriter<AudioStopMessage>) {
+    if preview.playing {
+        stops.write(AudioStopMessage::FadeOut(PREVIEW_FADE));
+    }
+    *preview = PreviewState::default();
+}
+
+/// 创建选曲列表根节点
+fn spawn_song_list(mut commands: Commands, mut list: ResMut<SongList>) {
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(24.0)),
+            row_gap: Val::Px(4.0),
+            ..Default::default()
+        },
+        BackgroundColor(Color::srgb(0.05, 0.05, 0.07)),
+        GlobalZIndex(10),
+        DespawnOnExit(AppState::SongSelect),
+        SongListRoot,
+    ));
+    // 触发首次渲染
+    list.set_changed();
+}
+
+/// 列表变化时重建条目
+fn render_song_list(
+    mut commands: Commands,
+    list: Res<SongList>,
+    config: Res<SysConfig>,
+    q_root: Query<Entity, With<SongListRoot>>,
+) {
+    if !list.is_changed() {
+        return;
+    }
+    let Ok(root) = q_root.single() else {
+        return;
+    };
+
+    // 以光标为中心显示一页
+    let start = list
+        .cursor
+        .saturating_sub(VISIBLE_ENTRIES / 2)
+        .min(list.entries.len().saturating_sub(VISIBLE_ENTRIES));
+
+    commands
+        .entity(root)
+        .despawn_children()
+        .with_children(|parent| {
+            let status = if list.is_loading() { " LOADING..." } else { "" };
+            parent.spawn((
+                Text::new(format!(
+                    "SONG SELECT ({}){status}   F8: KEY CONFIG   LEFT/RIGHT: TRAVEL {}ms",
+                    list.entries.len(),
+                    config.judge.visible_travel_ms
+                )),
+                TextFont {
+                    font_size: 28.0,
+                    ..Default::default()
+                },
+            ));
+
+            for (idx, entry) in list
+                .entries
+                .iter()
+                .enumerate()
+                .skip(start)
+                .take(VISIBLE_ENTRIES)
+            {
+                parent.spawn((
+                    Node {
+                        padding: UiRect::axes(Val::Px(12.0), Val::Px(4.0)),
+                        ..Default::default()
+                    },
+                    BackgroundColor(if idx == list.cursor {
+                        SELECTED_COLOR
+                    } else {
+                        ENTRY_COLOR
+                    }),
+                    children![Text::new(song_label(entry, idx == list.cursor))],
+                ));
+            }
+        });
+}
+
+/// 列表条目的显示文字，选中的条目附加流派与 TOTAL
+fn song_label(entry: &SongEntry, selected: bool) -> String {
+    let level = entry
+        .level
+        .map(|level| format!("[{level}] "))
+        .unwrap_or_default();
+    let label = format!("{level}{} / {}", entry.title, entry.artist);
+    if !selected {
+        return label;
+    }
+    let genre = entry
+        .genre
+        .as_ref()
+        .map(|genre| format!("   {genre}"))
+        .unwrap_or_default();
+    let total = entry
+        .total
+        .map(|total| format!("   TOTAL {total}"))
+        .unwrap_or_default();
+    format!("{label}{genre}{total}")
+}
@@ -0,0 +1,125 @@
+//! 性能统计插件
+//!
+//! 按 F9 切换左上角的帧率、帧时间与绘制数量显示
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::plugins::input_handler::ControlMessage;
+use crate::plugins::note_renderer::NotePoolState;
+
+/// 计算平均帧时间使用的最近帧数
+const FRAME_SAMPLE_COUNT: usize = 120;
+/// 文本刷新间隔（秒），避免每帧重建文本影响测量
+const REFRESH_INTERVAL: f32 = 0.25;
+
+/// 帧时间统计
+#[derive(Resource, Default)]
+struct FrameStats {
+    /// 最近的帧时间（秒）
+    samples: VecDeque<f32>,
+    /// 距上次刷新文本的时长
+    since_refresh: f32,
+}
+
+impl FrameStats {
+    /// 平均帧时间（秒）
+    fn average(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().sum::<f32>() / self.samples.len() as f32
+    }
+
+    /// 最近窗口内的最长帧时间（秒）
+    fn worst(&self) -> f32 {
+        self.samples.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+/// 性能统计文本
+#[derive(Component)]
+struct StatsText;
+
+/// 性能统计插件
+pub struct StatsOverlayPlugin;
+
+impl Plugin for StatsOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameStats>()
+            .add_systems(Startup, spawn_stats_text)
+            .add_systems(Update, (toggle_stats, update_stats).chain());
+    }
+}
+
+/// 创建性能统计文本（默认隐藏）
+fn spawn_stats_text(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            ..Default::default()
+        },
+        Text::new(""),
+        TextFont {
+            font_size: 16.0,
+            ..Default::default()
+        },
+        TextColor(Color::srgb(0.4, 1.0, 0.4)),
+        GlobalZIndex(20),
+        Visibility::Hidden,
+        StatsText,
+    ));
+}
+
+/// 切换性能统计显示
+fn toggle_stats(
+    mut controls: MessageReader<ControlMessage>,
+    mut q_text: Query<&mut Visibility, With<StatsText>>,
+) {
+    if !ControlMessage::received(&mut controls, ControlMessage::ToggleStats) {
+        return;
+    }
+    for mut vis in &mut q_text {
+        *vis = match *vis {
+            Visibility::Hidden => Visibility::Visible,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+/// 记录帧时间并定期刷新统计文本
+fn update_stats(
+    time: Res<Time>,
+    mut stats: ResMut<FrameStats>,
+    pool: Res<NotePoolState>,
+    q_sprites: Query<&ViewVisibility, With<Sprite>>,
+    mut q_text: Query<(&mut Text, &Visibility), With<StatsText>>,
+) {
+    let delta = time.delta_secs();
+    stats.samples.push_back(delta);
+    if stats.samples.len() > FRAME_SAMPLE_COUNT {
+        stats.samples.pop_front();
+    }
+    stats.since_refresh += delta;
+
+    let Ok((mut text, vis)) = q_text.single_mut() else {
+        return;
+    };
+    if *vis == Visibility::Hidden || stats.since_refresh < REFRESH_INTERVAL {
+        return;
+    }
+    stats.since_refresh = 0.0;
+
+    let average = stats.average();
+    let fps = if average > 0.0 { 1.0 / average } else { 0.0 };
+    let sprites = q_sprites.iter().filter(|v| v.get()).count();
+    text.0 = format!(
+        "FPS {fps:.0}\nFRAME {:.2}ms (MAX {:.2}ms)\nNOTES {}\nSPRITES {sprites}",
+        average * 1000.0,
+        stats.worst() * 1000.0,
+        pool.active_count()
+    );
+}
@@ -1,11 +1,95 @@
 //! 时间管理插件
 //!
-//! 提供全局时间戳管理和更新
+//! 提供全局时间戳管理和更新，并负责暂停时冻结游戏时钟、按播放速率缩放游戏时钟
 
 use bevy::prelude::*;
-use gametime::TimeStamp;
+use gametime::{TimeSpan, TimeStamp};
 
-use crate::resources::NowStamp;
+use crate::plugins::input_handler::ControlMessage;
+use crate::resources::{ExecArgs, NowStamp};
+use crate::state::AppState;
+
+/// 播放速率下限
+const MIN_RATE: f64 = 0.5;
+/// 播放速率上限
+const MAX_RATE: f64 = 2.0;
+/// 每次按键调整播放速率的步长
+const RATE_STEP: f64 = 0.05;
+
+/// 暂停状态
+#[derive(Resource, Debug, Default)]
+pub struct PauseState {
+    /// 暂停开始的实际时刻（未暂停时为 `None`）
+    paused_at: Option<TimeStamp>,
+    /// 累计暂停时长
+    paused_total: TimeSpan,
+}
+
+impl PauseState {
+    /// 是否处于暂停状态
+    #[must_use]
+    pub const fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+}
+
+/// 播放速率
+///
+/// 游戏时钟按此速率推进，判定窗口以游戏时间计算，因此随速率等比缩放
+#[derive(Resource, Debug)]
+pub struct PlaybackRate {
+    /// 当前速率
+    rate: f64,
+    /// 速率生效时的实际时刻（已扣除暂停时长）
+    anchor_real: TimeStamp,
+    /// 速率生效时的游戏时刻
+    anchor_game: TimeStamp,
+}
+
+impl FromWorld for PlaybackRate {
+    fn from_world(world: &mut World) -> Self {
+        let rate = world
+            .get_resource::<ExecArgs>()
+            .map_or(1.0, |args| args.rate)
+            .clamp(MIN_RATE, MAX_RATE);
+        let now = TimeStamp::now();
+        Self {
+            rate,
+            anchor_real: now,
+            anchor_game: now,
+        }
+    }
+}
+
+impl PlaybackRate {
+    /// 当前速率
+    #[must_use]
+    pub const fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// 将实际时刻换算为游戏时刻
+    fn game_time(&self, real: TimeStamp) -> TimeStamp {
+        let elapsed = real
+            .checked_elapsed_since(self.anchor_real)
+            .unwrap_or(TimeSpan::ZERO);
+        self.anchor_game + TimeSpan::from_duration(elapsed.as_duration().mul_f64(self.rate))
+    }
+}
+
+/// 设置播放速率消息
+#[derive(Message, Clone, Copy, Debug)]
+pub struct SetPlaybackRateMessage {
+    /// 目标速率（1.0 为原速）
+    pub rate: f64,
+}
+
+/// 时间系统集合
+#[derive(SystemSet, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum TimeSystemSet {
+    /// 更新当前时间戳（渲染依赖此后的时间戳外推音符位置）
+    NowStamp,
+}
 
 /// 时间管理插件
 pub struct TimeSystemPlugin;
@@ -13,11 +97,111 @@ pub struct TimeSystemPlugin;
 impl Plugin for TimeSystemPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<NowStamp>()
-            .add_systems(Update, update_now_stamp);
+            .init_resource::<PauseState>()
+            .init_resource::<PlaybackRate>()
+            .add_message::<SetPlaybackRateMessage>()
+            .add_systems(
+                Update,
+                (
+                    handle_pause_control.run_if(in_state(AppState::Playing)),
+                    adjust_rate_by_keys,
+                    apply_rate_messages,
+                    update_now_stamp,
+                )
+                    .chain()
+                    .in_set(TimeSystemSet::NowStamp),
+            );
+    }
+}
+
+/// 计算两个时间戳之间的有符号秒数（`later - earlier`）
+#[must_use]
+pub fn signed_secs(later: TimeStamp, earlier: TimeStamp) -> f64 {
+    if later >= earlier {
+        later.elapsed_since(earlier).as_secs_f64()
+    } else {
+        -earlier.elapsed_since(later).as_secs_f64()
+    }
+}
+
+/// 当前实际时刻对应的游戏时刻（暂停期间停在暂停时刻）
+///
+/// 输入读取时用它记录发生时刻，判定不受逻辑更新间隔影响
+#[must_use]
+pub fn current_game_time(pause: &PauseState, rate: &PlaybackRate) -> TimeStamp {
+    let real = pause.paused_at.unwrap_or_else(TimeStamp::now) - pause.paused_total;
+    rate.game_time(real)
+}
+
+/// 处理暂停/继续控制消息
+fn handle_pause_control(
+    mut controls: MessageReader<ControlMessage>,
+    mut pause: ResMut<PauseState>,
+) {
+    if !ControlMessage::received(&mut controls, ControlMessage::TogglePause) {
+        return;
+    }
+    let now = TimeStamp::now();
+    if let Some(paused_at) = pause.paused_at.take() {
+        pause.paused_total = pause.paused_total + now.elapsed_since(paused_at);
+        println!("▶ 继续");
+    } else {
+        pause.paused_at = Some(now);
+        println!("⏸ 暂停");
+    }
+}
+
+/// 按键调整播放速率（F5 减速，F6 加速）
+fn adjust_rate_by_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    rate: Res<PlaybackRate>,
+    mut messages: MessageWriter<SetPlaybackRateMessage>,
+) {
+    if keys.just_pressed(KeyCode::F5) {
+        messages.write(SetPlaybackRateMessage {
+            rate: rate.rate - RATE_STEP,
+        });
+    }
+    if keys.just_pressed(KeyCode::F6) {
+        messages.write(SetPlaybackRateMessage {
+            rate: rate.rate + RATE_STEP,
+        });
+    }
+}
+
+/// 应用播放速率消息
+///
+/// 以当前游戏时刻为新的锚点，使速率切换前后游戏时钟连续
+fn apply_rate_messages(
+    mut messages: MessageReader<SetPlaybackRateMessage>,
+    mut rate: ResMut<PlaybackRate>,
+    pause: Res<PauseState>,
+) {
+    for message in messages.read() {
+        let new_rate = message.rate.clamp(MIN_RATE, MAX_RATE);
+        if (new_rate - rate.rate).abs() < f64::EPSILON {
+            continue;
+        }
+        // 按旧速率换算出此刻的游戏时刻再切换，上一帧的时间戳会落后最多一帧
+        let real_now = pause.paused_at.unwrap_or_else(TimeStamp::now) - pause.paused_total;
+        rate.anchor_game = rate.game_time(real_now);
+        rate.anchor_real = real_now;
+        rate.rate = new_rate;
+        println!("⏩ 播放速率: {new_rate:.2}x");
     }
 }
 
 /// 更新当前时间戳
-fn update_now_stamp(mut now_stamp: ResMut<NowStamp>) {
-    now_stamp.0 = TimeStamp::now();
+///
+/// 暂停期间保持不变，继续后扣除累计暂停时长，使游戏时钟连续；
+/// 非暂停时按播放速率推进
+fn update_now_stamp(
+    mut now_stamp: ResMut<NowStamp>,
+    pause: Res<PauseState>,
+    rate: Res<PlaybackRate>,
+) {
+    if pause.is_paused() {
+        return;
+    }
+    now_stamp.0 = current_game_time(&pause, &rate);
 }
@@ -0,0 +1,383 @@
+//! 窗口模式插件
+//!
+//! 按 F11 在窗口与无边框全屏之间切换，并将选择保存到配置；
+//! 启动时按配置设置呈现模式（垂直同步）、更新频率与多重采样抗锯齿，
+//! 窗口表面不支持配置的呈现模式时回退为自动模式；
+//! 窗口在后台时可停止绘制以节省 GPU；加载谱面后在窗口标题中显示曲名；
+//! 窗口图标由代码生成，不依赖图片资源
+
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use bevy::{
+    ecs::system::NonSendMarker,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        Render, RenderApp,
+        render_resource::TextureFormat,
+        renderer::{RenderAdapter, RenderInstance},
+        view::{ExtractedWindows, Msaa, create_surfaces},
+    },
+    window::{
+        MonitorSelection, PresentMode, PrimaryWindow, RawHandleWrapper, WindowCreated,
+        WindowFocused, WindowMode, WindowPosition,
+    },
+    winit::{UpdateMode, WINIT_WINDOWS, WinitSettings},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, SYS_CONFIG_PATH, SysConfig};
+use crate::plugins::bms_processor::BmsProcessorResource;
+use crate::plugins::input_handler::ControlMessage;
+
+/// 未加载谱面时的窗口标题
+const APP_TITLE: &str = "Nebula Tunes";
+
+/// 窗口图标边长（像素）
+const ICON_SIZE: u32 = 32;
+
+/// 设计分辨率宽度（与默认窗口大小一致），画面按此缩放
+pub const VIEW_WIDTH: f32 = 1280.0;
+/// 设计分辨率高度
+pub const VIEW_HEIGHT: f32 = 720.0;
+
+/// 呈现模式设置
+///
+/// 开启垂直同步不会撕裂但会增加最多一帧的显示延迟；关闭时延迟最低但可能撕裂。
+/// `fifo` 所有平台都支持；`mailbox` 与 `immediate` 需要驱动支持，
+/// 窗口表面不支持时分别回退为 `auto_vsync` 与 `auto_no_vsync`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentModeSetting {
+    /// 垂直同步，优先允许掉帧时立即显示（`FifoRelaxed`），不支持时使用 `Fifo`
+    AutoVsync,
+    /// 关闭垂直同步，依次尝试 `Immediate`、`Mailbox`、`Fifo`
+    AutoNoVsync,
+    /// 严格垂直同步，延迟最高但从不撕裂（默认）
+    #[default]
+    Fifo,
+    /// 不撕裂且不阻塞渲染，只显示最新一帧，延迟低于 `Fifo`
+    Mailbox,
+    /// 立即显示，延迟最低，可能撕裂
+    Immediate,
+}
+
+impl PresentModeSetting {
+    /// 对应的呈现模式
+    const fn present_mode(self) -> PresentMode {
+        match self {
+            Self::AutoVsync => PresentMode::AutoVsync,
+            Self::AutoNoVsync => PresentMode::AutoNoVsync,
+            Self::Fifo => PresentMode::Fifo,
+            Self::Mailbox => PresentMode::Mailbox,
+            Self::Immediate => PresentMode::Immediate,
+        }
+    }
+}
+
+/// 需要检查表面支持的呈现模式：返回对应的 wgpu 呈现模式与不支持时的回退模式
+const fn checked_present_mode(mode: PresentMode) -> Option<(wgpu::PresentMode, PresentMode)> {
+    match mode {
+        PresentMode::Mailbox => Some((wgpu::PresentMode::Mailbox, PresentMode::AutoVsync)),
+        PresentMode::Immediate => Some((wgpu::PresentMode::Immediate, PresentMode::AutoNoVsync)),
+        _ => None,
+    }
+}
+
+/// 窗口模式插件
+pub struct WindowModePlugin;
+
+impl Plugin for WindowModePlugin {
+    fn build(&self, app: &mut App) {
+        let (tick_hz, background_throttle) = app
+            .world()
+            .get_resource::<SysConfig>()
+            .map_or((0, false), |c| {
+                (c.video.tick_hz, c.video.background_throttle)
+            });
+        app.insert_resource(winit_settings(tick_hz, background_throttle))
+            .add_systems(
+                Update,
+                (
+                    toggle_fullscreen,
+                    apply_msaa,
+                    update_window_title,
+                    set_window_icon,
+                    pause_rendering_when_unfocused.run_if(move || background_throttle),
+                ),
+            );
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_systems(
+                Render,
+                fallback_unsupported_present_mode.before(create_surfaces),
+            );
+        }
+    }
+}
+
+/// 根据配置创建主窗口
+#[must_use]
+pub fn primary_window(config: &SysConfig) -> Window {
+    Window {
+        title: APP_TITLE.to_string(),
+        mode: window_mode(config.display.fullscreen),
+        present_mode: config.video.present_mode.present_mode(),
+        ..Default::default()
+    }
+}
+
+/// 生成窗口图标的 RGBA 像素：紫蓝渐变的圆形底色上一条横向音符
+fn icon_rgba() -> Vec<u8> {
+    let size = ICON_SIZE as f32;
+    let center = size / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let distance = (px - center).hypot(py - center);
+            // 圆形边缘按覆盖比例淡出
+            let alpha = (center - distance).clamp(0.0, 1.0);
+            let t = py / size;
+            let mut color = [0.23 - 0.07 * t, 0.11 + 0.37 * t, 0.44 + 0.43 * t];
+            let on_note = (8..24).contains(&x) && (18..22).contains(&y);
+            if on_note {
+                color = [0.94, 0.94, 1.0];
+            }
+            rgba.extend(color.map(|c| (c * 255.0).round() as u8));
+            rgba.push((alpha * 255.0).round() as u8);
+        }
+    }
+    rgba
+}
+
+/// 主窗口创建后设置窗口图标
+fn set_window_icon(
+    _marker: NonSendMarker,
+    mut created: MessageReader<WindowCreated>,
+    q_primary: Query<(), With<PrimaryWindow>>,
+) {
+    for event in created.read() {
+        if !q_primary.contains(event.window) {
+            continue;
+        }
+        let icon = match winit::window::Icon::from_rgba(icon_rgba(), ICON_SIZE, ICON_SIZE) {
+            Ok(icon) => icon,
+            Err(e) => {
+                warn!("窗口图标无效: {e}");
+                return;
+            }
+        };
+        WINIT_WINDOWS.with_borrow(|windows| {
+            if let Some(window) = windows.get_window(event.window) {
+                window.set_window_icon(Some(icon));
+            }
+        });
+    }
+}
+
+/// 按更新频率生成事件循环设置
+///
+/// 获得焦点时按频率定时更新，并在键盘等窗口事件到达时立即更新。
+/// 垂直同步下每次更新还要等待画面刷新，此时的更新间隔由刷新率决定。
+/// 后台停止绘制时失去焦点也保持同样的频率，使按键音与判定不受影响；
+/// 否则失去焦点时降至 60Hz 以节省 CPU
+fn winit_settings(tick_hz: u32, background_throttle: bool) -> WinitSettings {
+    let focused_mode = if tick_hz == 0 {
+        UpdateMode::Continuous
+    } else {
+        UpdateMode::Reactive {
+            wait: Duration::from_secs_f64(1.0 / f64::from(tick_hz)),
+            react_to_device_events: false,
+            react_to_user_events: true,
+            react_to_window_events: true,
+        }
+    };
+    let unfocused_mode = if background_throttle {
+        focused_mode
+    } else {
+        UpdateMode::reactive_low_power(Duration::from_secs_f64(1.0 / 60.0))
+    };
+    WinitSettings {
+        focused_mode,
+        unfocused_mode,
+    }
+}
+
+/// 窗口表面不支持提取的呈现模式时改用回退模式（在渲染世界中运行）
+///
+/// 必须在配置表面之前完成，否则 wgpu 会因不支持的呈现模式报错。
+/// 主世界中的呈现模式只在启动时设置，每帧提取后都重新替换并清除变更标记
+fn fallback_unsupported_present_mode(
+    #[cfg(any(target_os = "macos", target_os = "ios"))] _marker: NonSendMarker,
+    mut windows: ResMut<ExtractedWindows>,
+    instance: Res<RenderInstance>,
+    adapter: Res<RenderAdapter>,
+    mut supported: Local<HashMap<(Entity, PresentMode), bool>>,
+) {
+    for window in windows.windows.values_mut() {
+        let Some((wanted, fallback)) = checked_present_mode(window.present_mode) else {
+            continue;
+        };
+        let is_supported = *supported
+            .entry((window.entity, window.present_mode))
+            .or_insert_with(|| {
+                let ok = surface_supports(&instance, &adapter, &window.handle, wanted);
+                if !ok {
+                    eprintln!(
+                        "显卡不支持 {:?} 呈现模式，已回退为 {fallback:?}",
+                        window.present_mode
+                    );
+                }
+                ok
+            });
+        if !is_supported {
+            window.present_mode = fallback;
+            window.present_mode_changed = false;
+        }
+    }
+}
+
+/// 为窗口创建临时表面并查询是否支持指定的呈现模式，无法创建表面时视为支持
+fn surface_supports(
+    instance: &RenderInstance,
+    adapter: &RenderAdapter,
+    handle: &RawHandleWrapper,
+    mode: wgpu::PresentMode,
+) -> bool {
+    let target = wgpu::SurfaceTargetUnsafe::RawHandle {
+        raw_display_handle: handle.get_display_handle(),
+        raw_window_handle: handle.get_window_handle(),
+    };
+    // SAFETY: 提取的窗口句柄在窗口存在期间始终有效，临时表面只用于查询能力，随即释放
+    let Ok(surface) = (unsafe { instance.create_surface_unsafe(target) }) else {
+        return true;
+    };
+    surface
+        .get_capabilities(adapter)
+        .present_modes
+        .contains(&mode)
+}
+
+/// 为新建的相机设置配置的多重采样数
+///
+/// 采样数无效或显卡不支持时回退为关闭
+fn apply_msaa(
+    mut commands: Commands,
+    q_cameras: Query<Entity, Added<Camera>>,
+    config: Res<SysConfig>,
+    adapter: Option<Res<RenderAdapter>>,
+    mut resolved: Local<Option<Msaa>>,
+) {
+    if q_cameras.is_empty() {
+        return;
+    }
+    let msaa = *resolved.get_or_insert_with(|| {
+        let samples = config.video.msaa;
+        if !matches!(samples, 1 | 2 | 4 | 8) {
+            eprintln!("无效的 MSAA 采样数 {samples}，已关闭抗锯齿");
+            return Msaa::Off;
+        }
+        let supported = adapter.is_none_or(|adapter| {
+            adapter
+                .get_texture_format_features(TextureFormat::bevy_default())
+                .flags
+                .sample_count_supported(samples)
+        });
+        if !supported {
+            eprintln!("显卡不支持 {samples}x MSAA，已关闭抗锯齿");
+            return Msaa::Off;
+        }
+        Msaa::from_samples(samples)
+    });
+    for entity in &q_cameras {
+        commands.entity(entity).insert(msaa);
+    }
+}
+
+/// 主窗口失去焦点时停用所有相机，重新获得焦点时恢复
+fn pause_rendering_when_unfocused(
+    mut focus_events: MessageReader<WindowFocused>,
+    q_primary: Query<(), With<PrimaryWindow>>,
+    mut q_cameras: Query<&mut Camera>,
+) {
+    let Some(focused) = focus_events
+        .read()
+        .filter(|event| q_primary.contains(event.window))
+        .last()
+        .map(|event| event.focused)
+    else {
+        return;
+    };
+    for mut camera in &mut q_cameras {
+        camera.is_active = focused;
+    }
+}
+
+/// 加载谱面后将曲名与艺术家加入窗口标题，卸载后恢复
+fn update_window_title(
+    status: Option<Res<BmsProcessorResource>>,
+    mut q_window: Query<&mut Window, With<PrimaryWindow>>,
+    mut loaded: Local<bool>,
+) {
+    let added = status.as_ref().is_some_and(DetectChanges::is_added);
+    let removed = *loaded && status.is_none();
+    *loaded = status.is_some();
+    if !added && !removed {
+        return;
+    }
+    let Ok(mut window) = q_window.single_mut() else {
+        return;
+    };
+    window.title = status
+        .as_ref()
+        .and_then(|status| {
+            let header = &status.bms.header;
+            chart_title(header.title.as_deref(), header.artist.as_deref())
+        })
+        .map_or_else(
+            || APP_TITLE.to_string(),
+            |title| format!("{title} - {APP_TITLE}"),
+        );
+}
+
+/// 谱面的“曲名 / 艺术家”，没有曲名时返回 `None`
+fn chart_title(title: Option<&str>, artist: Option<&str>) -> Option<String> {
+    let title = title?;
+    Some(artist.map_or_else(|| title.to_string(), |artist| format!("{title} / {artist}")))
+}
+
+/// 全屏开关对应的窗口模式
+const fn window_mode(fullscreen: bool) -> WindowMode {
+    if fullscreen {
+        WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+    } else {
+        WindowMode::Windowed
+    }
+}
+
+/// 切换全屏并保存到配置，回到窗口模式时恢复设计分辨率并居中
+fn toggle_fullscreen(
+    mut controls: MessageReader<ControlMessage>,
+    mut q_window: Query<&mut Window, With<PrimaryWindow>>,
+    mut config: ResMut<SysConfig>,
+) {
+    if !ControlMessage::received(&mut controls, ControlMessage::ToggleFullscreen) {
+        return;
+    }
+    let Ok(mut window) = q_window.single_mut() else {
+        return;
+    };
+
+    let fullscreen = matches!(window.mode, WindowMode::Windowed);
+    window.mode = window_mode(fullscreen);
+    if !fullscreen {
+        window.resolution.set(VIEW_WIDTH, VIEW_HEIGHT);
+        window.position = WindowPosition::Centered(MonitorSelection::Current);
+    }
+    println!("🖥 {}", if fullscreen { "全屏" } else { "窗口" });
+
+    config.display.fullscreen = fullscreen;
+    if let Err(e) = config::save_sys(Path::new(SYS_CONFIG_PATH), &config) {
+        eprintln!("{e:#}");
+    }
+}
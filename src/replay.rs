@@ -0,0 +1,270 @@
+//! 回放文件模块
+//!
+//! 负责回放文件的读取与保存
+//!
+//! 文件格式（小端序）：4 字节魔数 `NTRP`、1 字节版本号，之后为文件头：
+//! 1 字节长度加谱面摘要、8 字节 `#RANDOM` 种子、1 字节轨道变换、1 字节皿是否参与变换、
+//! 8 字节轨道变换种子、2 字节轨道数加每条轨道变换后的 2 字节轨道索引；
+//! 其余每条记录依次为 8 字节有符号纳秒偏移、2 字节轨道索引、2 字节输入来源序号、1 字节按下标志
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use crate::lane::LaneModifier;
+
+/// 文件魔数
+const MAGIC: &[u8; 4] = b"NTRP";
+/// 文件格式版本
+const VERSION: u8 = 3;
+/// 单条记录的字节数
+const RECORD_SIZE: usize = 13;
+
+/// 回放事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayEvent {
+    /// 相对谱面开始播放的偏移（纳秒）
+    pub offset_ns: i64,
+    /// 轨道索引
+    pub lane: u16,
+    /// 输入来源序号（录制时按来源首次出现的顺序编号，用于还原各来源的按下状态）
+    pub source: u16,
+    /// 是否按下
+    pub pressed: bool,
+}
+
+/// 回放文件头：还原录制时的谱面与轨道变换所需的信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayHeader {
+    /// 谱面内容摘要
+    pub chart_hash: String,
+    /// 选择 `#RANDOM` 分支使用的随机种子
+    pub chart_seed: i64,
+    /// 轨道变换
+    pub lane_modifier: Option<LaneModifier>,
+    /// 皿轨道是否参与变换
+    pub shuffle_scratch: bool,
+    /// 轨道变换的随机种子
+    pub shuffle_seed: u64,
+    /// 各轨道变换后的轨道
+    pub permutation: Vec<u16>,
+}
+
+/// 回放
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// 文件头
+    pub header: ReplayHeader,
+    /// 按录制顺序排列的事件
+    pub events: Vec<ReplayEvent>,
+}
+
+/// 轨道变换在文件中的编码
+const fn modifier_code(modifier: Option<LaneModifier>) -> u8 {
+    match modifier {
+        None => 0,
+        Some(LaneModifier::Mirror) => 1,
+        Some(LaneModifier::Random) => 2,
+        Some(LaneModifier::SRandom) => 3,
+    }
+}
+
+/// 从文件中的编码还原轨道变换
+fn modifier_from_code(code: u8) -> Result<Option<LaneModifier>> {
+    Ok(match code {
+        0 => None,
+        1 => Some(LaneModifier::Mirror),
+        2 => Some(LaneModifier::Random),
+        3 => Some(LaneModifier::SRandom),
+        _ => bail!("未知的轨道变换: {code}"),
+    })
+}
+
+/// 从字节切片头部依次读取定长字段
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// 读取 `len` 个字节
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let Some((head, rest)) = self.0.split_at_checked(len) else {
+            bail!("文件过短");
+        };
+        self.0 = rest;
+        Ok(head)
+    }
+
+    /// 读取 `N` 个字节
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let Some((head, rest)) = self.0.split_first_chunk::<N>() else {
+            bail!("文件过短");
+        };
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    /// 读取 1 字节
+    fn u8(&mut self) -> Result<u8> {
+        self.array::<1>().map(|[byte]| byte)
+    }
+
+    /// 读取 2 字节无符号整数
+    fn u16(&mut self) -> Result<u16> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    /// 读取 8 字节无符号整数
+    fn u64(&mut self) -> Result<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    /// 读取 8 字节有符号整数
+    fn i64(&mut self) -> Result<i64> {
+        self.array().map(i64::from_le_bytes)
+    }
+}
+
+/// 解析回放文件内容
+fn decode(bytes: &[u8]) -> Result<Replay> {
+    let mut reader = Reader(bytes);
+    if reader.array::<4>()? != *MAGIC || reader.u8()? != VERSION {
+        bail!("格式不支持");
+    }
+    let hash_len = reader.u8()?;
+    let chart_hash =
+        String::from_utf8(reader.bytes(hash_len as usize)?.to_vec()).context("谱面摘要无效")?;
+    let chart_seed = reader.i64()?;
+    let lane_modifier = modifier_from_code(reader.u8()?)?;
+    let shuffle_scratch = reader.u8()? != 0;
+    let shuffle_seed = reader.u64()?;
+    let lane_count = reader.u16()?;
+    let permutation = (0..lane_count)
+        .map(|_| reader.u16())
+        .collect::<Result<Vec<_>>>()?;
+    let header = ReplayHeader {
+        chart_hash,
+        chart_seed,
+        lane_modifier,
+        shuffle_scratch,
+        shuffle_seed,
+        permutation,
+    };
+
+    let body = reader.0;
+    if body.len() % RECORD_SIZE != 0 {
+        bail!("已损坏");
+    }
+    let events = body
+        .chunks_exact(RECORD_SIZE)
+        .map(|record| {
+            let mut record = Reader(record);
+            Ok(ReplayEvent {
+                offset_ns: record.i64()?,
+                lane: record.u16()?,
+                source: record.u16()?,
+                pressed: record.u8()? != 0,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Replay { header, events })
+}
+
+/// 生成回放文件内容
+fn encode(replay: &Replay) -> Vec<u8> {
+    let header = &replay.header;
+    let hash = header.chart_hash.as_bytes();
+    let hash = hash.get(..hash.len().min(u8::MAX as usize)).unwrap_or(hash);
+    let mut bytes = Vec::with_capacity(
+        MAGIC.len() + 64 + header.permutation.len() * 2 + replay.events.len() * RECORD_SIZE,
+    );
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.push(hash.len() as u8);
+    bytes.extend_from_slice(hash);
+    bytes.extend_from_slice(&header.chart_seed.to_le_bytes());
+    bytes.push(modifier_code(header.lane_modifier));
+    bytes.push(u8::from(header.shuffle_scratch));
+    bytes.extend_from_slice(&header.shuffle_seed.to_le_bytes());
+    bytes.extend_from_slice(&(header.permutation.len() as u16).to_le_bytes());
+    for lane in &header.permutation {
+        bytes.extend_from_slice(&lane.to_le_bytes());
+    }
+    for event in &replay.events {
+        bytes.extend_from_slice(&event.offset_ns.to_le_bytes());
+        bytes.extend_from_slice(&event.lane.to_le_bytes());
+        bytes.extend_from_slice(&event.source.to_le_bytes());
+        bytes.push(u8::from(event.pressed));
+    }
+    bytes
+}
+
+/// 读取回放文件
+///
+/// # Errors
+///
+/// 读取文件失败或格式不正确时返回错误
+pub fn load_replay(path: &Path) -> Result<Replay> {
+    let bytes = std::fs::read(path).with_context(|| format!("读取回放失败: {}", path.display()))?;
+    decode(&bytes).with_context(|| format!("回放文件无效: {}", path.display()))
+}
+
+/// 保存回放文件
+///
+/// # Errors
+///
+/// 写入文件失败时返回错误
+pub fn save_replay(path: &Path, replay: &Replay) -> Result<()> {
+    std::fs::write(path, encode(replay))
+        .with_context(|| format!("写入回放失败: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 含文件头与各种事件的回放
+    fn sample_replay() -> Replay {
+        Replay {
+            header: ReplayHeader {
+                chart_hash: "0123456789abcdef".to_string(),
+                chart_seed: -42,
+                lane_modifier: Some(LaneModifier::Random),
+                shuffle_scratch: true,
+                shuffle_seed: 0x1234_5678_9abc_def0,
+                permutation: vec![0, 3, 1, 2, 7, 5, 6, 4],
+            },
+            events: vec![
+                ReplayEvent {
+                    offset_ns: -5_000_000,
+                    lane: 1,
+                    source: 0,
+                    pressed: true,
+                },
+                ReplayEvent {
+                    offset_ns: 1_000_000_123,
+                    lane: 7,
+                    source: 2,
+                    pressed: false,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_header_and_events() {
+        let replay = sample_replay();
+        let decoded = decode(&encode(&replay)).expect("解析回放");
+        assert_eq!(decoded, replay);
+    }
+
+    #[test]
+    fn rejects_other_versions_and_truncated_files() {
+        let mut bytes = encode(&sample_replay());
+        let last = bytes.len() - 1;
+        assert!(decode(bytes.get(..last).unwrap_or_default()).is_err());
+        assert!(decode(bytes.get(..10).unwrap_or_default()).is_err());
+        if let Some(version) = bytes.get_mut(4) {
+            *version = 1;
+        }
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -8,13 +8,72 @@ use bevy::prelude::*;
 use clap::Parser;
 use gametime::TimeStamp;
 
+use crate::lane::LaneModifier;
+use crate::plugins::judge::GaugeKind;
+
 /// 命令行参数
+// 命令行开关均为布尔值
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser, Resource)]
 #[command(author, version, about, long_about = None)]
 pub struct ExecArgs {
     /// BMS文件路径
     #[arg(long)]
     pub bms_path: Option<PathBuf>,
+    /// 自动演奏模式（所有音符以 PERFECT 自动击中）
+    #[arg(long)]
+    pub autoplay: bool,
+    /// 判定校准模式（播放节拍器并统计按键偏差）
+    #[arg(long)]
+    pub calibrate: bool,
+    /// 演奏时按谱面 BPM 播放节拍器
+    #[arg(long)]
+    pub metronome: bool,
+    /// BGM 模式：按键音按谱面时间随 BGM 播放，击中时不再发声（判定不受影响）
+    #[arg(long)]
+    pub bgm_mode: bool,
+    /// 双人模式（启用 P2 侧轨道）
+    #[arg(long)]
+    pub double: bool,
+    /// 播放速率（1.0 为原速）
+    #[arg(long, default_value_t = 1.0)]
+    pub rate: f64,
+    /// 录制回放并在演奏结束或退出时写入该路径
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+    /// 播放回放文件代替键盘轨道输入
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+    /// 结算时将演奏结果以 JSON 写入该路径
+    #[arg(long, value_name = "PATH")]
+    pub results_out: Option<PathBuf>,
+    /// 检查谱面并输出问题后退出（不启动窗口）
+    #[arg(long, value_name = "PATH")]
+    pub check: Option<PathBuf>,
+    /// 以最快速度推进谱面并以合成输入判定，输出每帧与判定耗时及可持续的音符密度后退出（不启动窗口）
+    #[arg(long, value_name = "PATH")]
+    pub stress: Option<PathBuf>,
+    /// 列出谱面中每个 `#WAV` 定义解析到的音频文件后退出（不启动窗口）
+    #[arg(long, value_name = "PATH")]
+    pub list_wavs: Option<PathBuf>,
+    /// 播放谱面中指定编号（如 `0Z` 或 --list-wavs 输出的十进制编号）的按键音后退出（不启动窗口）
+    #[arg(long, num_args = 2, value_names = ["PATH", "ID"])]
+    pub play_wav: Option<Vec<String>>,
+    /// 以 JSON 输出检查结果（配合 --check 使用）
+    #[arg(long, requires = "check")]
+    pub json: bool,
+    /// 轨道变换（镜像、随机、S-RANDOM）
+    #[arg(long, value_enum)]
+    pub lane_modifier: Option<LaneModifier>,
+    /// 轨道变换时皿轨道也参与变换（默认固定）
+    #[arg(long)]
+    pub shuffle_scratch: bool,
+    /// 血条类型（未指定时使用配置）
+    #[arg(long, value_enum)]
+    pub gauge: Option<GaugeKind>,
+    /// 谱面 `#RANDOM` 分支的随机种子（指定后每次加载选中相同的分支，不影响轨道变换）
+    #[arg(long)]
+    pub seed: Option<i64>,
 }
 
 /// 当前时间戳
@@ -0,0 +1,165 @@
+//! 皮肤模块
+//!
+//! 负责皮肤文件 `skin.toml` 的读取，描述背景、音符、轨道、判定线与血条的颜色
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::lane::{KeyLayout, LaneKind};
+
+/// 皮肤文件路径
+pub const SKIN_PATH: &str = "skin.toml";
+
+/// 皮肤
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Skin {
+    /// 背景颜色（留空时使用引擎默认的清屏颜色）
+    pub background_color: Option<[f32; 3]>,
+    /// 绘制在轨道之后的背景图片（按设计分辨率拉伸）
+    pub background_image: Option<PathBuf>,
+    /// 音符颜色
+    pub note: LaneColors,
+    /// 轨道背景颜色
+    pub lane: LaneColors,
+    /// 轨道按下时的背景颜色
+    pub lane_pressed: [f32; 3],
+    /// 按键闪光颜色
+    pub lane_flash: [f32; 3],
+    /// 连击里程碑闪光颜色
+    pub combo_flash: [f32; 3],
+    /// 判定线颜色
+    pub judge_line: [f32; 3],
+    /// 血条颜色
+    pub gauge: GaugeColors,
+    /// 轨道与音符尺寸
+    pub field: FieldSize,
+    /// 音符出现后淡入的距离（像素，从可见区域顶端或轨道遮挡线起算），0 表示不淡入
+    pub fade_in: f32,
+}
+
+impl Default for Skin {
+    fn default() -> Self {
+        Self {
+            background_color: None,
+            background_image: None,
+            note: LaneColors {
+                scratch: [1.0, 0.35, 0.35],
+                white: [0.3, 0.7, 1.0],
+                black: [0.2, 0.45, 0.9],
+                lanes: Vec::new(),
+            },
+            lane: LaneColors {
+                scratch: [0.15, 0.15, 0.18],
+                white: [0.15, 0.15, 0.18],
+                black: [0.15, 0.15, 0.18],
+                lanes: Vec::new(),
+            },
+            lane_pressed: [0.3, 0.3, 0.4],
+            lane_flash: [0.6, 0.85, 1.0],
+            combo_flash: [1.0, 0.9, 0.5],
+            judge_line: [0.9, 0.9, 0.9],
+            gauge: GaugeColors::default(),
+            field: FieldSize::default(),
+            fade_in: 0.0,
+        }
+    }
+}
+
+/// 轨道与音符尺寸（设计分辨率下的像素）
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FieldSize {
+    /// 轨道宽度
+    pub lane_width: f32,
+    /// 皿轨道宽度（PMS 等没有皿的布局不使用）
+    pub scratch_width: f32,
+    /// 轨道间距
+    pub lane_gap: f32,
+    /// 音符高度
+    pub note_height: f32,
+}
+
+impl Default for FieldSize {
+    fn default() -> Self {
+        Self {
+            lane_width: 60.0,
+            scratch_width: 60.0,
+            lane_gap: 8.0,
+            note_height: 12.0,
+        }
+    }
+}
+
+/// 按轨道类型区分的颜色
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaneColors {
+    /// 皿轨道
+    pub scratch: [f32; 3],
+    /// 白键轨道
+    pub white: [f32; 3],
+    /// 黑键轨道
+    pub black: [f32; 3],
+    /// 按轨道索引覆盖的颜色（留空或超出时按轨道类型取色）
+    pub lanes: Vec<[f32; 3]>,
+}
+
+impl LaneColors {
+    /// 指定轨道的颜色
+    #[must_use]
+    pub fn color(&self, layout: KeyLayout, lane: usize) -> Color {
+        let rgb = self
+            .lanes
+            .get(lane)
+            .copied()
+            .unwrap_or_else(|| match layout.lane_kind(lane) {
+                LaneKind::Scratch => self.scratch,
+                LaneKind::White => self.white,
+                LaneKind::Black => self.black,
+            });
+        srgb(rgb)
+    }
+}
+
+/// 血条颜色
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GaugeColors {
+    /// 达成通关
+    pub clear: [f32; 3],
+    /// 未达成通关
+    pub failed: [f32; 3],
+}
+
+impl Default for GaugeColors {
+    fn default() -> Self {
+        Self {
+            clear: [0.4, 0.9, 1.0],
+            failed: [0.9, 0.3, 0.3],
+        }
+    }
+}
+
+/// 将 sRGB 数组转换为颜色
+#[must_use]
+pub const fn srgb([r, g, b]: [f32; 3]) -> Color {
+    Color::srgb(r, g, b)
+}
+
+/// 读取皮肤，文件不存在时返回默认皮肤
+///
+/// # Errors
+///
+/// 读取或解析皮肤文件失败时返回错误
+pub fn load_skin(path: &Path) -> Result<Skin> {
+    if !path.exists() {
+        return Ok(Skin::default());
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("读取皮肤失败: {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("解析皮肤失败: {}", path.display()))
+}
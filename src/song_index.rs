@@ -0,0 +1,209 @@
+//! 曲库索引模块
+//!
+//! 只读取谱面头信息（不解析谱面数据），并按路径与修改时间缓存到 `song_index.json`，
+//! 重新扫描时未修改的谱面直接使用缓存
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context, Result};
+use async_fs as afs;
+use futures_lite::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+use crate::plugins::bms_processor::decode_chart_text;
+
+/// 曲库索引文件路径
+pub const SONG_INDEX_PATH: &str = "song_index.json";
+
+/// 读取谱面头部时每次读取的字节数
+const HEADER_CHUNK_SIZE: usize = 16 * 1024;
+
+/// 谱面头信息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SongHeader {
+    /// 标题（`#TITLE`）
+    pub title: Option<String>,
+    /// 艺术家（`#ARTIST`）
+    pub artist: Option<String>,
+    /// 流派（`#GENRE`）
+    pub genre: Option<String>,
+    /// 难度等级（`#PLAYLEVEL`）
+    pub level: Option<u32>,
+    /// 血条总量（`#TOTAL`）
+    pub total: Option<f64>,
+}
+
+/// 索引条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    /// 谱面（或所在压缩包）的修改时间（自 UNIX 纪元起的纳秒数）
+    modified: u64,
+    /// 头信息
+    header: SongHeader,
+}
+
+/// 曲库索引
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SongIndex {
+    /// 谱面路径到索引条目的映射
+    entries: HashMap<PathBuf, IndexEntry>,
+}
+
+impl SongIndex {
+    /// 修改时间一致时返回缓存的头信息
+    #[must_use]
+    pub fn get(&self, path: &Path, modified: u64) -> Option<&SongHeader> {
+        self.entries
+            .get(path)
+            .filter(|entry| entry.modified == modified)
+            .map(|entry| &entry.header)
+    }
+
+    /// 记录头信息
+    pub fn insert(&mut self, path: PathBuf, modified: u64, header: SongHeader) {
+        self.entries.insert(path, IndexEntry { modified, header });
+    }
+}
+
+/// 读取曲库索引，文件不存在时返回空索引
+///
+/// # Errors
+///
+/// 读取或解析索引文件失败时返回错误
+pub fn load_index(path: &Path) -> Result<SongIndex> {
+    if !path.exists() {
+        return Ok(SongIndex::default());
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("读取曲库索引失败: {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("解析曲库索引失败: {}", path.display()))
+}
+
+/// 保存曲库索引
+///
+/// # Errors
+///
+/// 序列化或写入文件失败时返回错误
+pub fn save_index(path: &Path, index: &SongIndex) -> Result<()> {
+    let text = serde_json::to_string(index).context("序列化曲库索引失败")?;
+    std::fs::write(path, text).with_context(|| format!("写入曲库索引失败: {}", path.display()))
+}
+
+/// 谱面的修改时间（压缩包内的谱面取压缩包的修改时间）
+pub async fn modified_time(path: &Path) -> Option<u64> {
+    let file = archive::split_archive_path(path).map_or_else(|| path.to_path_buf(), |(zip, _)| zip);
+    let modified = afs::metadata(&file).await.ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
+/// 读取谱面头信息
+///
+/// 只读取到第一条通道数据之前；压缩包内的谱面复用已解析的压缩包索引
+///
+/// # Errors
+///
+/// 文件读取失败时返回错误
+pub async fn read_header(path: &Path) -> Result<SongHeader> {
+    let bytes = read_header_bytes(path).await?;
+    Ok(parse_header(&decode_chart_text(&bytes)))
+}
+
+/// 读取谱面开头到第一条通道数据（`#xxxyy:`）之前的内容
+async fn read_header_bytes(path: &Path) -> Result<Vec<u8>> {
+    if archive::split_archive_path(path).is_some() {
+        let mut bytes = archive::read_file(path).await?;
+        bytes.truncate(header_end(&bytes).unwrap_or(bytes.len()));
+        return Ok(bytes);
+    }
+    let mut file = afs::File::open(path)
+        .await
+        .with_context(|| format!("读取文件失败: {}", path.display()))?;
+    let mut bytes = Vec::new();
+    let mut chunk = vec![0; HEADER_CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut chunk)
+            .await
+            .with_context(|| format!("读取文件失败: {}", path.display()))?;
+        let Some(chunk) = chunk.get(..read).filter(|chunk| !chunk.is_empty()) else {
+            return Ok(bytes);
+        };
+        bytes.extend_from_slice(chunk);
+        if let Some(end) = header_end(&bytes) {
+            bytes.truncate(end);
+            return Ok(bytes);
+        }
+    }
+}
+
+/// 第一条通道数据行的起始位置（头信息在此之前结束），未出现时返回 `None`
+fn header_end(bytes: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    for line in bytes.split(|&b| b == b'\n') {
+        let indent = line.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        if let Some([b'#', measure @ .., b':']) = line.get(indent..indent + 7)
+            && let Some((channel, measure)) = measure.split_last_chunk::<2>()
+            && measure.iter().all(u8::is_ascii_digit)
+            && channel.iter().all(u8::is_ascii_alphanumeric)
+        {
+            return Some(start);
+        }
+        start += line.len() + 1;
+    }
+    None
+}
+
+/// 从谱面文本中提取头信息（同一命令以第一次出现为准）
+fn parse_header(text: &str) -> SongHeader {
+    let mut header = SongHeader::default();
+    for line in text.lines() {
+        let Some((command, value)) = line.trim().split_once(|c: char| c.is_ascii_whitespace())
+        else {
+            continue;
+        };
+        let value = value.trim();
+        match command.to_ascii_uppercase().as_str() {
+            "#TITLE" => {
+                header.title.get_or_insert_with(|| value.to_string());
+            }
+            "#ARTIST" => {
+                header.artist.get_or_insert_with(|| value.to_string());
+            }
+            "#GENRE" => {
+                header.genre.get_or_insert_with(|| value.to_string());
+            }
+            "#PLAYLEVEL" if header.level.is_none() => header.level = value.parse().ok(),
+            "#TOTAL" if header.total.is_none() => header.total = value.parse().ok(),
+            _ => {}
+        }
+    }
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_ends_at_first_channel_line() {
+        let text = "*---- HEADER\r\n#TITLE song\r\n#WAV01 a.wav\r\n #00111:01\r\n#ARTIST late\r\n";
+        let end = header_end(text.as_bytes()).expect("找到通道数据");
+        let header = text.get(..end).unwrap_or_default();
+        assert!(header.ends_with("a.wav\r\n"));
+        let parsed = parse_header(header);
+        assert_eq!(parsed.title.as_deref(), Some("song"));
+        assert_eq!(parsed.artist, None);
+    }
+
+    #[test]
+    fn header_without_channel_lines_has_no_end() {
+        // 命令名与通道数据前缀相似但不是通道数据
+        let text = "#TITLE a\n#BPM01 150\n#WAVZZ b.wav\n#00111";
+        assert_eq!(header_end(text.as_bytes()), None);
+    }
+}
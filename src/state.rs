@@ -0,0 +1,21 @@
+//! 应用状态定义
+//!
+//! 定义游戏流程中的各个阶段
+
+use bevy::prelude::*;
+
+/// 应用状态
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AppState {
+    /// 选曲
+    SongSelect,
+    /// 游戏中
+    #[default]
+    Playing,
+    /// 结算画面
+    Result,
+    /// 判定校准
+    Calibration,
+    /// 键位设置
+    KeyConfig,
+}
@@ -0,0 +1,244 @@
+//! 压力测试模块
+//!
+//! 不启动窗口，加载谱面后以逻辑帧率的步长推进谱面时间、不做任何等待，
+//! 统计每帧推进处理器与收集可见音符的耗时，估算可持续处理的音符密度。
+//! 同时按固定的时间偏差为每个音符生成按下与松开输入，交给判定逻辑处理并单独统计判定耗时
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bms_rs::chart_process::prelude::*;
+use futures_lite::future;
+use gametime::{TimeSpan, TimeStamp};
+
+use crate::lane::KeyLayout;
+use crate::plugins::bms_processor::{
+    ChartNote, LoadedBms, VISIBLE_TRAVEL, load_bms_and_collect_paths,
+};
+use crate::plugins::input_handler::{InputSource, LaneInputMessage};
+use crate::plugins::judge::{
+    GameState, GaugeKind, GaugeProfiles, JudgeLevel, JudgeParams, NoteCrossedEvent, UpcomingNote,
+};
+
+/// 最多推进的谱面时长
+const MAX_SPAN: Duration = Duration::from_secs(3600);
+
+/// 合成输入相对音符时刻的偏差（毫秒，负值为偏早），按音符顺序循环使用，覆盖各判定等级与漏击
+const INPUT_JITTER_MS: [i64; 7] = [0, -15, 8, 35, -70, 150, 260];
+/// 合成输入按住的时长
+const INPUT_HOLD: Duration = Duration::from_millis(20);
+
+/// 合成输入：相对谱面开始的时刻与对应的轨道输入（时刻在推进时填入）
+struct SyntheticInput {
+    /// 相对谱面开始的时刻
+    at: Duration,
+    /// 轨道输入
+    input: LaneInputMessage,
+}
+
+/// 为每个可判定的音符生成一次按下与松开，按时刻排列
+///
+/// 每个音符使用单独的输入来源，同一轨道上相邻音符的输入交错时也都是新的按下
+fn synthetic_inputs(notes: &[ChartNote], layout: KeyLayout) -> Vec<SyntheticInput> {
+    let mut inputs = Vec::with_capacity(notes.len() * 2);
+    let jitters = INPUT_JITTER_MS.iter().cycle();
+    for ((index, note), &jitter) in notes.iter().enumerate().zip(jitters) {
+        let Some(lane) = layout.key_to_lane(note.side, note.key) else {
+            continue;
+        };
+        let shift = Duration::from_millis(jitter.unsigned_abs());
+        let press_at = if jitter < 0 {
+            note.at.saturating_sub(shift)
+        } else {
+            note.at + shift
+        };
+        let source = InputSource::Replay(index as u16);
+        for (at, pressed) in [(press_at, true), (press_at + INPUT_HOLD, false)] {
+            inputs.push(SyntheticInput {
+                at,
+                input: LaneInputMessage {
+                    lane,
+                    source,
+                    pressed,
+                    time: None,
+                },
+            });
+        }
+    }
+    inputs.sort_by_key(|input| input.at);
+    inputs
+}
+
+/// 升序耗时中第 `percent` 百分位的值
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let index = sorted.len().saturating_sub(1) * percent / 100;
+    sorted.get(index).copied().unwrap_or_default()
+}
+
+/// 以最快速度推进谱面并输出每帧耗时统计，返回进程退出码
+#[must_use]
+pub fn run(chart_path: &Path, layout: KeyLayout, tick_hz: u32, seed: Option<i64>) -> i32 {
+    let loaded = future::block_on(load_bms_and_collect_paths(
+        chart_path.to_path_buf(),
+        layout,
+        VISIBLE_TRAVEL,
+        seed,
+    ));
+    let LoadedBms {
+        mut processor,
+        notes: chart_notes,
+        ..
+    } = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{e:#}");
+            return 1;
+        }
+    };
+
+    let note_times: HashMap<ChartEventId, Duration> =
+        chart_notes.iter().map(|note| (note.id, note.at)).collect();
+    let inputs = synthetic_inputs(&chart_notes, layout);
+    let mut pending_inputs = inputs.iter().peekable();
+    let params = JudgeParams::default();
+    let gauge_kind = GaugeKind::default();
+    let mut state = GameState::new(
+        layout.lane_count(),
+        gauge_kind,
+        GaugeProfiles::default().get(gauge_kind),
+        None,
+    );
+
+    let step = Duration::from_secs(1) / tick_hz.max(1);
+    let max_ticks = (MAX_SPAN.as_nanos() / step.as_nanos()) as u32;
+    let start = TimeStamp::now();
+    processor.start_play(start);
+
+    let note_time = |id: ChartEventId| {
+        note_times
+            .get(&id)
+            .map(|at| start + TimeSpan::from_duration(*at))
+    };
+
+    let mut ticks = Vec::new();
+    let mut judge_ticks = Vec::new();
+    let mut notes: usize = 0;
+    let mut judged_inputs: usize = 0;
+    let mut peak_visible = 0;
+    let mut crossed = Vec::new();
+    let wall_start = Instant::now();
+    for tick in 1..=max_ticks {
+        let elapsed = step * tick;
+        let now = start + TimeSpan::from_duration(elapsed);
+        let tick_start = Instant::now();
+        let mut ended = false;
+        crossed.clear();
+        for evp in processor.update(now) {
+            match evp.event() {
+                ChartEvent::Note {
+                    side, key, wav_id, ..
+                } => {
+                    if let Some(lane) = layout.key_to_lane(*side, *key) {
+                        notes += 1;
+                        crossed.push(NoteCrossedEvent {
+                            event_id: evp.id(),
+                            lane,
+                            wav_id: *wav_id,
+                            crossed_at: note_time(evp.id()).unwrap_or(now),
+                        });
+                    }
+                }
+                ChartEvent::ChartEnd => ended = true,
+                _ => {}
+            }
+        }
+        // 与渲染一致，每帧收集全部可见事件
+        let visible: Vec<_> = processor.visible_events().map(|(evp, _)| evp).collect();
+        peak_visible = peak_visible.max(visible.len());
+
+        // 与判定插件一致：先登记越过判定线的音符，再判定本帧的输入，最后结算漏击
+        let judge_start = Instant::now();
+        for note in &crossed {
+            state.queue_crossed(note);
+        }
+        let upcoming: Vec<UpcomingNote> = visible
+            .iter()
+            .filter_map(|evp| {
+                let ChartEvent::Note {
+                    side, key, wav_id, ..
+                } = evp.event()
+                else {
+                    return None;
+                };
+                Some(UpcomingNote {
+                    event_id: evp.id(),
+                    lane: layout.key_to_lane(*side, *key)?,
+                    wav_id: *wav_id,
+                    at: note_time(evp.id())?,
+                })
+            })
+            .collect();
+        while let Some(synthetic) = pending_inputs.next_if(|input| input.at <= elapsed) {
+            let input = LaneInputMessage {
+                time: Some(start + TimeSpan::from_duration(synthetic.at)),
+                ..synthetic.input
+            };
+            if state.judge_input(&input, now, &upcoming, &params).is_some() {
+                judged_inputs += 1;
+            }
+        }
+        state.sweep_missed(now, &params);
+        judge_ticks.push(judge_start.elapsed());
+
+        ticks.push(tick_start.elapsed());
+        if ended {
+            break;
+        }
+    }
+    let wall = wall_start.elapsed();
+
+    let chart_secs = (step * ticks.len() as u32).as_secs_f64();
+    ticks.sort_unstable();
+    judge_ticks.sort_unstable();
+    println!("📄 谱面: {}", chart_path.display());
+    println!(
+        "⏱ {} 帧（{tick_hz}Hz，谱面 {chart_secs:.1}s）用时 {:.3}s",
+        ticks.len(),
+        wall.as_secs_f64()
+    );
+    println!(
+        "📊 帧耗时 p50 {:?} | p99 {:?} | 最大 {:?}",
+        percentile(&ticks, 50),
+        percentile(&ticks, 99),
+        ticks.last().copied().unwrap_or_default()
+    );
+    println!(
+        "⚖ 判定耗时 p50 {:?} | p99 {:?} | 最大 {:?}（合成输入 {} 次，击中 {judged_inputs} 次）",
+        percentile(&judge_ticks, 50),
+        percentile(&judge_ticks, 99),
+        judge_ticks.last().copied().unwrap_or_default(),
+        inputs.len() / 2
+    );
+    println!(
+        "🎯 {}",
+        JudgeLevel::ALL
+            .iter()
+            .zip(state.judge_counts)
+            .map(|(level, count)| format!("{} {count}", level.label()))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    );
+    println!(
+        "🎹 音符 {notes}（平均 {:.1} NPS）| 同屏最多 {peak_visible} 个事件",
+        notes as f64 / chart_secs.max(f64::EPSILON)
+    );
+    println!(
+        "🚀 可持续 {:.0} NPS（{:.0} 倍速）",
+        notes as f64 / wall.as_secs_f64().max(f64::EPSILON),
+        chart_secs / wall.as_secs_f64().max(f64::EPSILON)
+    );
+    0
+}
@@ -0,0 +1,220 @@
+//! 按键音试听模块
+//!
+//! 不启动窗口，加载谱面并列出每个 `#WAV` 定义解析到的音频文件（可确认替换扩展名后选中的文件）；
+//! 指定编号时只解码并播放该按键音，播放结束后退出
+
+use std::{path::Path, time::Duration};
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    asset::{AssetPath, AssetPlugin, UnapprovedPathMode},
+    prelude::*,
+};
+use bevy_kira_audio::{
+    AudioApp, AudioChannel, AudioControl, AudioPlugin,
+    prelude::{AudioInstance, AudioSource as KiraAudioSource, PlaybackState},
+};
+use bms_rs::chart_process::ChartProcessor;
+use futures_lite::future;
+
+use crate::archive;
+use crate::lane::KeyLayout;
+use crate::plugins::bms_processor::{LoadedBms, VISIBLE_TRAVEL, load_bms_and_collect_paths};
+
+/// 试听时的帧间隔
+const FRAME_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 试听音频通道
+#[derive(Resource)]
+struct WavPreviewChannel;
+
+/// 试听状态
+#[derive(Resource)]
+struct WavPreview {
+    /// 待播放的音频
+    source: Handle<KiraAudioSource>,
+    /// 开始播放后的实例
+    instance: Option<Handle<AudioInstance>>,
+}
+
+/// 谱面中 `#WAVxx` 的两位 36 进制编号（大写）
+fn base36_id(id: u64) -> String {
+    let digit =
+        |d: u64| char::from_digit((d % 36) as u32, 36).map_or('?', |c| c.to_ascii_uppercase());
+    [digit(id / 36), digit(id)].iter().collect()
+}
+
+/// 解析音频编号，可带 `#` 与 `WAV` 前缀（不区分大小写）
+///
+/// 两位编号按谱面中的 36 进制解析（如 `0Z`），其余按 `--list-wavs` 输出的十进制解析（如 `035`）
+fn parse_wav_id(id: &str) -> Option<u64> {
+    let id = id.trim_start_matches('#');
+    let id = match id.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("WAV") => id.get(3..)?,
+        _ => id,
+    };
+    if id.len() == 2 {
+        u64::from_str_radix(id, 36).ok()
+    } else {
+        id.parse().ok()
+    }
+}
+
+/// 加载谱面，失败时输出原因
+fn load_chart(chart_path: &Path, layout: KeyLayout, seed: Option<i64>) -> Option<LoadedBms> {
+    future::block_on(load_bms_and_collect_paths(
+        chart_path.to_path_buf(),
+        layout,
+        VISIBLE_TRAVEL,
+        seed,
+    ))
+    .map_err(|e| eprintln!("{e:#}"))
+    .ok()
+}
+
+/// 列出谱面中每个 `#WAV` 定义解析到的文件，返回进程退出码（有缺失文件时为 1）
+#[must_use]
+pub fn list(chart_path: &Path, layout: KeyLayout, seed: Option<i64>) -> i32 {
+    let Some(LoadedBms {
+        processor,
+        audio_paths,
+        ..
+    }) = load_chart(chart_path, layout, seed)
+    else {
+        return 1;
+    };
+
+    let mut wavs = processor.audio_files().into_iter().collect::<Vec<_>>();
+    wavs.sort_by_key(|(id, _)| *id);
+    println!("📄 谱面: {}", chart_path.display());
+    let mut missing: usize = 0;
+    for (id, declared) in &wavs {
+        match audio_paths.get(id) {
+            Some(path) => println!(
+                "🔊 #WAV{} ({:03}) {} -> {}",
+                base36_id(id.0 as u64),
+                id.0,
+                declared.display(),
+                path.display()
+            ),
+            None => {
+                missing += 1;
+                println!(
+                    "✗ #WAV{} ({:03}) {} -> 缺失",
+                    base36_id(id.0 as u64),
+                    id.0,
+                    declared.display()
+                );
+            }
+        }
+    }
+    println!("共 {} 个音频定义，缺失 {missing} 个", wavs.len());
+    i32::from(missing > 0)
+}
+
+/// 解码并播放指定编号（谱面中的 36 进制编号或 `--list-wavs` 输出的十进制编号）的按键音，
+/// 返回进程退出码
+#[must_use]
+pub fn play(chart_path: &Path, layout: KeyLayout, seed: Option<i64>, id: &str) -> i32 {
+    let Some(wanted) = parse_wav_id(id) else {
+        eprintln!("无效的音频编号: {id}");
+        return 1;
+    };
+    let Some(LoadedBms { audio_paths, .. }) = load_chart(chart_path, layout, seed) else {
+        return 1;
+    };
+    let Some(path) = audio_paths
+        .iter()
+        .find(|(wav_id, _)| wav_id.0 as u64 == wanted)
+        .map(|(_, path)| path.clone())
+    else {
+        eprintln!(
+            "✗ 谱面中没有可播放的 #WAV{} ({wanted:03})（未定义或文件缺失）",
+            base36_id(wanted)
+        );
+        return 1;
+    };
+    println!(
+        "🔊 #WAV{} ({wanted:03}) -> {}",
+        base36_id(wanted),
+        path.display()
+    );
+
+    let mut app = App::new();
+    archive::register_asset_sources(&mut app);
+    app.add_plugins((
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(FRAME_INTERVAL)),
+        AssetPlugin {
+            unapproved_path_mode: UnapprovedPathMode::Deny,
+            ..Default::default()
+        },
+        AudioPlugin,
+    ))
+    .add_audio_channel::<WavPreviewChannel>()
+    .add_systems(
+        Startup,
+        move |mut commands: Commands, asset_server: Res<AssetServer>| {
+            let asset_str = archive::asset_path_string(&path);
+            commands.insert_resource(WavPreview {
+                source: asset_server.load_override(AssetPath::parse(&asset_str)),
+                instance: None,
+            });
+        },
+    )
+    .add_systems(Update, play_when_loaded);
+
+    match app.run() {
+        AppExit::Success => 0,
+        AppExit::Error(code) => i32::from(code.get()),
+    }
+}
+
+/// 音频加载完成后播放，播放结束或加载失败时退出
+fn play_when_loaded(
+    asset_server: Res<AssetServer>,
+    mut preview: ResMut<WavPreview>,
+    channel: Res<AudioChannel<WavPreviewChannel>>,
+    instances: Res<Assets<AudioInstance>>,
+    mut exits: MessageWriter<AppExit>,
+) {
+    let Some(instance) = &preview.instance else {
+        let state = asset_server.load_state(&preview.source);
+        if state.is_failed() {
+            eprintln!("✗ 音频加载失败");
+            exits.write(AppExit::error());
+        } else if state.is_loaded() {
+            preview.instance = Some(channel.play(preview.source.clone()).handle());
+        }
+        return;
+    };
+    if instances
+        .get(instance)
+        .is_some_and(|instance| instance.state() == PlaybackState::Stopped)
+    {
+        println!("✓ 播放完成");
+        exits.write(AppExit::Success);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_chart_and_listed_ids() {
+        for (id, expected) in [
+            ("0Z", Some(35)),
+            ("#WAV0Z", Some(35)),
+            ("wav0z", Some(35)),
+            ("zz", Some(1295)),
+            ("035", Some(35)),
+            ("#WAV035", Some(35)),
+            ("WAV", None),
+            ("0-", None),
+        ] {
+            assert_eq!(parse_wav_id(id), expected, "{id}");
+        }
+        assert_eq!(base36_id(35), "0Z");
+        assert_eq!(base36_id(1295), "ZZ");
+    }
+}